@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use routing::Route;
+use Handler;
+
+/// A set of routes and error handlers that can be mounted into a `Rask` application at a
+/// prefix with `Rask::mount`. Lets larger applications be split across modules or crates.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::{Rask, StatusCode};
+/// use rask::blueprint::Blueprint;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn list(_: &Request, _: Response) {
+/// }
+///
+/// fn not_found(_: &Request, res: Response) {
+///     res.send(("{\"error\": \"not found\"}", StatusCode::NotFound));
+/// }
+///
+/// fn api() -> Blueprint {
+///     let mut api = Blueprint::new();
+///     api.register("/items", list);
+///     api.register_error_handler(StatusCode::NotFound, not_found);
+///     api
+/// }
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.mount("/api", api()); // "/api/items"
+/// ```
+pub struct Blueprint {
+    routes: Vec<Route>,
+    error_handlers: HashMap<StatusCode, Arc<Box<Handler>>>,
+}
+
+impl Blueprint {
+    /// Creates a new empty blueprint.
+    pub fn new() -> Blueprint {
+        Blueprint {
+            routes: Vec::new(),
+            error_handlers: HashMap::new(),
+        }
+    }
+
+    /// Same as `Rask::register`. `route` is relative to the prefix the blueprint is
+    /// mounted at.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        self.routes.push(Route::new(route, handler));
+    }
+
+    /// Same as `Rask::register_with_methods`. `route` is relative to the prefix the
+    /// blueprint is mounted at.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_with_methods<H: 'static + Handler>(
+        &mut self,
+        route: &str,
+        methods: &[Method],
+        handler: H)
+    {
+        self.routes.push(Route::with_methods(route, handler, methods));
+    }
+
+    /// Same as `register`, but also gives the route a name. See `Rask::register_named`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_named<H: 'static + Handler>(&mut self, name: &str, route: &str, handler: H) {
+        self.routes.push(Route::new(route, handler).named(name));
+    }
+
+    /// Same as `register_with_methods`, but also gives the route a name. See
+    /// `Rask::register_named`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_named_with_methods<H: 'static + Handler>(
+        &mut self,
+        name: &str,
+        route: &str,
+        methods: &[Method],
+        handler: H)
+    {
+        self.routes.push(Route::with_methods(route, handler, methods).named(name));
+    }
+
+    /// Register a error handler used for requests below the blueprint's prefix. Falls back
+    /// to the application's error handlers for status codes without one.
+    pub fn register_error_handler<H: 'static + Handler>(&mut self, status_code: StatusCode, handler: H) {
+        self.error_handlers.insert(status_code, Arc::new(Box::new(handler)));
+    }
+
+    #[doc(hidden)]
+    pub fn into_parts(self) -> (Vec<Route>, HashMap<StatusCode, Arc<Box<Handler>>>) {
+        (self.routes, self.error_handlers)
+    }
+}
@@ -0,0 +1,264 @@
+//! Building cookies with the attributes real cookies need, see `CookieBuilder`, and the
+//! cookies of a response, see `ResponseCookies`.
+
+use std::ops::{Deref, DerefMut};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cookie::{Cookie, CookieJar};
+
+use time::{self, Timespec};
+
+/// The `SameSite` attribute of a cookie, which controls whether it's sent with requests from
+/// other sites.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// Only sent with requests from the same site.
+    Strict,
+    /// Also sent when navigating to the site from another one, but not with e.g. its images
+    /// or forms posted from it.
+    Lax,
+    /// Sent with all requests. Browsers require the cookie to be `secure` too.
+    None,
+}
+
+/// Builds a `Cookie` with its attributes, to add with `Response::cookies`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use rask::cookies::{CookieBuilder, SameSite};
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn login(_: &Request, mut res: Response) {
+///     let token = CookieBuilder::new("token", "7f3a")
+///         .max_age(Duration::from_secs(14 * 24 * 60 * 60))
+///         .secure(true)
+///         .http_only(true)
+///         .same_site(SameSite::Lax)
+///         .finish();
+///     res.cookies().add(token);
+///     res.redirect("/");
+/// }
+/// ```
+pub struct CookieBuilder {
+    cookie: Cookie,
+}
+
+impl CookieBuilder {
+    /// Starts a cookie `name` with `value`, for the path "/".
+    pub fn new(name: &str, value: &str) -> CookieBuilder {
+        CookieBuilder {
+            cookie: Cookie::new(name.to_owned(), value.to_owned()),
+        }
+    }
+
+    /// Sets the path below which the cookie is sent.
+    pub fn path(mut self, path: &str) -> CookieBuilder {
+        self.cookie.path = Some(path.to_owned());
+        self
+    }
+
+    /// Sets the domain the cookie is sent to, including its subdomains. Without it the cookie
+    /// is only sent to the host that set it.
+    pub fn domain(mut self, domain: &str) -> CookieBuilder {
+        self.cookie.domain = Some(domain.to_owned());
+        self
+    }
+
+    /// Makes the cookie expire `max_age` after it's received. Without `max_age` or `expires`
+    /// the cookie expires when the browser is closed.
+    pub fn max_age(mut self, max_age: Duration) -> CookieBuilder {
+        self.cookie.max_age = Some(max_age.as_secs());
+        self
+    }
+
+    /// Makes the cookie expire at `expires`. Clients prefer `max_age` if both are set.
+    pub fn expires(mut self, expires: SystemTime) -> CookieBuilder {
+        let seconds = match expires.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as i64,
+            Err(err) => -(err.duration().as_secs() as i64),
+        };
+        self.cookie.expires = Some(time::at_utc(Timespec::new(seconds, 0)));
+        self
+    }
+
+    /// Whether the cookie is only sent over https.
+    pub fn secure(mut self, secure: bool) -> CookieBuilder {
+        self.cookie.secure = secure;
+        self
+    }
+
+    /// Whether the cookie is hidden from javascript, which keeps e.g. session tokens from
+    /// being stolen by injected scripts.
+    pub fn http_only(mut self, http_only: bool) -> CookieBuilder {
+        self.cookie.httponly = http_only;
+        self
+    }
+
+    /// Sets the `SameSite` attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> CookieBuilder {
+        let value = match same_site {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        };
+        self.cookie.custom.insert("SameSite".to_owned(), value.to_owned());
+        self
+    }
+
+    /// The cookie.
+    pub fn finish(self) -> Cookie {
+        self.cookie
+    }
+}
+
+/// The cookies of a response, see `Response::cookies`. Cookies are added plain, signed or
+/// encrypted. Derefs to the `CookieJar` of the request's cookies.
+pub struct ResponseCookies {
+    jar: CookieJar<'static>,
+    deleted: Vec<Cookie>,
+}
+
+impl ResponseCookies {
+    #[doc(hidden)]
+    pub fn new(jar: CookieJar<'static>) -> ResponseCookies {
+        ResponseCookies { jar: jar, deleted: Vec::new() }
+    }
+
+    /// Adds `cookie`, replacing a cookie with the same name.
+    pub fn add(&mut self, cookie: Cookie) {
+        self.deleted.retain(|deleted| deleted.name != cookie.name);
+        self.jar.add(cookie);
+    }
+
+    /// Adds `cookie` signed with the application's secret, replacing a cookie with the same
+    /// name. The value stays readable, e.g. by javascript unless the cookie is `http_only`,
+    /// followed by "--" and the signature, but can't be changed without
+    /// `Request::cookies().find_signed` noticing.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::cookies::CookieBuilder;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn choose_plan(_: &Request, mut res: Response) {
+    ///     res.cookies().add_signed(CookieBuilder::new("plan", "pro").finish());
+    ///     res.redirect("/");
+    /// }
+    ///
+    /// fn plan(req: &Request, res: Response) {
+    ///     match req.cookies().find_signed("plan") {
+    ///         Some(plan) => res.send(plan.value),
+    ///         None => res.send("free"),
+    ///     };
+    /// }
+    /// ```
+    pub fn add_signed(&mut self, cookie: Cookie) {
+        self.deleted.retain(|deleted| deleted.name != cookie.name);
+        self.jar.signed().add(cookie);
+    }
+
+    /// Adds `cookie` encrypted with the application's secret, replacing a cookie with the
+    /// same name. Its value can neither be read nor changed by the client, see
+    /// `Request::cookies().find_encrypted`.
+    pub fn add_encrypted(&mut self, cookie: Cookie) {
+        self.deleted.retain(|deleted| deleted.name != cookie.name);
+        self.jar.encrypted().add(cookie);
+    }
+
+    /// Makes the client delete the cookie `name` set for the path "/", by sending it
+    /// expired.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn logout(_: &Request, mut res: Response) {
+    ///     res.cookies().delete("token");
+    ///     res.redirect("/");
+    /// }
+    /// ```
+    pub fn delete(&mut self, name: &str) {
+        self.delete_cookie(CookieBuilder::new(name, "").finish());
+    }
+
+    /// Makes the client delete `cookie`, by sending it expired. Clients only delete a cookie
+    /// set with the same path and domain, so those of `cookie` must be the ones it was set
+    /// with, e.g. built with the same `CookieBuilder`.
+    pub fn delete_cookie(&mut self, mut cookie: Cookie) {
+        self.jar.remove(&cookie.name);
+        cookie.value = String::new();
+        cookie.max_age = Some(0);
+        cookie.expires = Some(time::at_utc(Timespec::new(0, 0)));
+        self.deleted.retain(|deleted| deleted.name != cookie.name);
+        self.deleted.push(cookie);
+    }
+
+    /// The cookies to send with the response.
+    #[doc(hidden)]
+    pub fn delta(&self) -> Vec<Cookie> {
+        merge_deleted(self.jar.delta(), &self.deleted)
+    }
+}
+
+impl Deref for ResponseCookies {
+    type Target = CookieJar<'static>;
+
+    fn deref(&self) -> &CookieJar<'static> {
+        &self.jar
+    }
+}
+
+impl DerefMut for ResponseCookies {
+    fn deref_mut(&mut self) -> &mut CookieJar<'static> {
+        &mut self.jar
+    }
+}
+
+/// The changes of a jar, with the cookies it expires replaced by the deleted ones, which have
+/// the right path and domain.
+fn merge_deleted(delta: Vec<Cookie>, deleted: &[Cookie]) -> Vec<Cookie> {
+    let mut cookies: Vec<Cookie> = delta
+        .into_iter()
+        .filter(|cookie| !deleted.iter().any(|deleted| deleted.name == cookie.name))
+        .collect();
+    cookies.extend(deleted.iter().cloned());
+    cookies
+}
+
+#[test]
+fn cookie_attributes() {
+    let cookie = CookieBuilder::new("token", "a b")
+        .path("/app")
+        .domain("example.com")
+        .max_age(Duration::from_secs(60))
+        .expires(UNIX_EPOCH + Duration::from_secs(784111777))
+        .secure(true)
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .finish();
+
+    assert_eq!(cookie.to_string(),
+               "token=a%20b; HttpOnly; Secure; Path=/app; Domain=example.com; Max-Age=60; \
+                Expires=Sun, 06 Nov 1994 08:49:37 GMT; SameSite=Strict");
+    assert_eq!(CookieBuilder::new("theme", "dark").finish().to_string(), "theme=dark; Path=/");
+}
+
+#[test]
+fn deleted_cookies_replace_changes() {
+    let mut expired = CookieBuilder::new("token", "").path("/app").domain("example.com").finish();
+    expired.max_age = Some(0);
+    let delta = vec![
+        CookieBuilder::new("theme", "dark").finish(),
+        CookieBuilder::new("token", "").finish(),
+    ];
+
+    let cookies = merge_deleted(delta, &[expired.clone()]);
+    assert_eq!(cookies, vec![CookieBuilder::new("theme", "dark").finish(), expired]);
+}
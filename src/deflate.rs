@@ -0,0 +1,200 @@
+//! Compression of response bodies with `gzip` or `deflate`, and `br` with the `brotli`
+//! feature, see `Rask::compress_responses_above`.
+//!
+//! Matches are found with hash chains and written in a single block of the fixed huffman
+//! codes, which is far from the best compression but cheap and good enough for text. Brotli
+//! is left to the `brotli` crate.
+
+use inflate::{adler32, crc32, DISTANCE_BASE, DISTANCE_EXTRA, LENGTH_BASE, LENGTH_EXTRA};
+
+const WINDOW_SIZE: usize = 32 * 1024;
+const MIN_MATCH: usize = 3;
+const MAX_MATCH: usize = 258;
+const HASH_BITS: u32 = 15;
+/// How many earlier positions with the same hash are tried for a match.
+const MAX_CHAIN: usize = 64;
+
+/// Compresses `data` to a gzip member (RFC 1952).
+pub fn gzip(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x1f, 0x8b, 8, 0, 0, 0, 0, 0, 0, 0xff];
+    deflate(data, &mut out);
+    for value in &[crc32(data), data.len() as u32] {
+        out.extend_from_slice(&[*value as u8, (*value >> 8) as u8, (*value >> 16) as u8, (*value >> 24) as u8]);
+    }
+    out
+}
+
+/// Compresses `data` to zlib (RFC 1950), which is what the `deflate` content encoding means.
+pub fn zlib(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![0x78, 0x01];
+    deflate(data, &mut out);
+    let checksum = adler32(data);
+    out.extend_from_slice(&[(checksum >> 24) as u8, (checksum >> 16) as u8, (checksum >> 8) as u8, checksum as u8]);
+    out
+}
+
+/// Compresses `data` to a brotli stream (RFC 7932), at a quality that's still fast enough
+/// for compressing responses on the fly.
+#[cfg(feature = "brotli")]
+pub fn brotli(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut out = Vec::new();
+    {
+        let mut writer = ::brotli::CompressorWriter::new(&mut out, 4096, 5, 22);
+        writer.write_all(data).expect("writing to a vector can't fail");
+    }
+    out
+}
+
+/// Compresses `data` to raw deflate data (RFC 1951), appended to `out`.
+fn deflate(data: &[u8], out: &mut Vec<u8>) {
+    let mut bits = Bits { out: out, value: 0, count: 0 };
+    // the final block, with fixed huffman codes
+    bits.write(1, 1);
+    bits.write(1, 2);
+
+    let mut head = vec![usize::max_value(); 1 << HASH_BITS];
+    let mut prev = vec![usize::max_value(); WINDOW_SIZE];
+    let mut pos = 0;
+    while pos < data.len() {
+        let (length, distance) = longest_match(data, pos, &head, &prev);
+        let end = if length >= MIN_MATCH {
+            bits.length(length);
+            bits.distance(distance);
+            pos + length
+        } else {
+            bits.literal(data[pos] as u16);
+            pos + 1
+        };
+        while pos < end {
+            if pos + MIN_MATCH <= data.len() {
+                let hash = hash(&data[pos..]);
+                prev[pos % WINDOW_SIZE] = head[hash];
+                head[hash] = pos;
+            }
+            pos += 1;
+        }
+    }
+
+    bits.literal(256);
+    bits.flush();
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let value = (bytes[0] as u32) << 16 | (bytes[1] as u32) << 8 | bytes[2] as u32;
+    (value.wrapping_mul(2654435761) >> (32 - HASH_BITS)) as usize
+}
+
+/// The longest earlier match for the bytes at `pos`, as its length and distance.
+fn longest_match(data: &[u8], pos: usize, head: &[usize], prev: &[usize]) -> (usize, usize) {
+    if pos + MIN_MATCH > data.len() {
+        return (0, 0);
+    }
+    let max = ::std::cmp::min(MAX_MATCH, data.len() - pos);
+    let (mut best_length, mut best_distance) = (0, 0);
+    let mut candidate = head[hash(&data[pos..])];
+    for _ in 0..MAX_CHAIN {
+        if candidate == usize::max_value() || candidate >= pos || pos - candidate > WINDOW_SIZE {
+            break;
+        }
+        let length = data[candidate..].iter().zip(&data[pos..pos + max]).take_while(|&(a, b)| a == b).count();
+        if length > best_length {
+            best_length = length;
+            best_distance = pos - candidate;
+            if length == max {
+                break;
+            }
+        }
+        let next = prev[candidate % WINDOW_SIZE];
+        if next >= candidate {
+            // the slot has been reused by a later position
+            break;
+        }
+        candidate = next;
+    }
+    (best_length, best_distance)
+}
+
+/// Writes bits least significant first, as deflate packs them.
+struct Bits<'o> {
+    out: &'o mut Vec<u8>,
+    value: u32,
+    count: u32,
+}
+
+impl<'o> Bits<'o> {
+    fn write(&mut self, value: u32, count: u32) {
+        self.value |= value << self.count;
+        self.count += count;
+        while self.count >= 8 {
+            self.out.push(self.value as u8);
+            self.value >>= 8;
+            self.count -= 8;
+        }
+    }
+
+    /// Writes a huffman code, which is packed most significant bit first.
+    fn code(&mut self, code: u32, length: u32) {
+        let reversed = (0..length).fold(0, |reversed, i| reversed << 1 | (code >> i) & 1);
+        self.write(reversed, length);
+    }
+
+    /// Writes a literal/length symbol with its fixed code.
+    fn literal(&mut self, symbol: u16) {
+        let symbol = symbol as u32;
+        match symbol {
+            0..=143 => self.code(0x30 + symbol, 8),
+            144..=255 => self.code(0x190 + symbol - 144, 9),
+            256..=279 => self.code(symbol - 256, 7),
+            _ => self.code(0xc0 + symbol - 280, 8),
+        }
+    }
+
+    fn length(&mut self, length: usize) {
+        let index = LENGTH_BASE.iter().rposition(|&base| base as usize <= length).unwrap();
+        self.literal(257 + index as u16);
+        self.write((length - LENGTH_BASE[index] as usize) as u32, LENGTH_EXTRA[index] as u32);
+    }
+
+    fn distance(&mut self, distance: usize) {
+        let index = DISTANCE_BASE.iter().rposition(|&base| base as usize <= distance).unwrap();
+        self.code(index as u32, 5);
+        self.write((distance - DISTANCE_BASE[index] as usize) as u32, DISTANCE_EXTRA[index] as u32);
+    }
+
+    fn flush(&mut self) {
+        if self.count > 0 {
+            self.out.push(self.value as u8);
+        }
+        self.value = 0;
+        self.count = 0;
+    }
+}
+
+#[test]
+fn compress_round_trip() {
+    use inflate::{gunzip, zlib_or_raw};
+
+    let text: String = (0..500).map(|i| format!("line {}: the quick brown fox jumps over the lazy dog\n", i % 7)).collect();
+    let binary: Vec<u8> = (0..70000u32).map(|i| (i.wrapping_mul(2654435761) >> 13) as u8).collect();
+
+    for data in &[text.as_bytes(), &binary[..], b"", b"ab", &[0; 1000][..]] {
+        assert_eq!(gunzip(&gzip(data), 1 << 20).unwrap(), *data);
+        assert_eq!(zlib_or_raw(&zlib(data), 1 << 20).unwrap(), *data);
+    }
+    assert!(gzip(text.as_bytes()).len() < text.len() / 10);
+}
+
+#[cfg(feature = "brotli")]
+#[test]
+fn brotli_round_trip() {
+    let text: String = (0..500).map(|i| format!("line {}: the quick brown fox jumps over the lazy dog\n", i % 7)).collect();
+
+    for data in &[text.as_bytes(), b"", b"ab"] {
+        let mut decompressed = Vec::new();
+        ::brotli::BrotliDecompress(&mut &brotli(data)[..], &mut decompressed).unwrap();
+        assert_eq!(decompressed, *data);
+    }
+    assert!(brotli(text.as_bytes()).len() < text.len() / 10);
+}
@@ -0,0 +1,117 @@
+//! Handlers that return a `Result`, with the errors answered by the application's
+//! `ErrorHandler`, see `Rask::handle_errors`.
+
+use std::error::Error;
+use std::marker::PhantomData;
+
+use hyper::status::StatusCode;
+
+use request::Request;
+use response::{Response, Sendable};
+use Handler;
+
+/// An error returned by a fallible handler. Any error type converts into it with `?` or
+/// `try!`, and an `ErrorHandler` can get it back with `downcast_ref`.
+pub type HandlerError = Box<Error + Send + Sync>;
+
+/// Decides the response for errors returned by fallible handlers, e.g. 404 (Not Found) for
+/// a missing record and 409 (Conflict) for a duplicate, instead of 500 (Internal Server
+/// Error) for all of them.
+///
+/// Default implementation for `Fn(&HandlerError, &Request, Response)`.
+pub trait ErrorHandler: Send + Sync {
+    fn handle(&self, err: &HandlerError, req: &Request, res: Response);
+}
+
+impl<F> ErrorHandler for F where F: Fn(&HandlerError, &Request, Response) + Send + Sync {
+    fn handle(&self, err: &HandlerError, req: &Request, res: Response) {
+        (*self)(err, req, res);
+    }
+}
+
+/// A handler that sends what `handler` returns, or hands its error to the application's
+/// `ErrorHandler`. Created by `fallible`.
+pub struct Fallible<F, S, E> {
+    handler: F,
+    _result: PhantomData<fn() -> Result<S, E>>,
+}
+
+/// Makes a handler of a function that returns the response to send, or an error. Errors are
+/// answered by the `ErrorHandler` given to `Rask::handle_errors`, or with 500 (Internal
+/// Server Error) without one.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::fmt;
+/// use std::error::Error;
+/// use rask::{Rask, StatusCode};
+/// use rask::fallible::{HandlerError, fallible};
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// #[derive(Debug)]
+/// struct NotFound;
+///
+/// impl fmt::Display for NotFound {
+///     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+///         write!(f, "No such user.")
+///     }
+/// }
+///
+/// impl Error for NotFound {
+///     fn description(&self) -> &str {
+///         "No such user."
+///     }
+/// }
+///
+/// fn user(req: &Request) -> Result<String, NotFound> {
+///     match req.vars.get("name").map(|name| &name[..]) {
+///         Some("alice") => Ok("Alice".to_owned()),
+///         _ => Err(NotFound),
+///     }
+/// }
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.get("/users/{name}", fallible(user));
+/// app.handle_errors(|err: &HandlerError, _: &Request, res: Response| {
+///     let _ = match err.downcast_ref::<NotFound>() {
+///         Some(_) => res.error(StatusCode::NotFound),
+///         None => res.error(StatusCode::InternalServerError),
+///     };
+/// });
+/// ```
+pub fn fallible<F, S, E>(handler: F) -> Fallible<F, S, E>
+    where F: Fn(&Request) -> Result<S, E> + Send + Sync,
+          S: 'static + Sendable<'static>,
+          E: Into<HandlerError>
+{
+    Fallible {
+        handler: handler,
+        _result: PhantomData,
+    }
+}
+
+impl<F, S, E> Handler for Fallible<F, S, E>
+    where F: Fn(&Request) -> Result<S, E> + Send + Sync,
+          S: 'static + Sendable<'static>,
+          E: Into<HandlerError>
+{
+    fn handle(&self, req: &Request, res: Response) {
+        match (self.handler)(req) {
+            Ok(response) => {
+                let _ = res.send(response);
+            },
+            Err(err) => {
+                let err = err.into();
+                match req.app().error_translator() {
+                    Some(error_handler) => error_handler.handle(&err, req, res),
+                    None => {
+                        error!("[{}] Handler failed: {}", req.id(), err);
+                        let _ = res.error(StatusCode::InternalServerError);
+                    },
+                }
+            },
+        }
+    }
+}
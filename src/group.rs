@@ -0,0 +1,127 @@
+use hyper::method::Method;
+
+use routing::join_path;
+use resource::Resource;
+use middleware::Middleware;
+use {Handler, Rask};
+
+/// A set of routes registered under a common prefix. Created by `Rask::group`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::Method::*;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn users(_: &Request, _: Response) {
+/// }
+///
+/// fn create_user(_: &Request, _: Response) {
+/// }
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// {
+///     let mut api = app.group("/api/v1");
+///     api.register_with_methods("/users", &[Get], users); // "/api/v1/users"
+///     api.register_with_methods("/users", &[Post], create_user);
+/// }
+/// ```
+pub struct Group<'a> {
+    app: &'a mut Rask,
+    prefix: String,
+    methods: Vec<Method>,
+}
+
+impl<'a> Group<'a> {
+    #[doc(hidden)]
+    pub fn new(app: &'a mut Rask, prefix: &str) -> Group<'a> {
+        Group {
+            app: app,
+            prefix: prefix.to_owned(),
+            methods: Vec::new(),
+        }
+    }
+
+    /// Restricts the routes registered with `register` to the given http methods. Routes
+    /// registered with `register_with_methods` keep their own methods.
+    pub fn methods(mut self, methods: &[Method]) -> Group<'a> {
+        self.methods = methods.to_vec();
+        self
+    }
+
+    /// Same as `Rask::register`, with the group's prefix prepended to `route`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        let methods = self.methods.clone();
+        self.register_with_methods(route, &methods, handler);
+    }
+
+    /// Same as `Rask::register_with_methods`, with the group's prefix prepended to `route`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_with_methods<H: 'static + Handler>(
+        &mut self,
+        route: &str,
+        methods: &[Method],
+        handler: H)
+    {
+        let route = join_path(&self.prefix, route);
+        self.app.register_with_methods(&route, methods, handler);
+    }
+
+    /// Same as `register`, but also gives the route a name. See `Rask::register_named`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_named<H: 'static + Handler>(&mut self, name: &str, route: &str, handler: H) {
+        let methods = self.methods.clone();
+        self.register_named_with_methods(name, route, &methods, handler);
+    }
+
+    /// Same as `register_with_methods`, but also gives the route a name. See
+    /// `Rask::register_named`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_named_with_methods<H: 'static + Handler>(
+        &mut self,
+        name: &str,
+        route: &str,
+        methods: &[Method],
+        handler: H)
+    {
+        let route = join_path(&self.prefix, route);
+        self.app.register_named_with_methods(name, &route, methods, handler);
+    }
+
+    /// Same as `Rask::route`, with the group's prefix prepended to `route`. The group's
+    /// methods don't apply.
+    pub fn route<'b>(&'b mut self, route: &str) -> Resource<'b> {
+        Resource::new(self.app, &join_path(&self.prefix, route))
+    }
+
+    /// Same as `Rask::wrap_under` with the group's prefix, so the middleware only runs for
+    /// requests to the group's routes.
+    pub fn wrap<M: 'static + Middleware>(&mut self, middleware: M) {
+        self.app.wrap_under(&self.prefix, middleware);
+    }
+
+    /// Creates a nested group, e.g. `api.group("/v1")`. The nested group inherits the methods
+    /// of this group.
+    pub fn group<'b>(&'b mut self, prefix: &str) -> Group<'b> {
+        Group {
+            app: self.app,
+            prefix: join_path(&self.prefix, prefix),
+            methods: self.methods.clone(),
+        }
+    }
+}
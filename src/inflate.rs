@@ -0,0 +1,372 @@
+//! Decompression of `gzip` and `deflate` encoded request bodies, see `Request::body`.
+
+/// The error returned when a compressed body can't be decompressed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InflateError {
+    /// The data isn't valid for its format.
+    Corrupt,
+    /// The decompressed data is larger than the limit.
+    TooLarge,
+}
+
+pub const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115,
+    131, 163, 195, 227, 258];
+pub const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0];
+pub const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577];
+pub const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13];
+const CODE_LENGTH_ORDER: [usize; 19] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// Decompresses a gzip member (RFC 1952), checking its crc and size.
+pub fn gunzip(data: &[u8], limit: u64) -> Result<Vec<u8>, InflateError> {
+    if data.len() < 18 || data[0] != 0x1f || data[1] != 0x8b || data[2] != 8 {
+        return Err(InflateError::Corrupt);
+    }
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & 4 != 0 {
+        let extra = try!(data.get(pos..pos + 2).ok_or(InflateError::Corrupt));
+        pos += 2 + (extra[0] as usize | (extra[1] as usize) << 8);
+    }
+    for &flag in &[8, 16] {
+        if flags & flag != 0 {
+            let end = try!(data.get(pos..).and_then(|rest| rest.iter().position(|&b| b == 0)).ok_or(InflateError::Corrupt));
+            pos += end + 1;
+        }
+    }
+    if flags & 2 != 0 {
+        pos += 2;
+    }
+
+    let (out, used) = try!(inflate(try!(data.get(pos..).ok_or(InflateError::Corrupt)), limit));
+    let trailer = try!(data.get(pos + used..pos + used + 8).ok_or(InflateError::Corrupt));
+    if read_u32_le(&trailer[..4]) != crc32(&out) || read_u32_le(&trailer[4..]) != out.len() as u32 {
+        return Err(InflateError::Corrupt);
+    }
+    Ok(out)
+}
+
+/// Decompresses zlib (RFC 1950) data, or raw deflate data as sent by some clients for the
+/// `deflate` content encoding.
+pub fn zlib_or_raw(data: &[u8], limit: u64) -> Result<Vec<u8>, InflateError> {
+    let is_zlib = data.len() >= 2 && data[0] & 0x0f == 8 && ((data[0] as u16) << 8 | data[1] as u16) % 31 == 0;
+    if !is_zlib {
+        return inflate(data, limit).map(|(out, _)| out);
+    }
+    if data[1] & 0x20 != 0 {
+        // a preset dictionary, which can't be known
+        return Err(InflateError::Corrupt);
+    }
+    let (out, used) = try!(inflate(&data[2..], limit));
+    let trailer = try!(data.get(2 + used..2 + used + 4).ok_or(InflateError::Corrupt));
+    let checksum = (trailer[0] as u32) << 24 | (trailer[1] as u32) << 16 | (trailer[2] as u32) << 8 | trailer[3] as u32;
+    if checksum != adler32(&out) {
+        return Err(InflateError::Corrupt);
+    }
+    Ok(out)
+}
+
+/// Decompresses raw deflate data (RFC 1951). Returns the data and the number of bytes of
+/// `data` it used.
+fn inflate(data: &[u8], limit: u64) -> Result<(Vec<u8>, usize), InflateError> {
+    let mut state = State { input: data, pos: 0, bit_buffer: 0, bit_count: 0, out: Vec::new(), limit: limit };
+    loop {
+        let last = try!(state.bits(1));
+        match try!(state.bits(2)) {
+            0 => try!(state.stored()),
+            1 => {
+                let (lengths, distances) = fixed_codes();
+                try!(state.codes(&lengths, &distances));
+            },
+            2 => try!(state.dynamic()),
+            _ => return Err(InflateError::Corrupt),
+        }
+        if last == 1 {
+            return Ok((state.out, state.pos));
+        }
+    }
+}
+
+struct State<'a> {
+    input: &'a [u8],
+    pos: usize,
+    bit_buffer: u32,
+    bit_count: u32,
+    out: Vec<u8>,
+    limit: u64,
+}
+
+impl<'a> State<'a> {
+    fn bits(&mut self, n: u32) -> Result<u32, InflateError> {
+        while self.bit_count < n {
+            let byte = try!(self.input.get(self.pos).ok_or(InflateError::Corrupt));
+            self.bit_buffer |= (*byte as u32) << self.bit_count;
+            self.pos += 1;
+            self.bit_count += 8;
+        }
+        let value = self.bit_buffer & ((1 << n) - 1);
+        self.bit_buffer >>= n;
+        self.bit_count -= n;
+        Ok(value)
+    }
+
+    fn push(&mut self, byte: u8) -> Result<(), InflateError> {
+        if self.out.len() as u64 >= self.limit {
+            return Err(InflateError::TooLarge);
+        }
+        self.out.push(byte);
+        Ok(())
+    }
+
+    fn stored(&mut self) -> Result<(), InflateError> {
+        self.bit_buffer = 0;
+        self.bit_count = 0;
+        let header = try!(self.input.get(self.pos..self.pos + 4).ok_or(InflateError::Corrupt));
+        let len = header[0] as usize | (header[1] as usize) << 8;
+        let complement = header[2] as usize | (header[3] as usize) << 8;
+        if len != !complement & 0xffff {
+            return Err(InflateError::Corrupt);
+        }
+        self.pos += 4;
+        let block = try!(self.input.get(self.pos..self.pos + len).ok_or(InflateError::Corrupt));
+        if (self.out.len() + len) as u64 > self.limit {
+            return Err(InflateError::TooLarge);
+        }
+        self.out.extend_from_slice(block);
+        self.pos += len;
+        Ok(())
+    }
+
+    fn dynamic(&mut self) -> Result<(), InflateError> {
+        let literals = try!(self.bits(5)) as usize + 257;
+        let distances = try!(self.bits(5)) as usize + 1;
+        let code_lengths = try!(self.bits(4)) as usize + 4;
+        if literals > 286 || distances > 30 {
+            return Err(InflateError::Corrupt);
+        }
+
+        let mut lengths = [0u8; 19];
+        for &index in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+            lengths[index] = try!(self.bits(3)) as u8;
+        }
+        let code = try!(Huffman::new(&lengths));
+
+        let mut lengths = vec![0u8; literals + distances];
+        let mut index = 0;
+        while index < lengths.len() {
+            let symbol = try!(self.decode(&code));
+            let (length, repeat) = match symbol {
+                symbol if symbol < 16 => (symbol as u8, 1),
+                16 if index > 0 => (lengths[index - 1], 3 + try!(self.bits(2)) as usize),
+                17 => (0, 3 + try!(self.bits(3)) as usize),
+                18 => (0, 11 + try!(self.bits(7)) as usize),
+                _ => return Err(InflateError::Corrupt),
+            };
+            if index + repeat > lengths.len() {
+                return Err(InflateError::Corrupt);
+            }
+            for length_at in &mut lengths[index..index + repeat] {
+                *length_at = length;
+            }
+            index += repeat;
+        }
+        if lengths[256] == 0 {
+            return Err(InflateError::Corrupt);
+        }
+
+        let literal_code = try!(Huffman::new(&lengths[..literals]));
+        let distance_code = try!(Huffman::new(&lengths[literals..]));
+        self.codes(&literal_code, &distance_code)
+    }
+
+    fn codes(&mut self, literals: &Huffman, distances: &Huffman) -> Result<(), InflateError> {
+        loop {
+            let symbol = try!(self.decode(literals)) as usize;
+            if symbol < 256 {
+                try!(self.push(symbol as u8));
+                continue;
+            }
+            if symbol == 256 {
+                return Ok(());
+            }
+            let symbol = symbol - 257;
+            if symbol >= LENGTH_BASE.len() {
+                return Err(InflateError::Corrupt);
+            }
+            let length = LENGTH_BASE[symbol] as usize + try!(self.bits(LENGTH_EXTRA[symbol] as u32)) as usize;
+
+            let symbol = try!(self.decode(distances)) as usize;
+            if symbol >= DISTANCE_BASE.len() {
+                return Err(InflateError::Corrupt);
+            }
+            let distance = DISTANCE_BASE[symbol] as usize + try!(self.bits(DISTANCE_EXTRA[symbol] as u32)) as usize;
+            if distance > self.out.len() {
+                return Err(InflateError::Corrupt);
+            }
+            for _ in 0..length {
+                let byte = self.out[self.out.len() - distance];
+                try!(self.push(byte));
+            }
+        }
+    }
+
+    fn decode(&mut self, huffman: &Huffman) -> Result<u16, InflateError> {
+        let mut code = 0i32;
+        let mut first = 0i32;
+        let mut index = 0i32;
+        for len in 1..16 {
+            code |= try!(self.bits(1)) as i32;
+            let count = huffman.counts[len] as i32;
+            if code - first < count {
+                return Ok(huffman.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(InflateError::Corrupt)
+    }
+}
+
+/// A canonical huffman code: the number of codes per length and the symbols ordered by code.
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Result<Huffman, InflateError> {
+        let mut counts = [0u16; 16];
+        for &length in lengths {
+            counts[length as usize] += 1;
+        }
+
+        let mut left = 1i32;
+        for len in 1..16 {
+            left = (left << 1) - counts[len] as i32;
+            if left < 0 {
+                return Err(InflateError::Corrupt);
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0u16; lengths.len()];
+        for (symbol, &length) in lengths.iter().enumerate() {
+            if length != 0 {
+                symbols[offsets[length as usize] as usize] = symbol as u16;
+                offsets[length as usize] += 1;
+            }
+        }
+        counts[0] = 0;
+        Ok(Huffman { counts: counts, symbols: symbols })
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0u8; 288];
+    for (symbol, length) in lengths.iter_mut().enumerate() {
+        *length = if symbol < 144 {
+            8
+        } else if symbol < 256 {
+            9
+        } else if symbol < 280 {
+            7
+        } else {
+            8
+        };
+    }
+    let literals = Huffman::new(&lengths).unwrap();
+    let distances = Huffman::new(&[5; 30]).unwrap();
+    (literals, distances)
+}
+
+fn read_u32_le(bytes: &[u8]) -> u32 {
+    bytes.iter().rev().fold(0, |value, &byte| value << 8 | byte as u32)
+}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb88320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+pub fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for &byte in chunk {
+            a += byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    b << 16 | a
+}
+
+#[test]
+fn decompress_gzip_and_zlib() {
+    let text: String = (0..40).map(|i| format!("line {}: the quick brown fox jumps over the lazy dog\n", i)).collect();
+    let short = b"Hello Hello Hello, rask!";
+
+    // written by python's gzip and zlib modules: dynamic, dynamic, stored and fixed blocks
+    let gzipped = [
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x9d, 0xd5, 0x5b, 0x16,
+        0xc1, 0x50, 0x0c, 0x46, 0xe1, 0x77, 0xa3, 0xc8, 0x10, 0xe4, 0x0f, 0x2d, 0x66, 0xe3,
+        0x72, 0x68, 0x39, 0x7a, 0x68, 0xd5, 0x6d, 0xf4, 0x16, 0x33, 0xb0, 0x9f, 0xb3, 0xf6,
+        0x53, 0xbe, 0x95, 0xe4, 0xb6, 0x4b, 0x36, 0x5d, 0xd9, 0xad, 0x49, 0x76, 0x1d, 0xdb,
+        0xed, 0xc9, 0x36, 0x7d, 0x79, 0x74, 0xb6, 0x2f, 0x4f, 0x3b, 0x8e, 0xe7, 0xcb, 0x60,
+        0xe5, 0x9e, 0xfa, 0xdf, 0x38, 0xaf, 0xdf, 0x2f, 0xdb, 0x95, 0xc3, 0x24, 0x7f, 0x1b,
+        0x07, 0x8d, 0x40, 0x13, 0xa0, 0x99, 0x81, 0x66, 0x0e, 0x9a, 0x0a, 0x34, 0x35, 0x68,
+        0x16, 0xa0, 0x59, 0x92, 0x9d, 0x22, 0x08, 0x44, 0x82, 0x13, 0x0a, 0x4e, 0x2c, 0x38,
+        0xc1, 0xe0, 0x44, 0x83, 0x13, 0x0e, 0x4e, 0x3c, 0x38, 0x01, 0xe1, 0x44, 0x84, 0x88,
+        0x08, 0xa1, 0xdb, 0x40, 0x44, 0x88, 0x88, 0x10, 0x11, 0x21, 0x22, 0x42, 0x44, 0x84,
+        0x88, 0x08, 0x11, 0x11, 0x22, 0x22, 0x82, 0x88, 0x08, 0x22, 0x22, 0xd0, 0xbb, 0x20,
+        0x22, 0x82, 0x88, 0x08, 0x22, 0x22, 0x88, 0x88, 0x20, 0x22, 0x82, 0x88, 0x88, 0x3f,
+        0x45, 0x7c, 0x00, 0x64, 0x58, 0x7b, 0x18, 0x3e, 0x08, 0x00, 0x00];
+    let zlibbed = [
+        0x78, 0xda, 0x9d, 0xd5, 0x5b, 0x16, 0xc1, 0x50, 0x0c, 0x46, 0xe1, 0x77, 0xa3, 0xc8,
+        0x10, 0xe4, 0x0f, 0x2d, 0x66, 0xe3, 0x72, 0x68, 0x39, 0x7a, 0x68, 0xd5, 0x6d, 0xf4,
+        0x16, 0x33, 0xb0, 0x9f, 0xb3, 0xf6, 0x53, 0xbe, 0x95, 0xe4, 0xb6, 0x4b, 0x36, 0x5d,
+        0xd9, 0xad, 0x49, 0x76, 0x1d, 0xdb, 0xed, 0xc9, 0x36, 0x7d, 0x79, 0x74, 0xb6, 0x2f,
+        0x4f, 0x3b, 0x8e, 0xe7, 0xcb, 0x60, 0xe5, 0x9e, 0xfa, 0xdf, 0x38, 0xaf, 0xdf, 0x2f,
+        0xdb, 0x95, 0xc3, 0x24, 0x7f, 0x1b, 0x07, 0x8d, 0x40, 0x13, 0xa0, 0x99, 0x81, 0x66,
+        0x0e, 0x9a, 0x0a, 0x34, 0x35, 0x68, 0x16, 0xa0, 0x59, 0x92, 0x9d, 0x22, 0x08, 0x44,
+        0x82, 0x13, 0x0a, 0x4e, 0x2c, 0x38, 0xc1, 0xe0, 0x44, 0x83, 0x13, 0x0e, 0x4e, 0x3c,
+        0x38, 0x01, 0xe1, 0x44, 0x84, 0x88, 0x08, 0xa1, 0xdb, 0x40, 0x44, 0x88, 0x88, 0x10,
+        0x11, 0x21, 0x22, 0x42, 0x44, 0x84, 0x88, 0x08, 0x11, 0x11, 0x22, 0x22, 0x82, 0x88,
+        0x08, 0x22, 0x22, 0xd0, 0xbb, 0x20, 0x22, 0x82, 0x88, 0x08, 0x22, 0x22, 0x88, 0x88,
+        0x20, 0x22, 0x82, 0x88, 0x88, 0x3f, 0x45, 0x7c, 0x00, 0xd5, 0x99, 0xe3, 0xf7];
+    let stored = [
+        0x78, 0x01, 0x01, 0x18, 0x00, 0xe7, 0xff, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x48,
+        0x65, 0x6c, 0x6c, 0x6f, 0x20, 0x48, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x72, 0x61,
+        0x73, 0x6b, 0x21, 0x68, 0xdb, 0x08, 0x3b];
+    let raw = [
+        0xf3, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xf0, 0x40, 0x90, 0x3a, 0x0a, 0x45, 0x89, 0xc5,
+        0xd9, 0x8a, 0x00];
+
+    assert_eq!(gunzip(&gzipped, 4096).unwrap(), text.as_bytes());
+    assert_eq!(zlib_or_raw(&zlibbed, 4096).unwrap(), text.as_bytes());
+    assert_eq!(zlib_or_raw(&stored, 4096).unwrap(), &short[..]);
+    assert_eq!(zlib_or_raw(&raw, 4096).unwrap(), &short[..]);
+
+    assert_eq!(gunzip(&gzipped, 100), Err(InflateError::TooLarge));
+    assert_eq!(zlib_or_raw(&stored, 10), Err(InflateError::TooLarge));
+    let mut corrupt = gzipped.to_vec();
+    let crc = corrupt.len() - 8;
+    corrupt[crc] ^= 1;
+    assert_eq!(gunzip(&corrupt, 4096), Err(InflateError::Corrupt));
+    assert_eq!(gunzip(&gzipped[..100], 4096), Err(InflateError::Corrupt));
+}
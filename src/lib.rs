@@ -26,7 +26,7 @@
 //!     let mut app = Rask::new("SUPER SECRET KEY");
 //!
 //!     app.register("/", index); // all methods
-//!     app.register_with_methods("/create", &[Method::Post], create);
+//!     app.post("/create", create);
 //!     app.register_with_methods("/profile/{name}", &[Method::Get], profile);
 //!
 //!     // must be commented out due to 'rust test'.
@@ -42,16 +42,32 @@ extern crate hyper;
 extern crate url;
 extern crate multimap;
 extern crate cookie;
+extern crate rustc_serialize;
+extern crate time;
+extern crate rand;
+#[cfg(feature = "brotli")]
+extern crate brotli;
+#[cfg(feature = "redis-sessions")]
+extern crate redis;
+#[cfg(feature = "redis-sessions")]
+extern crate r2d2;
+#[cfg(any(feature = "json", feature = "query", feature = "templates"))]
+#[cfg_attr(feature = "query", macro_use)]
+extern crate serde;
+#[cfg(any(feature = "json", feature = "templates"))]
+extern crate serde_json;
 
-use std::net::{Ipv4Addr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, SocketAddrV4};
+use std::path::Path;
 use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::Ordering;
 use std::collections::HashMap;
 
 use cookie::CookieJar;
+pub use cookie::Cookie;
 
 use hyper::Server;
-use hyper::uri::RequestUri;
 use hyper::server::response::Response as HttpResponse;
 use hyper::server::request::Request as HttpRequest;
 use hyper::server::Handler as HttpHandler;
@@ -59,16 +75,47 @@ use hyper::net::Fresh;
 pub use hyper::header;
 pub use hyper::status::StatusCode;
 pub use hyper::method::Method;
+pub use hyper::mime;
+pub use hyper::uri::RequestUri;
+pub use hyper::version::HttpVersion;
 
 use url::UrlParser;
+use url::form_urlencoded;
 
-use routing::Route;
+use routing::{Route, Router, Match, RouteError, RouteInfo, TrailingSlash, UrlError, is_below, join_path, toggle_trailing_slash};
 use request::Request;
-use response::Response;
+use response::{Response, Sendable};
+use group::Group;
+use middleware::{Chain, Guard, Middleware, Next};
+use fallible::ErrorHandler;
+use session::{CookieStore, Expiry, Session, SessionStore};
+use resource::Resource;
+use blueprint::Blueprint;
+use static_files::ServeStatic;
+#[cfg(feature = "templates")]
+use templates::{Mustache, TemplateEngine};
+
+#[macro_use]
+mod macros;
+mod inflate;
+mod deflate;
 
 pub mod routing;
 pub mod response;
 pub mod request;
+pub mod multipart;
+pub mod session;
+pub mod cookies;
+#[cfg(feature = "query")]
+pub mod query;
+pub mod group;
+pub mod resource;
+pub mod blueprint;
+pub mod static_files;
+pub mod middleware;
+pub mod fallible;
+#[cfg(feature = "templates")]
+pub mod templates;
 
 /// Trait that all handlers must implement.
 ///
@@ -104,9 +151,29 @@ impl<F> Handler for F where F: Fn(&Request, Response), F: Sync + Send {
 
 /// The Rask web application.
 pub struct Rask {
-    routes: Vec<Route>,
+    router: Router,
     error_handlers: HashMap<StatusCode, Arc<Box<Handler>>>,
+    blueprint_error_handlers: Vec<(String, HashMap<StatusCode, Arc<Box<Handler>>>)>,
+    fallback: Option<Box<Handler>>,
+    trailing_slash: TrailingSlash,
+    auto_options: bool,
+    method_override: bool,
+    max_body_size: Option<u64>,
+    max_decompressed_body_size: u64,
+    spool_uploads_above: Option<u64>,
+    compress_responses_above: Option<u64>,
+    #[cfg(feature = "json")]
+    pretty_json: bool,
+    trusted_proxies: Vec<IpAddr>,
+    continue_check: Option<Box<Fn(&Method, &str, &header::Headers) -> StatusCode + Send + Sync>>,
+    middleware: Chain,
+    error_translator: Option<Box<ErrorHandler>>,
+    session_store: Arc<Box<SessionStore>>,
+    session_expiry: Option<Expiry>,
+    #[cfg(feature = "templates")]
+    template_engine: Option<Arc<Box<TemplateEngine>>>,
     secret: String,
+    previous_secrets: Vec<String>,
 }
 
 impl Rask {
@@ -123,10 +190,32 @@ impl Rask {
         let mut default_error_handlers: HashMap<StatusCode, Arc<Box<Handler>>> = HashMap::new();
         default_error_handlers.insert(StatusCode::NotFound, Arc::new(Box::new(default_404_handler)));
         default_error_handlers.insert(StatusCode::InternalServerError, Arc::new(Box::new(default_500_handler)));
+        default_error_handlers.insert(StatusCode::PayloadTooLarge, Arc::new(Box::new(default_413_handler)));
+        default_error_handlers.insert(StatusCode::UnsupportedMediaType, Arc::new(Box::new(default_415_handler)));
         Rask {
-            routes: Vec::new(),
+            router: Router::new(),
             error_handlers: default_error_handlers,
+            blueprint_error_handlers: Vec::new(),
+            fallback: None,
+            trailing_slash: TrailingSlash::Strict,
+            auto_options: true,
+            method_override: false,
+            max_body_size: None,
+            max_decompressed_body_size: 16 * 1024 * 1024,
+            spool_uploads_above: None,
+            compress_responses_above: None,
+            #[cfg(feature = "json")]
+            pretty_json: false,
+            trusted_proxies: Vec::new(),
+            continue_check: None,
+            middleware: Chain::new(),
+            error_translator: None,
+            session_store: Arc::new(Box::new(CookieStore)),
+            session_expiry: None,
+            #[cfg(feature = "templates")]
+            template_engine: None,
             secret: secret.into(),
+            previous_secrets: Vec::new(),
         }
     }
 
@@ -162,12 +251,23 @@ impl Rask {
     ///
     /// * "/" -> requests matching the "/" literal.
     /// * "/profile" -> requests matching the "/profile" literal.
-    /// * "/{name}" -> requests matching any requests with "/" + a word.
+    /// * "/{name}" -> requests matching any requests with "/" + any text but "/".
     /// The name variable will be
     /// accesible from `Request.vars`.
+    /// * "/{id:int}" -> same as above, but only matches if the segment can be converted to
+    /// the given type: `str` (the default), `slug`, `int`, `float` or `uuid`. The converted
+    /// value is accessible from `Request.vars.get_as`.
+    /// * "/{id:[0-9]+}" -> same as above, but only matches if the segment matches the regex.
+    /// * "/static/**" -> requests matching "/static/" + anything, including slashes.
+    /// * "/static/{path:**}" -> same as above, with the rest of the path accessible as `path`
+    /// from `Request.vars`.
     ///
-    /// Rask will search for a matching handler in the order they are registered and
-    /// either returns a 405 (Method not allowed) or a 404 (Not found) error.
+    /// Rask will search for a matching handler segment by segment, trying literals before
+    /// variables and variables before wildcards, so "/users/new" is found before
+    /// "/users/{name}" regardless of the order they are registered in. Routes with the same
+    /// pattern are tried in the order they are registered. If routes match the path, but
+    /// none of them accepts the http method, a 405 (Method not allowed) error is returned,
+    /// otherwise a 404 (Not found) error.
     ///
     /// # Examples
     ///
@@ -185,10 +285,67 @@ impl Rask {
     ///
     /// # Panics
     ///
-    /// Panics if the given route can't be compiled to a valid regex.
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
     pub fn register<H: 'static + Handler>(&mut self, route: &str, handler: H) {
         let route = Route::new(route, handler);
-        self.routes.push(route);
+        self.router.add(route);
+    }
+
+    /// Same as `register`, but fails instead of registering a route that would never be
+    /// reached, because a route registered earlier matches the same requests. `register` only
+    /// logs a warning for such routes.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn user(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// assert!(app.try_register("/users/{id}", user).is_ok());
+    /// assert!(app.try_register("/users/{name}", user).is_err());
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn try_register<H: 'static + Handler>(&mut self, route: &str, handler: H) -> Result<(), RouteError> {
+        let route = Route::new(route, handler);
+        if let Some(earlier) = self.router.shadowing(&route) {
+            return Err(RouteError::Shadowed(earlier.pattern.clone()));
+        }
+        self.router.add(route);
+        Ok(())
+    }
+
+    /// Same as `register`, but also gives the route a name that `url_for` can build urls
+    /// from.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn profile(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.register_named("profile", "/profile/{name}", profile);
+    /// assert_eq!(app.url_for("profile", &[("name", "bob")]).unwrap(), "/profile/bob");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_named<H: 'static + Handler>(&mut self, name: &str, route: &str, handler: H) {
+        let route = Route::new(route, handler).named(name);
+        self.router.add(route);
     }
 
     /// Same as `register`, but also specifies which http methods the handler will receive.
@@ -218,83 +375,992 @@ impl Rask {
         handler: H)
     {
         let route = Route::with_methods(route, handler, methods);
-        self.routes.push(route);
+        self.router.add(route);
+    }
+
+    /// Same as `register_with_methods(route, &[Method::Get], handler)`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn items(_: &Request, _: Response) {
+    /// }
+    ///
+    /// fn create_item(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.get("/items", items);
+    /// app.post("/items", create_item);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn get<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        self.register_with_methods(route, &[Method::Get], handler);
+    }
+
+    /// Same as `register_with_methods(route, &[Method::Post], handler)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn post<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        self.register_with_methods(route, &[Method::Post], handler);
+    }
+
+    /// Same as `register_with_methods(route, &[Method::Put], handler)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn put<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        self.register_with_methods(route, &[Method::Put], handler);
+    }
+
+    /// Same as `register_with_methods(route, &[Method::Delete], handler)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn delete<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        self.register_with_methods(route, &[Method::Delete], handler);
+    }
+
+    /// Same as `register_with_methods(route, &[Method::Patch], handler)`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn patch<H: 'static + Handler>(&mut self, route: &str, handler: H) {
+        self.register_with_methods(route, &[Method::Patch], handler);
+    }
+
+    /// Same as `register_with_methods`, but also gives the route a name. See
+    /// `register_named`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_named_with_methods<H: 'static + Handler>(
+        &mut self,
+        name: &str,
+        route: &str,
+        methods: &[Method],
+        handler: H)
+    {
+        let route = Route::with_methods(route, handler, methods).named(name);
+        self.router.add(route);
+    }
+
+    /// Same as `register`, but routes with a higher `priority` are tried before routes with
+    /// a lower one when several patterns match the same path. Routes registered without a
+    /// priority have priority 0, and routes with the same priority are tried literal first,
+    /// then variables, then wildcards.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn new_user(_: &Request, _: Response) {
+    /// }
+    ///
+    /// fn user(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.register_with_priority("/users/new", 1, new_user);
+    /// app.register("/users/{id}", user);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_with_priority<H: 'static + Handler>(&mut self, route: &str, priority: i32, handler: H) {
+        let route = Route::new(route, handler).with_priority(priority);
+        self.router.add(route);
+    }
+
+    /// Same as `register`, but the route only matches requests whose `Host` header matches
+    /// `host`. Labels of the form `{name}` are variables, accessible from `Request.vars`
+    /// just like the variables of `route`. Routes restricted to a host are tried before
+    /// routes that aren't.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn users(_: &Request, _: Response) {
+    /// }
+    ///
+    /// fn tenant(req: &Request, _: Response) {
+    ///     let _tenant = &req.vars["tenant"];
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.register_on_host("api.example.com", "/v1/users", users);
+    /// app.register_on_host("{tenant}.example.com", "/", tenant);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type,
+    /// or if a label of `host` isn't a literal or a variable.
+    pub fn register_on_host<H: 'static + Handler>(&mut self, host: &str, route: &str, handler: H) {
+        let route = Route::new(route, handler).on_host(host);
+        self.router.add(route);
+    }
+
+    /// Redirects all requests for `route` to `location` with the given status code, e.g.
+    /// `StatusCode::MovedPermanently` for a page that has moved for good.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::{Rask, StatusCode};
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.register_redirect("/old", "/new", StatusCode::MovedPermanently);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if the given route can't be compiled to a valid regex or uses an unknown type.
+    pub fn register_redirect(&mut self, route: &str, location: &str, status_code: StatusCode) {
+        let location = location.to_owned();
+        self.register(route, move |_: &Request, mut res: Response| {
+            res.set_header(header::Location(location.clone()));
+            let _ = res.send(status_code);
+        });
+    }
+
+    /// Serves the files in the directory `root` for GET requests below `prefix`, e.g.
+    /// "/static/css/site.css" from "./public/css/site.css". Requests for files that don't
+    /// exist are handled by the NotFound error handler.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.serve_static("/static", "./public");
+    /// ```
+    pub fn serve_static<P: AsRef<Path>>(&mut self, prefix: &str, root: P) {
+        let route = join_path(prefix, "/{path:**}");
+        self.get(&route, ServeStatic::new(root));
+    }
+
+    /// Registers handlers for `route` per http method. See `resource::Resource`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn item(_: &Request, _: Response) {
+    /// }
+    ///
+    /// fn update_item(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.route("/items/{id:int}").get(item).put(update_item);
+    /// ```
+    pub fn route<'a>(&'a mut self, route: &str) -> Resource<'a> {
+        Resource::new(self, route)
+    }
+
+    /// Creates a group of routes that share the `prefix`. See `group::Group`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::Method::*;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn users(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.group("/api/v1").methods(&[Get]).register("/users", users);
+    /// ```
+    pub fn group<'a>(&'a mut self, prefix: &str) -> Group<'a> {
+        Group::new(self, prefix)
+    }
+
+    /// Mounts the routes and error handlers of `blueprint` at `prefix`. The blueprint's error
+    /// handlers are used for requests below `prefix`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a prefixed route can't be compiled to a valid regex.
+    pub fn mount(&mut self, prefix: &str, blueprint: Blueprint) {
+        let (routes, error_handlers) = blueprint.into_parts();
+        for route in routes {
+            self.router.add(route.prefixed(prefix));
+        }
+        if !error_handlers.is_empty() {
+            self.blueprint_error_handlers.push((prefix.trim_right_matches('/').to_owned(), error_handlers));
+        }
+    }
+
+    /// Sets how requests that only differ from a registered route by a trailing slash are
+    /// handled. Defaults to `TrailingSlash::Strict`, i.e. "/profile/" is not found if only
+    /// "/profile" is registered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::routing::TrailingSlash;
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.trailing_slash(TrailingSlash::Redirect);
+    /// ```
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) {
+        self.trailing_slash = policy;
+    }
+
+    /// Sets whether OPTIONS requests are answered automatically with an `Allow` header listing
+    /// the methods registered for the path. Enabled by default, but never used for paths
+    /// with a route explicitly registered for `Method::Options`. Disable it to let OPTIONS
+    /// requests reach routes registered for all methods.
+    pub fn auto_options(&mut self, enabled: bool) {
+        self.auto_options = enabled;
+    }
+
+    /// Sets whether POST requests can override their http method with the
+    /// `X-HTTP-Method-Override` header, a `_method` query string parameter, e.g.
+    /// "/items/1?_method=DELETE", or a `_method` field of an urlencoded body, e.g. from
+    /// `<input type="hidden" name="_method" value="DELETE">`. Lets html forms, which can only
+    /// send GET and POST, reach routes registered for PUT, PATCH and DELETE, the only methods
+    /// that can be given. The body is read within `max_body_size` and is still available to
+    /// the handler. Disabled by default.
+    pub fn method_override(&mut self, enabled: bool) {
+        self.method_override = enabled;
+    }
+
+    /// Limits the size of request bodies to `bytes`. Requests with a larger `Content-Length`
+    /// are failed with a 413 (Payload too large) error before they reach the handler. Bodies
+    /// without one, e.g. chunked ones, fail with `BodyError::TooLarge` when they're read past
+    /// the limit, and the response the handler then sends whole, or hands off with
+    /// `Response::error`, is replaced by the 413 error. Unlimited by default.
+    pub fn max_body_size(&mut self, bytes: u64) {
+        self.max_body_size = Some(bytes);
+    }
+
+    /// Limits the size compressed request bodies may decompress to, see `Request::body`.
+    /// Protects against small bodies that decompress to huge amounts of data. 16 MiB by
+    /// default.
+    pub fn max_decompressed_body_size(&mut self, bytes: u64) {
+        self.max_decompressed_body_size = bytes;
+    }
+
+    /// Writes uploaded files larger than `bytes` to temporary files instead of keeping them in
+    /// memory, see `Request::files` and `multipart::SavedFile`. The temporary files are
+    /// removed after the request unless they're persisted. Files are kept in memory by
+    /// default.
+    pub fn spool_uploads_above(&mut self, bytes: u64) {
+        self.spool_uploads_above = Some(bytes);
+    }
+
+    /// Compresses response bodies larger than `bytes` with gzip or deflate, if the client
+    /// accepts one of them. With the `brotli` feature brotli is preferred for clients
+    /// accepting `br`. Only bodies sent at once, not streamed, and of text-like content
+    /// types such as html, css, javascript and json are compressed. Turn it off for a
+    /// response with `Response::compress`. Responses aren't compressed by default.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.compress_responses_above(1024);
+    /// ```
+    pub fn compress_responses_above(&mut self, bytes: u64) {
+        self.compress_responses_above = Some(bytes);
+    }
+
+    /// Indents json bodies for reading them, e.g. while developing. Can be changed for a
+    /// response with `Response::pretty_json`. Compact by default. Requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.pretty_json(cfg!(debug_assertions));
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn pretty_json(&mut self, enabled: bool) {
+        self.pretty_json = enabled;
+    }
+
+    /// Sets the addresses of the proxies in front of the application, e.g. nginx. Only
+    /// requests from these addresses are trusted to give the address of the client with the
+    /// `Forwarded` or `X-Forwarded-For` headers. See `Request::client_ip`, and
+    /// `middleware::ProxyFix` for trusting proxies by network or number.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.trusted_proxies(&["127.0.0.1".parse().unwrap()]);
+    /// ```
+    pub fn trusted_proxies(&mut self, proxies: &[IpAddr]) {
+        self.trusted_proxies = proxies.to_vec();
+    }
+
+    /// Sets a check for requests with an `Expect: 100-continue` header, which wait for an
+    /// interim 100 (Continue) response before sending their body. The check gets the method,
+    /// path and headers of the request and returns `StatusCode::Continue` to let the client
+    /// send the body, or an error status to reject the request before the body is sent.
+    /// Requests with a `Content-Length` above `max_body_size` are rejected with 413 (Payload
+    /// too large) before the check is called. Without a check all other requests continue.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::{Rask, StatusCode};
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.check_continue(|_, path, headers| {
+    ///     if path.starts_with("/uploads") && headers.get_raw("Authorization").is_none() {
+    ///         StatusCode::Unauthorized
+    ///     } else {
+    ///         StatusCode::Continue
+    ///     }
+    /// });
+    /// ```
+    pub fn check_continue<F>(&mut self, check: F)
+        where F: 'static + Fn(&Method, &str, &header::Headers) -> StatusCode + Send + Sync
+    {
+        self.continue_check = Some(Box::new(check));
+    }
+
+    /// Adds middleware that runs for every request before it's routed, after the middleware
+    /// added before it in its phase, see `middleware::Middleware` and `middleware::Phase`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::header::Server;
+    /// use rask::middleware::{Csrf, Next};
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn server<'a, 'b>(req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+    ///     res.set_header(Server("rask".to_owned()));
+    ///     next.run(req, res);
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.wrap(server);
+    /// app.wrap(Csrf::new());
+    /// ```
+    pub fn wrap<M: 'static + Middleware>(&mut self, middleware: M) {
+        let name = middleware.name().to_owned();
+        self.wrap_named(&name, middleware);
+    }
+
+    /// Adds middleware like `wrap`, that only runs for requests to `prefix` and paths below
+    /// it, e.g. to check API tokens without checking requests for static files and pages.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::{Rask, StatusCode};
+    /// use rask::middleware::Next;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn require_token<'a, 'b>(req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+    ///     if req.headers().get_raw("X-Api-Token").is_some() {
+    ///         next.run(req, res);
+    ///     } else {
+    ///         let _ = res.error(StatusCode::Unauthorized);
+    ///     }
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.wrap_under("/api", require_token);
+    /// ```
+    pub fn wrap_under<M: 'static + Middleware>(&mut self, prefix: &str, middleware: M) {
+        let name = middleware.name().to_owned();
+        let phase = middleware.phase();
+        self.middleware.push_under(Some(prefix), &name, phase, Box::new(middleware));
+    }
+
+    /// Adds middleware like `wrap`, named `name` instead of its own name, e.g. to add the
+    /// same kind of middleware twice and refer to each.
+    pub fn wrap_named<M: 'static + Middleware>(&mut self, name: &str, middleware: M) {
+        let phase = middleware.phase();
+        self.middleware.push(name, phase, Box::new(middleware));
+    }
+
+    /// Adds middleware right before the middleware named `existing`, in its phase, so it runs
+    /// before it whenever it's added.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no middleware named `existing`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::middleware::{AccessLog, Csrf, ResponseTimer};
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.wrap(Csrf::new());
+    /// app.wrap(AccessLog::common());
+    /// app.wrap_before("access_log", ResponseTimer::new());
+    /// assert_eq!(app.middleware(), vec!["response_timer", "access_log", "csrf"]);
+    /// ```
+    pub fn wrap_before<M: 'static + Middleware>(&mut self, existing: &str, middleware: M) {
+        let name = middleware.name().to_owned();
+        if !self.middleware.push_before(existing, &name, Box::new(middleware)) {
+            panic!("No middleware named {:?}.", existing);
+        }
+    }
+
+    /// Adds middleware right after the middleware named `existing`, in its phase.
+    ///
+    /// # Panics
+    ///
+    /// Panics if there's no middleware named `existing`.
+    pub fn wrap_after<M: 'static + Middleware>(&mut self, existing: &str, middleware: M) {
+        let name = middleware.name().to_owned();
+        if !self.middleware.push_after(existing, &name, Box::new(middleware)) {
+            panic!("No middleware named {:?}.", existing);
+        }
+    }
+
+    /// The names of the middleware, in the order it runs. Middleware without a name is
+    /// listed as "".
+    pub fn middleware(&self) -> Vec<&str> {
+        self.middleware.names()
+    }
+
+    /// Adds a guard that's called for every request before it's routed, in order with the
+    /// middleware. If the guard returns a response it's sent right away, without calling the
+    /// handler, e.g. for a maintenance page or to require a login for the whole application.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::{Rask, StatusCode};
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.before_request(|req| {
+    ///     match req.session().get("username") {
+    ///         Some(_) => None,
+    ///         None if req.path.as_ref().map_or(false, |path| path == "/login") => None,
+    ///         None => Some(("Please log in.", StatusCode::Unauthorized)),
+    ///     }
+    /// });
+    /// ```
+    pub fn before_request<F, S>(&mut self, guard: F)
+        where F: 'static + Fn(&Request) -> Option<S> + Send + Sync, S: 'static + Sendable<'static>
+    {
+        self.wrap(Guard::new(guard));
+    }
+
+    /// Sets the `ErrorHandler` that answers the errors returned by fallible handlers, see
+    /// `fallible::fallible`, replacing an earlier one.
+    pub fn handle_errors<H: 'static + ErrorHandler>(&mut self, handler: H) {
+        self.error_translator = Some(Box::new(handler));
+    }
+
+    /// The handler given to `handle_errors`.
+    #[doc(hidden)]
+    pub fn error_translator(&self) -> Option<&ErrorHandler> {
+        self.error_translator.as_ref().map(|handler| &**handler)
+    }
+
+    /// Sets secrets the application used before the one it's created with, newest first.
+    /// Cookies and sessions are signed and encrypted with the new secret, but the ones made
+    /// with the previous secrets are still accepted, so the secret can be changed without
+    /// logging everyone out. Sessions are saved with the new secret when they're used.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    ///
+    /// let mut app = Rask::new("NEW SUPER SECRET KEY");
+    /// app.previous_secrets(&["SUPER SECRET KEY"]);
+    /// ```
+    pub fn previous_secrets(&mut self, secrets: &[&str]) {
+        self.previous_secrets = secrets.iter().map(|secret| secret.to_string()).collect();
+    }
+
+    /// Sets where sessions are kept, see `session::Session`. By default they're kept in the
+    /// session cookie, see `session::CookieStore`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use std::io;
+    /// use std::sync::Mutex;
+    /// use rask::Rask;
+    /// use rask::session::{SessionData, SessionStore};
+    ///
+    /// struct Sessions {
+    ///     sessions: Mutex<HashMap<String, SessionData>>,
+    /// }
+    ///
+    /// impl SessionStore for Sessions {
+    ///     fn load(&self, id: &str) -> Option<SessionData> {
+    ///         self.sessions.lock().unwrap().get(id).cloned()
+    ///     }
+    ///
+    ///     fn save(&self, id: Option<&str>, data: &SessionData) -> io::Result<String> {
+    ///         let mut sessions = self.sessions.lock().unwrap();
+    ///         let id = id.map(|id| id.to_owned()).unwrap_or_else(|| sessions.len().to_string());
+    ///         sessions.insert(id.clone(), data.clone());
+    ///         Ok(id)
+    ///     }
+    ///
+    ///     fn destroy(&self, id: &str) {
+    ///         self.sessions.lock().unwrap().remove(id);
+    ///     }
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.session_store(Sessions { sessions: Mutex::new(HashMap::new()) });
+    /// ```
+    pub fn session_store<S: 'static + SessionStore>(&mut self, store: S) {
+        self.session_store = Arc::new(Box::new(store));
+    }
+
+    /// Makes sessions expire, see `session::Expiry`. Without it sessions only end when the
+    /// browser is closed, and permanent ones 31 days after they're last used.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::time::Duration;
+    /// use rask::Rask;
+    /// use rask::session::Expiry;
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.session_expiry(Expiry::Sliding(Duration::from_secs(2 * 60 * 60)));
+    /// ```
+    pub fn session_expiry(&mut self, expiry: Expiry) {
+        self.session_expiry = Some(expiry);
     }
 
     /// Register a error handler for the specified http status code. This will only have an
-    /// effect for NotFound (404) and InternalServerError (500) for now.
+    /// effect for NotFound (404), PayloadTooLarge (413), UnsupportedMediaType (415) and
+    /// InternalServerError (500) for now.
     pub fn register_error_handler<H: 'static + Handler>(&mut self, status_code: StatusCode, handler: H) {
         self.error_handlers.insert(status_code, Arc::new(Box::new(handler)));
     }
 
-    fn find_route(&self, path: &str, method: &Method) -> RouteResult {
-        for route in self.routes.iter() {
-            if route.re.is_match(path) {
-                if route.methods.is_empty() || route.methods.contains(method) {
-                    return RouteResult::Found(&route);
+    /// The registered routes, in the order they are registered.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::Method::*;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn users(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.register_named_with_methods("users", "/users", &[Get], users);
+    ///
+    /// let routes = app.routes();
+    /// assert_eq!(routes[0].pattern, "/users");
+    /// assert_eq!(routes[0].name, Some("users"));
+    /// assert_eq!(routes[0].methods, &[Get]);
+    /// ```
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        self.router.routes().iter().map(RouteInfo::from).collect()
+    }
+
+    /// Register a handler for all requests that don't match a route, instead of failing them
+    /// with a 404 (Not found) error. Unlike the NotFound error handler, the handler is free to
+    /// respond however it likes, e.g. serve the index page of a single page app.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn single_page_app(_: &Request, res: Response) {
+    ///     res.send("<html>...</html>");
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.register_fallback(single_page_app);
+    /// ```
+    pub fn register_fallback<H: 'static + Handler>(&mut self, handler: H) {
+        self.fallback = Some(Box::new(handler));
+    }
+
+    /// Renders templates from the directory `dir` with the built-in `templates::Mustache`
+    /// engine, for `Response::render`. Requires the `templates` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn profile(req: &Request, res: Response) {
+    ///     let mut context = HashMap::new();
+    ///     context.insert("name", req.vars.get("name").unwrap());
+    ///     res.render("profile.html", &context);
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.templates("./templates");
+    /// app.get("/profile/{name}", profile);
+    /// ```
+    #[cfg(feature = "templates")]
+    pub fn templates<P: AsRef<Path>>(&mut self, dir: P) {
+        self.template_engine(Mustache::new(dir));
+    }
+
+    /// Renders templates with `engine` for `Response::render`, replacing the engine
+    /// registered earlier. Requires the `templates` feature.
+    #[cfg(feature = "templates")]
+    pub fn template_engine<E: 'static + TemplateEngine>(&mut self, engine: E) {
+        self.template_engine = Some(Arc::new(Box::new(engine)));
+    }
+
+    /// Builds the url for the route named `name`, filling in the variables of its pattern
+    /// from `params`. Params that aren't variables in the pattern are added as a query
+    /// string.
+    ///
+    /// Fails if there's no route named `name`, a variable is missing from `params` or a value
+    /// can't be converted to the type of its variable.
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let route = self.router
+            .routes()
+            .iter()
+            .find(|route| route.name.as_ref().map(|n| &n[..]) == Some(name));
+        match route {
+            Some(route) => route.build_url(params),
+            None => Err(UrlError::UnknownRoute(name.to_owned())),
+        }
+    }
+
+    fn error_handler(&self, path: Option<&str>, status_code: &StatusCode) -> &Arc<Box<Handler>> {
+        self.find_error_handler(path, status_code).expect("no default error handler")
+    }
+
+    /// The error handler for `status_code`, from the innermost blueprint mounted above `path`
+    /// that has one, else from the application.
+    fn find_error_handler(&self, path: Option<&str>, status_code: &StatusCode) -> Option<&Arc<Box<Handler>>> {
+        let blueprint_handlers = path.and_then(|path| {
+            self.blueprint_error_handlers
+                .iter()
+                .filter(|&&(ref prefix, ref handlers)| is_below(path, prefix) && handlers.contains_key(status_code))
+                .max_by_key(|&&(ref prefix, _)| prefix.len())
+        });
+        match blueprint_handlers {
+            Some(&(_, ref handlers)) => handlers.get(status_code),
+            None => self.error_handlers.get(status_code),
+        }
+    }
+
+    /// Calls `handler`, then the error handler for the response if the handler handed it off
+    /// with `Response::error`. If the request body turned out to be larger than
+    /// `max_body_size`, that's the handler for 413 (Payload Too Large).
+    fn dispatch(&self, handler: &Handler, path: Option<&str>, request: &Request, mut response: Response) {
+        let handoff = response.handoff();
+        let body_too_large = response.body_too_large();
+        handler.handle(request, response);
+        let handed_off = handoff.borrow_mut().take();
+        if let Some((status, response)) = handed_off {
+            let status = if body_too_large.load(Ordering::Relaxed) { StatusCode::PayloadTooLarge } else { status };
+            match self.find_error_handler(path, &status) {
+                Some(error_handler) => error_handler.handle(request, response),
+                None => {
+                    let _ = response.send(status);
+                },
+            }
+        }
+    }
+
+    /// Routes `request` to its handler, after the middleware.
+    fn route_request<'a, 'k>(&'a self, request: &mut Request<'a, 'k>, mut response: Response<'a>) {
+        // errors from here on are handled here, not after the middleware
+        response.end_handoff();
+
+        let mut path = request.path.clone().unwrap_or("/".to_owned());
+        let method = request.method().clone();
+        let host = request.headers().get::<header::Host>().map(|host| host.hostname.clone());
+        let host = host.as_ref().map(|host| &host[..]);
+
+        let mut route_result = self.find_route(host, &path, &method);
+        if let RouteResult::NotFound = route_result {
+            let alternative = toggle_trailing_slash(&path)
+                .into_iter()
+                .find(|alternative| match self.find_route(host, alternative, &method) {
+                    RouteResult::NotFound => false,
+                    _ => true,
+                });
+            match (self.trailing_slash, alternative) {
+                (TrailingSlash::Ignore, Some(alternative)) => {
+                    path = alternative;
+                    request.path = Some(path.clone());
+                    route_result = self.find_route(host, &path, &method);
+                },
+                (TrailingSlash::Redirect, Some(alternative)) => {
+                    let location = match request.query_string() {
+                        Some(query_string) => format!("{}?{}", alternative, query_string),
+                        None => alternative,
+                    };
+                    response.set_header(header::Location(location));
+                    let _ = response.send(StatusCode::MovedPermanently);
+                    return;
+                },
+                _ => {},
+            }
+        }
+
+        if self.auto_options && method == Method::Options {
+            let routes = self.matching_routes(host, &path);
+            if !routes.is_empty() && !routes.iter().any(|m| m.route.methods.contains(&Method::Options)) {
+                response.set_header(header::Allow(allowed_methods(&routes, true)));
+                let _ = response.send(StatusCode::Ok);
+                return;
+            }
+        }
+
+        match route_result {
+            RouteResult::Found(Match { route, vars }) => {
+                debug!("[{}] Matched route {:?} ({:?}).", request.id(), route.pattern, route.name);
+                request.set_route(RouteInfo::from(route), vars);
+                if self.body_too_large(request.headers()) {
+                    self.error_handler(Some(&path), &StatusCode::PayloadTooLarge).handle(request, response);
+                    return;
                 }
-                else {
-                    return RouteResult::MethodNotAllowed;
+                if has_body(request.headers()) && !route.accepts_content_type(request.content_type()) {
+                    debug!("[{}] Content type {:?} not accepted by {:?}.", request.id(), request.content_type(), route.pattern);
+                    self.error_handler(Some(&path), &StatusCode::UnsupportedMediaType).handle(request, response);
+                    return;
+                }
+                self.dispatch(&*route.handler, Some(&path), request, response);
+            },
+            RouteResult::MethodNotAllowed => {
+                let routes = self.matching_routes(host, &path);
+                response.set_header(header::Allow(allowed_methods(&routes, self.auto_options)));
+                let _ = response.send(("405 Method Not Allowed", StatusCode::MethodNotAllowed));
+            }
+            RouteResult::NotFound => {
+                match self.fallback {
+                    Some(ref fallback) => self.dispatch(&**fallback, Some(&path), request, response),
+                    None => self.error_handler(Some(&path), &StatusCode::NotFound).handle(request, response),
                 }
             }
         }
+    }
+
+    /// Whether the `Content-Length` of a request is above `max_body_size`.
+    fn body_too_large(&self, headers: &header::Headers) -> bool {
+        match (self.max_body_size, headers.get::<header::ContentLength>()) {
+            (Some(max), Some(&header::ContentLength(length))) => length > max,
+            _ => false,
+        }
+    }
+
+    /// The method given by the `X-HTTP-Method-Override` header, the `_method` query string
+    /// parameter or the `_method` field of the urlencoded body of `req`, if any.
+    fn overridden_method(&self, req: &Request) -> Option<Method> {
+        let header = req.headers()
+            .get_raw("X-HTTP-Method-Override")
+            .and_then(|values| values.first())
+            .map(|value| String::from_utf8_lossy(value).into_owned());
+        let value = header
+            .or_else(|| req.gets.get("_method").cloned())
+            .or_else(|| match req.content_type() {
+                Some(&mime::Mime(mime::TopLevel::Application, mime::SubLevel::WwwFormUrlEncoded, _))
+                    if !self.body_too_large(req.headers()) =>
+                {
+                    req.body().ok().and_then(|body| form_field(&body, "_method"))
+                },
+                _ => None,
+            });
+        value.and_then(|value| override_method(&value))
+    }
+
+    /// The cookies of a request, keyed with the application's secret.
+    fn cookie_jar(&self, headers: &header::Headers) -> CookieJar<'static> {
+        keyed_cookie_jar(headers, &self.secret)
+    }
+
+    /// The cookies of a request keyed with each of the previous secrets, to read the cookies
+    /// signed or encrypted with them.
+    fn previous_cookie_jars(&self, headers: &header::Headers) -> Vec<CookieJar<'static>> {
+        self.previous_secrets.iter().map(|secret| keyed_cookie_jar(headers, secret)).collect()
+    }
+
+    fn matching_routes(&self, host: Option<&str>, path: &str) -> Vec<Match> {
+        self.router.find_on_host(host, path)
+    }
+
+    fn find_route(&self, host: Option<&str>, path: &str, method: &Method) -> RouteResult {
+        let routes = self.matching_routes(host, path);
+        if routes.is_empty() {
+            return RouteResult::NotFound;
+        }
 
-        RouteResult::NotFound
+        match routes.into_iter().find(|m| m.route.methods.is_empty() || m.route.methods.contains(method)) {
+            Some(m) => RouteResult::Found(m),
+            None => RouteResult::MethodNotAllowed,
+        }
+    }
+}
+
+/// The cookies in `headers`, keyed with `secret`.
+fn keyed_cookie_jar(headers: &header::Headers, secret: &str) -> CookieJar<'static> {
+    let key = secret.as_bytes();
+    match headers.get::<header::Cookie>() {
+        Some(cookie) => cookie.to_cookie_jar(key),
+        None => CookieJar::new(key)
     }
 }
 
 enum RouteResult<'a> {
-    Found(&'a Route),
+    Found(Match<'a>),
     MethodNotAllowed,
     NotFound,
 }
 
 
 impl HttpHandler for Rask {
-    fn handle(&self, req: HttpRequest, res: HttpResponse<Fresh>) {
-        let cookie_jar = {
-            let key = &self.secret.as_bytes();
-            match req.headers.get::<header::Cookie>() {
-                Some(cookie) => cookie.to_cookie_jar(key),
-                None => CookieJar::new(key)
-            }
-        };
+    fn check_continue(&self, (method, uri, headers): (&Method, &RequestUri, &header::Headers)) -> StatusCode {
+        if self.body_too_large(headers) {
+            return StatusCode::PayloadTooLarge;
+        }
+        match self.continue_check {
+            Some(ref check) => {
+                let path = get_path_and_query_string(uri).map(|(path, _)| path).unwrap_or(String::new());
+                check(method, &path, headers)
+            },
+            None => StatusCode::Continue,
+        }
+    }
 
-        let response = Response::new(res, cookie_jar);
+    fn handle<'a, 'k>(&'a self, req: HttpRequest<'a, 'k>, res: HttpResponse<'a, Fresh>) {
+        let id = request::request_id(&req.headers);
+        let cookie_jar = self.cookie_jar(&req.headers);
+        let session = Session::load(&**self.session_store, &cookie_jar, &self.previous_cookie_jars(&req.headers), self.session_expiry);
+        let mut response = Response::new(res, cookie_jar, session.clone(), self.session_store.clone());
+        response.set_compression(self.compress_responses_above, response::accepted_encoding(&req.headers));
+        #[cfg(feature = "json")]
+        response.pretty_json(self.pretty_json);
+        #[cfg(feature = "templates")]
+        response.set_template_engine(self.template_engine.clone());
 
         let (path, query_string) = match get_path_and_query_string(&req.uri) {
             Some((path, query_string)) => (path, query_string),
             None => {
-                let request = Request::new(req, None, None, None);
-                warn!("Couldn't parse path and/or query string from RequestUri. Failing with 500 error.");
-                self.error_handlers[&StatusCode::InternalServerError].handle(&request, response);
+                let request = Request::new(self, req, id.clone(), None, HashMap::new(), None, None, session, response.cookies_read(), response.body_too_large());
+                warn!("[{}] Couldn't parse path and/or query string from RequestUri. Failing with 500 error.", id);
+                self.error_handler(None, &StatusCode::InternalServerError).handle(&request, response);
                 return;
             }
         };
 
-        info!("{:?} {:?}", req.method, path);
-
-        match self.find_route(&path, &req.method) {
-            RouteResult::Found(router) => {
-                let captures = router.re.captures(&path);
-                let request = Request::new(req, captures, Some(path.clone()), query_string);
-                (*router.handler).handle(&request, response);
-            },
-            RouteResult::MethodNotAllowed => {
-                let _ = response.send(("405 Method Not Allowed", StatusCode::MethodNotAllowed));
-            }
-            RouteResult::NotFound => {
-                let req = Request::new(req, None, Some(path), query_string);
-                self.error_handlers[&StatusCode::NotFound].handle(&req, response);
+        let mut request = Request::new(self, req, id, None, HashMap::new(), Some(path), query_string, session, response.cookies_read(), response.body_too_large());
+        if self.method_override && *request.method() == Method::Post {
+            if let Some(method) = self.overridden_method(&request) {
+                debug!("[{}] Overriding method POST with {:?}.", request.id(), method);
+                request.set_method(method);
             }
         }
+
+        debug!("[{}] {:?} {:?}", request.id(), request.method(), request.path);
+
+        Next::new(self, self.middleware.entries()).run(&mut request, response);
+    }
+}
+
+/// Whether a request has a body, according to its `Content-Length` or `Transfer-Encoding`.
+fn has_body(headers: &header::Headers) -> bool {
+    match headers.get::<header::ContentLength>() {
+        Some(&header::ContentLength(length)) => length > 0,
+        None => headers.has::<header::TransferEncoding>(),
     }
 }
 
+/// The union of the methods of `routes`, or all methods if one of them doesn't restrict them.
+/// `Method::Options` is included if `with_options` is set, i.e. it's answered automatically.
+fn allowed_methods(routes: &[Match], with_options: bool) -> Vec<Method> {
+    if routes.iter().any(|m| m.route.methods.is_empty()) {
+        return vec![Method::Options, Method::Get, Method::Post, Method::Put, Method::Delete,
+                    Method::Head, Method::Trace, Method::Connect, Method::Patch];
+    }
+
+    let mut methods = if with_options { vec![Method::Options] } else { Vec::new() };
+    for method in routes.iter().flat_map(|m| m.route.methods.iter()) {
+        if !methods.contains(method) {
+            methods.push(method.clone());
+        }
+    }
+    methods
+}
+
+/// The method `value` overrides POST with, if it's one of the methods that can be given.
+fn override_method(value: &str) -> Option<Method> {
+    match value.trim().to_uppercase().parse() {
+        Ok(method @ Method::Put) | Ok(method @ Method::Patch) | Ok(method @ Method::Delete) => Some(method),
+        _ => None,
+    }
+}
+
+/// The value of the field `name` of the urlencoded `body`, if it has one.
+fn form_field(body: &[u8], name: &str) -> Option<String> {
+    form_urlencoded::parse(body).into_iter().find(|&(ref key, _)| key == name).map(|(_, value)| value)
+}
+
 fn default_404_handler(_: &Request, res: Response) {
     let _ = res.send(("404 Not Found", StatusCode::NotFound));
 }
 
+fn default_413_handler(_: &Request, res: Response) {
+    let _ = res.send(("413 Payload Too Large", StatusCode::PayloadTooLarge));
+}
+
+fn default_415_handler(_: &Request, res: Response) {
+    let _ = res.send(("415 Unsupported Media Type", StatusCode::UnsupportedMediaType));
+}
+
 fn default_500_handler(_: &Request, res: Response) {
     let _ = res.send(("500 Internal server error", StatusCode::InternalServerError));
 }
@@ -319,3 +1385,16 @@ fn get_path_and_query_string(uri: &RequestUri) -> Option<(String, Option<String>
         }
     }
 }
+
+#[test]
+fn methods_are_overridden_by_form_fields() {
+    let body = b"name=lamp&_method=delete";
+    assert_eq!(form_field(body, "_method"), Some("delete".to_owned()));
+    assert_eq!(form_field(b"name=lamp", "_method"), None);
+    assert_eq!(form_field(body, "_method").and_then(|value| override_method(&value)), Some(Method::Delete));
+    assert_eq!(override_method(" PATCH "), Some(Method::Patch));
+    assert_eq!(override_method("PUT"), Some(Method::Put));
+    assert_eq!(override_method("TRACE"), None);
+    assert_eq!(override_method("CONNECT"), None);
+    assert_eq!(override_method("GET"), None);
+}
@@ -0,0 +1,45 @@
+/// Registers a table of routes on a `Rask` application. Each line gives the http methods,
+/// the route and the handler, the same as `Rask::register_with_methods`.
+///
+/// # Examples
+///
+/// ```rust
+/// #[macro_use]
+/// extern crate rask;
+///
+/// use rask::Rask;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn items(_: &Request, _: Response) {
+/// }
+///
+/// fn create_item(_: &Request, _: Response) {
+/// }
+///
+/// fn item(_: &Request, _: Response) {
+/// }
+///
+/// fn main() {
+///     let mut app = Rask::new("SUPER SECRET KEY");
+///     routes!(app;
+///         [Get, Head] "/items"          => items,
+///         [Post]      "/items"          => create_item,
+///         [Get]       "/items/{id:int}" => item,
+///     );
+///     assert_eq!(app.routes().len(), 3);
+/// }
+/// ```
+///
+/// # Panics
+///
+/// Panics if a route can't be compiled to a valid regex or uses an unknown type.
+#[macro_export]
+macro_rules! routes {
+    ($app:expr; $([$($method:ident),+] $route:expr => $handler:expr),* $(,)*) => {{
+        let app: &mut $crate::Rask = &mut $app;
+        $(
+            app.register_with_methods($route, &[$($crate::Method::$method),+], $handler);
+        )*
+    }};
+}
@@ -0,0 +1,115 @@
+use std::time::Instant;
+
+use hyper::uri::RequestUri;
+
+use time;
+
+use middleware::{Middleware, Next, Phase, ResponseTime};
+use request::Request;
+use response::Response;
+
+/// Logs a line for each request in the Common or Combined Log Format, as web servers do, at
+/// the info level with the target "rask::access". The line is followed by the time taken to
+/// handle the request in milliseconds, the one measured by `ResponseTimer` if it's added
+/// after this middleware, e.g.
+///
+/// ```text
+/// 127.0.0.1 - bob [10/Oct/2016:13:55:36 +0000] "GET /index.html HTTP/1.1" 200 2326 4
+/// ```
+///
+/// The client address is the one of `Request::client_ip`, and the user the one of
+/// `Request::basic_auth`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::middleware::AccessLog;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(AccessLog::combined());
+/// ```
+pub struct AccessLog {
+    combined: bool,
+}
+
+impl AccessLog {
+    /// Logs in the Common Log Format.
+    pub fn common() -> AccessLog {
+        AccessLog { combined: false }
+    }
+
+    /// Logs in the Combined Log Format, which adds the `Referer` and `User-Agent` headers.
+    pub fn combined() -> AccessLog {
+        AccessLog { combined: true }
+    }
+}
+
+impl Middleware for AccessLog {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+        let started = Instant::now();
+        let date = time::now_utc();
+        let sent = res.sent();
+        next.run(req, res);
+        // the time up to sending the response, if it's measured
+        let elapsed = match req.extensions().get::<ResponseTime>() {
+            Some(&ResponseTime(elapsed)) => elapsed,
+            None => started.elapsed(),
+        };
+
+        let mut line = format!("{} - {} [{}] \"{} {} {}\" {} {}",
+                               req.client_ip(),
+                               req.basic_auth().map_or("-".to_owned(), |(user, _)| escape(&user)),
+                               date.strftime("%d/%b/%Y:%H:%M:%S +0000").unwrap(),
+                               req.method(),
+                               escape(&request_target(req.uri())),
+                               req.version(),
+                               sent.status().map_or("-".to_owned(), |status| status.to_u16().to_string()),
+                               match sent.bytes() {
+                                   0 => "-".to_owned(),
+                                   bytes => bytes.to_string(),
+                               });
+        if self.combined {
+            let header = |name: &str| {
+                req.headers()
+                    .get_raw(name)
+                    .and_then(|values| values.first())
+                    .map_or("-".to_owned(), |value| escape(&String::from_utf8_lossy(value)))
+            };
+            line.push_str(&format!(" \"{}\" \"{}\"", header("Referer"), header("User-Agent")));
+        }
+        let millis = elapsed.as_secs() * 1000 + elapsed.subsec_nanos() as u64 / 1000000;
+        info!(target: "rask::access", "{} {}", line, millis);
+    }
+
+    fn name(&self) -> &str {
+        "access_log"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Outer
+    }
+}
+
+/// The request target as it was sent in the request line.
+fn request_target(uri: &RequestUri) -> String {
+    match *uri {
+        RequestUri::AbsolutePath(ref path) => path.clone(),
+        RequestUri::AbsoluteUri(ref url) => url.to_string(),
+        RequestUri::Authority(ref authority) => authority.clone(),
+        RequestUri::Star => "*".to_owned(),
+    }
+}
+
+/// Escapes quotes, backslashes and control characters, so values from the client can't
+/// break the line up or forge another one.
+fn escape(value: &str) -> String {
+    value.chars().flat_map(|c| c.escape_default()).collect()
+}
+
+#[test]
+fn values_are_escaped() {
+    assert_eq!(escape("Mozilla/5.0 (X11)"), "Mozilla/5.0 (X11)");
+    assert_eq!(escape("a\" \"b\n127.0.0.1 - -"), "a\\\" \\\"b\\n127.0.0.1 - -");
+    assert_eq!(request_target(&RequestUri::AbsolutePath("/search?q=rust".to_owned())), "/search?q=rust");
+}
@@ -0,0 +1,92 @@
+use hyper::status::StatusCode;
+
+use middleware::{Middleware, Next};
+use request::Request;
+use response::Response;
+use routing::is_below;
+
+/// Requires a username and password, checked by a callback, for the requests below a path,
+/// or all requests. Other requests are failed with 401 (Unauthorized) and a
+/// `WWW-Authenticate` header, which makes browsers ask for the credentials.
+///
+/// The credentials are sent as plain text, so only use it over https.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::middleware::BasicAuth;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(BasicAuth::new("Admin", |user, password| user == "admin" && password == "hunter2")
+///     .under("/admin"));
+/// ```
+pub struct BasicAuth {
+    realm: String,
+    verify: Box<Fn(&str, &str) -> bool + Send + Sync>,
+    prefixes: Vec<String>,
+}
+
+impl BasicAuth {
+    /// Checks the username and password of requests with `verify`. The `realm` is shown by
+    /// browsers when they ask for credentials.
+    pub fn new<F>(realm: &str, verify: F) -> BasicAuth
+        where F: 'static + Fn(&str, &str) -> bool + Send + Sync
+    {
+        BasicAuth {
+            realm: realm.to_owned(),
+            verify: Box::new(verify),
+            prefixes: Vec::new(),
+        }
+    }
+
+    /// Only requires credentials for requests to `prefix` and paths below it, e.g. the routes
+    /// of a `Group`. Can be called several times. Without it all requests require them.
+    pub fn under(mut self, prefix: &str) -> BasicAuth {
+        self.prefixes.push(prefix.to_owned());
+        self
+    }
+
+    fn protects(&self, path: Option<&str>) -> bool {
+        self.prefixes.is_empty() || path.map_or(false, |path| self.prefixes.iter().any(|prefix| is_below(path, prefix)))
+    }
+}
+
+impl Middleware for BasicAuth {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        let path = req.path.clone();
+        if !self.protects(path.as_ref().map(|path| &path[..])) {
+            return next.run(req, res);
+        }
+        match req.basic_auth() {
+            Some((ref user, ref password)) if (self.verify)(user, password) => next.run(req, res),
+            credentials => {
+                if let Some((user, _)) = credentials {
+                    warn!("[{}] Invalid credentials for {:?}.", req.id(), user);
+                }
+                res.set_raw_header("WWW-Authenticate", &challenge(&self.realm));
+                let _ = res.error(StatusCode::Unauthorized);
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        "basic_auth"
+    }
+}
+
+/// The `WWW-Authenticate` value asking for credentials for `realm`.
+fn challenge(realm: &str) -> String {
+    let realm = realm.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("Basic realm=\"{}\", charset=\"UTF-8\"", realm)
+}
+
+#[test]
+fn challenges_and_prefixes() {
+    assert_eq!(challenge("Admin \"area\""), "Basic realm=\"Admin \\\"area\\\"\", charset=\"UTF-8\"");
+
+    let auth = BasicAuth::new("Admin", |_, _| true).under("/admin");
+    assert!(auth.protects(Some("/admin/users")));
+    assert!(!auth.protects(Some("/administrators")));
+    assert!(BasicAuth::new("Admin", |_, _| true).protects(Some("/")));
+}
@@ -0,0 +1,105 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+use hyper::status::StatusCode;
+
+use middleware::{Middleware, Next, Phase};
+use request::Request;
+use response::Response;
+
+/// Limits how many requests are handled at the same time. Requests over the limit are failed
+/// right away with 503 (Service Unavailable) and a `Retry-After` header, instead of waiting
+/// for one of the server's threads, so slow handlers can't take all of them.
+///
+/// Added with `Rask::wrap` it limits all requests, with `Rask::wrap_under` or `Group::wrap`
+/// only the requests below a path, each with its own count.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::middleware::ConcurrencyLimit;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(ConcurrencyLimit::new(64));
+/// app.wrap_under("/reports", ConcurrencyLimit::new(2));
+/// ```
+pub struct ConcurrencyLimit {
+    max: usize,
+    retry_after: u64,
+    in_flight: AtomicUsize,
+}
+
+impl ConcurrencyLimit {
+    /// Handles at most `max` requests at the same time, asking clients to retry after a
+    /// second.
+    pub fn new(max: usize) -> ConcurrencyLimit {
+        ConcurrencyLimit {
+            max: max,
+            retry_after: 1,
+            in_flight: AtomicUsize::new(0),
+        }
+    }
+
+    /// Asks clients to retry after `retry_after` instead, in whole seconds.
+    pub fn retry_after(mut self, retry_after: Duration) -> ConcurrencyLimit {
+        self.retry_after = retry_after.as_secs();
+        self
+    }
+}
+
+impl Middleware for ConcurrencyLimit {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        match Permit::acquire(&self.in_flight, self.max) {
+            Some(_permit) => next.run(req, res),
+            None => {
+                warn!("[{}] Over the limit of {} concurrent requests.", req.id(), self.max);
+                res.set_raw_header("Retry-After", &self.retry_after.to_string());
+                let _ = res.error(StatusCode::ServiceUnavailable);
+            },
+        }
+    }
+
+    fn name(&self) -> &str {
+        "concurrency_limit"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Request
+    }
+}
+
+/// A request counted in `in_flight`, until it's dropped, also if the handler panics.
+struct Permit<'a> {
+    in_flight: &'a AtomicUsize,
+}
+
+impl<'a> Permit<'a> {
+    fn acquire(in_flight: &'a AtomicUsize, max: usize) -> Option<Permit<'a>> {
+        if in_flight.fetch_add(1, Ordering::SeqCst) >= max {
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            return None;
+        }
+        Some(Permit { in_flight: in_flight })
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[test]
+fn permits_are_limited() {
+    let in_flight = AtomicUsize::new(0);
+    let first = Permit::acquire(&in_flight, 2);
+    let second = Permit::acquire(&in_flight, 2);
+    assert!(first.is_some() && second.is_some());
+    assert!(Permit::acquire(&in_flight, 2).is_none());
+    assert_eq!(in_flight.load(Ordering::SeqCst), 2);
+    drop(first);
+    assert!(Permit::acquire(&in_flight, 2).is_some());
+    drop(second);
+    assert_eq!(in_flight.load(Ordering::SeqCst), 0);
+}
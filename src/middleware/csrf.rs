@@ -0,0 +1,134 @@
+use rand::{self, Rng};
+
+use rustc_serialize::hex::ToHex;
+
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use middleware::{Middleware, Next};
+use request::Request;
+use response::Response;
+
+/// The session key the token is kept under.
+const SESSION_KEY: &'static str = "csrf-token";
+
+/// Protects against cross-site request forgery, i.e. other sites making a user's browser
+/// send requests, with the user's cookies, that change something.
+///
+/// Each session gets a random token, available to handlers as `Request::csrf_token`, that
+/// forms and scripts send back in a form field or header. Requests with other methods than
+/// GET, HEAD, OPTIONS and TRACE without the token are failed with 403 (Forbidden). The token
+/// is kept in the session, see `session::Session`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::middleware::Csrf;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn form(req: &Request, res: Response) {
+///     res.html(format!("<form method=\"post\" action=\"/comments\">\
+///                       <input type=\"hidden\" name=\"csrf_token\" value=\"{}\">\
+///                       <textarea name=\"comment\"></textarea></form>",
+///                      req.csrf_token().unwrap_or("")));
+/// }
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(Csrf::new());
+/// app.get("/comments/new", form);
+/// ```
+pub struct Csrf {
+    field: String,
+    header: String,
+}
+
+impl Csrf {
+    /// Reads the token from the form field "csrf_token" or the header "X-CSRF-Token".
+    pub fn new() -> Csrf {
+        Csrf {
+            field: "csrf_token".to_owned(),
+            header: "X-CSRF-Token".to_owned(),
+        }
+    }
+
+    /// Reads the token from the form field `name` instead.
+    pub fn field(mut self, name: &str) -> Csrf {
+        self.field = name.to_owned();
+        self
+    }
+
+    /// Reads the token from the header `name` instead, e.g. the one a javascript framework
+    /// sends it in.
+    pub fn header(mut self, name: &str) -> Csrf {
+        self.header = name.to_owned();
+        self
+    }
+
+    /// The token sent with the request, from the header or else the form field.
+    fn sent_token(&self, req: &Request) -> Option<String> {
+        let header = req.headers()
+            .get_raw(&self.header)
+            .and_then(|values| values.first())
+            .and_then(|value| String::from_utf8(value.clone()).ok());
+        header.or_else(|| req.form().ok().and_then(|form| form.get(&self.field).cloned()))
+    }
+}
+
+impl Middleware for Csrf {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        let token = match req.session().get(SESSION_KEY) {
+            Some(token) => token.to_owned(),
+            None => {
+                let token = rand::thread_rng().gen_iter::<u8>().take(32).collect::<Vec<u8>>().to_hex();
+                res.session().set(SESSION_KEY, &token);
+                token
+            },
+        };
+
+        if !is_safe(req.method()) {
+            let valid = self.sent_token(req).map_or(false, |sent| same_token(sent.trim().as_bytes(), token.as_bytes()));
+            if !valid {
+                warn!("[{}] Missing or invalid CSRF token for {:?} {:?}.", req.id(), req.method(), req.path);
+                let _ = res.error(StatusCode::Forbidden);
+                return;
+            }
+        }
+
+        req.extensions_mut().insert(CsrfToken(token));
+        next.run(req, res);
+    }
+
+    fn name(&self) -> &str {
+        "csrf"
+    }
+}
+
+/// The token of the request's session, see `Request::csrf_token`.
+#[doc(hidden)]
+pub struct CsrfToken(pub String);
+
+/// Whether requests with `method` are only reading, which they aren't protected for.
+fn is_safe(method: &Method) -> bool {
+    match *method {
+        Method::Get | Method::Head | Method::Options | Method::Trace => true,
+        _ => false,
+    }
+}
+
+/// Compares the tokens in constant time, so the time taken doesn't tell how much of a
+/// guessed token is right.
+fn same_token(sent: &[u8], token: &[u8]) -> bool {
+    sent.len() == token.len() && sent.iter().zip(token).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[test]
+fn tokens_are_compared() {
+    assert!(same_token(b"3fa9", b"3fa9"));
+    assert!(!same_token(b"3fa8", b"3fa9"));
+    assert!(!same_token(b"3fa", b"3fa9"));
+    assert!(!same_token(b"", b"3fa9"));
+    assert!(is_safe(&Method::Head));
+    assert!(!is_safe(&Method::Delete));
+}
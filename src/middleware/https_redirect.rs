@@ -0,0 +1,115 @@
+use std::time::Duration;
+
+use hyper::header::{Location, StrictTransportSecurity};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+use hyper::uri::RequestUri;
+
+use middleware::{Middleware, Next, Phase};
+use request::Request;
+use response::Response;
+
+/// Redirects requests made over plain http to the same url with https, permanently. Whether
+/// a request is secure is decided by `Request::is_secure`, so behind a proxy terminating
+/// https the proxy must be trusted, see `Rask::trusted_proxies` and `ProxyFix`.
+///
+/// GET and HEAD requests are redirected with 301 (Moved Permanently), others with 308
+/// (Permanent Redirect) so clients repeat them with the same method and body.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use rask::Rask;
+/// use rask::middleware::{HttpsRedirect, ProxyFix};
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(ProxyFix::hops(1));
+/// app.wrap(HttpsRedirect::new().hsts(Duration::from_secs(365 * 24 * 60 * 60), true));
+/// ```
+pub struct HttpsRedirect {
+    port: Option<u16>,
+    hsts: Option<(u64, bool)>,
+}
+
+impl HttpsRedirect {
+    /// Redirects to the default https port.
+    pub fn new() -> HttpsRedirect {
+        HttpsRedirect {
+            port: None,
+            hsts: None,
+        }
+    }
+
+    /// Redirects to `port` instead of the default 443.
+    pub fn port(mut self, port: u16) -> HttpsRedirect {
+        self.port = Some(port);
+        self
+    }
+
+    /// Adds a `Strict-Transport-Security` header to responses to secure requests, which makes
+    /// browsers use https for the host, and its subdomains if `include_subdomains` is set, for
+    /// `max_age` without asking over http first.
+    pub fn hsts(mut self, max_age: Duration, include_subdomains: bool) -> HttpsRedirect {
+        self.hsts = Some((max_age.as_secs(), include_subdomains));
+        self
+    }
+}
+
+impl Middleware for HttpsRedirect {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        if req.is_secure() {
+            if let Some((max_age, include_subdomains)) = self.hsts {
+                res.set_header(StrictTransportSecurity { max_age: max_age, include_subdomains: include_subdomains });
+            }
+            return next.run(req, res);
+        }
+
+        let hostname = match req.host() {
+            Some(host) => hostname(&host).to_owned(),
+            None => {
+                let _ = res.error(StatusCode::BadRequest);
+                return;
+            },
+        };
+        let host = match self.port {
+            Some(port) if port != 443 => format!("{}:{}", hostname, port),
+            _ => hostname,
+        };
+        let target = match *req.uri() {
+            RequestUri::AbsolutePath(ref target) => target.clone(),
+            _ => req.path.clone().unwrap_or("/".to_owned()),
+        };
+        let status = match *req.method() {
+            Method::Get | Method::Head => StatusCode::MovedPermanently,
+            _ => StatusCode::PermanentRedirect,
+        };
+        debug!("[{}] Redirecting to https.", req.id());
+        res.set_header(Location(format!("https://{}{}", host, target)));
+        let _ = res.send(status);
+    }
+
+    fn name(&self) -> &str {
+        "https_redirect"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Request
+    }
+}
+
+/// The host without its port, e.g. "example.com" for "example.com:8080".
+fn hostname(host: &str) -> &str {
+    match host.rfind(':') {
+        Some(colon) if !host[colon..].contains(']') => &host[..colon],
+        _ => host,
+    }
+}
+
+#[test]
+fn hostnames_without_ports() {
+    assert_eq!(hostname("example.com:8080"), "example.com");
+    assert_eq!(hostname("example.com"), "example.com");
+    assert_eq!(hostname("[2001:db8::1]:8080"), "[2001:db8::1]");
+    assert_eq!(hostname("[2001:db8::1]"), "[2001:db8::1]");
+}
@@ -0,0 +1,262 @@
+use std::collections::{HashMap, HashSet};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use hyper::header::{Headers, SetCookie};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use middleware::{Middleware, Next};
+use request::{BodyError, Request};
+use response::{Captured, Response};
+
+/// The longest key accepted, longer ones are failed with 400 (Bad Request).
+const MAX_KEY_LENGTH: usize = 255;
+
+/// Makes POST and PUT requests with an `Idempotency-Key` header safe to retry: the first
+/// response for a key is stored, and sent again for requests with the same key within the
+/// time to live, without calling the handler. Clients pick a unique key, e.g. a UUID, for
+/// each operation, like a payment, and reuse it when they retry it.
+///
+/// A retry with another method, path or body than the first request is failed with 422
+/// (Unprocessable Entity), and one made while the first is still being handled with 409
+/// (Conflict). Responses with a server error status aren't stored, so the request can be
+/// retried, nor are streamed ones.
+///
+/// The responses are kept in memory unless another `IdempotencyStore` is given with `store`,
+/// e.g. one shared by several servers.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use rask::Rask;
+/// use rask::middleware::Idempotency;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap_under("/payments", Idempotency::new(Duration::from_secs(24 * 60 * 60)));
+/// ```
+pub struct Idempotency {
+    ttl: Duration,
+    store: Box<IdempotencyStore>,
+    in_flight: Mutex<HashSet<String>>,
+}
+
+/// A response stored by `Idempotency`, with the fingerprint of the request it answered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredResponse {
+    /// A hash of the method, path and body of the request.
+    pub fingerprint: u64,
+    pub status: StatusCode,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Where `Idempotency` keeps the responses for the keys.
+pub trait IdempotencyStore: Send + Sync {
+    /// The response stored for `key`, unless there's none or it has expired.
+    fn get(&self, key: &str) -> Option<StoredResponse>;
+
+    /// Stores `response` for `key`, for `ttl`.
+    fn put(&self, key: &str, response: StoredResponse, ttl: Duration);
+}
+
+/// Keeps the responses in memory, in this process. The default store of `Idempotency`.
+pub struct MemoryIdempotencyStore {
+    responses: Mutex<HashMap<String, (Instant, Duration, StoredResponse)>>,
+}
+
+impl MemoryIdempotencyStore {
+    pub fn new() -> MemoryIdempotencyStore {
+        MemoryIdempotencyStore { responses: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl IdempotencyStore for MemoryIdempotencyStore {
+    fn get(&self, key: &str) -> Option<StoredResponse> {
+        let responses = self.responses.lock().unwrap();
+        match responses.get(key) {
+            Some(&(stored, ttl, ref response)) if stored.elapsed() < ttl => Some(response.clone()),
+            _ => None,
+        }
+    }
+
+    fn put(&self, key: &str, response: StoredResponse, ttl: Duration) {
+        let mut responses = self.responses.lock().unwrap();
+        responses.retain(|_, &mut (stored, ttl, _)| stored.elapsed() < ttl);
+        responses.insert(key.to_owned(), (Instant::now(), ttl, response));
+    }
+}
+
+impl Idempotency {
+    /// Stores the responses in memory for `ttl`.
+    pub fn new(ttl: Duration) -> Idempotency {
+        Idempotency {
+            ttl: ttl,
+            store: Box::new(MemoryIdempotencyStore::new()),
+            in_flight: Mutex::new(HashSet::new()),
+        }
+    }
+
+    /// Stores the responses in `store` instead.
+    pub fn store<S: 'static + IdempotencyStore>(mut self, store: S) -> Idempotency {
+        self.store = Box::new(store);
+        self
+    }
+
+    fn handle_new<'a, 'b>(&self, key: &str, fingerprint: u64, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        let capture = res.capture();
+        next.run(req, res);
+
+        let captured = capture.borrow_mut().take();
+        match captured {
+            Some(captured) => {
+                if !captured.status.is_server_error() {
+                    self.store.put(key, stored(fingerprint, captured), self.ttl);
+                }
+            },
+            None => debug!("[{}] The streamed response isn't stored for the idempotency key.", req.id()),
+        }
+    }
+}
+
+impl Middleware for Idempotency {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+        if *req.method() != Method::Post && *req.method() != Method::Put {
+            return next.run(req, res);
+        }
+        let key = match req.headers().get_raw("Idempotency-Key").and_then(|values| values.first()) {
+            Some(key) => String::from_utf8_lossy(key).trim().to_owned(),
+            None => return next.run(req, res),
+        };
+        if key.is_empty() || key.len() > MAX_KEY_LENGTH {
+            let _ = res.error(StatusCode::BadRequest);
+            return;
+        }
+        let fingerprint = match req.body() {
+            Ok(body) => fingerprint(req.method(), req.path.as_ref().map_or("", |path| &path[..]), &body),
+            Err(BodyError::TooLarge) => {
+                let _ = res.error(StatusCode::PayloadTooLarge);
+                return;
+            },
+            Err(_) => {
+                let _ = res.error(StatusCode::BadRequest);
+                return;
+            },
+        };
+
+        // the key is claimed before looking it up, so a retry can't slip in between the
+        // lookup and storing the response and call the handler again
+        let _in_flight = match InFlight::start(&self.in_flight, &key) {
+            Some(in_flight) => in_flight,
+            None => {
+                warn!("[{}] A request with the idempotency key {:?} is being handled.", req.id(), key);
+                let _ = res.error(StatusCode::Conflict);
+                return;
+            },
+        };
+        match self.store.get(&key) {
+            Some(ref stored) if stored.fingerprint != fingerprint => {
+                warn!("[{}] The idempotency key {:?} was used for another request.", req.id(), key);
+                let _ = res.error(StatusCode::UnprocessableEntity);
+            },
+            Some(stored) => {
+                debug!("[{}] Sending the stored response for the idempotency key {:?}.", req.id(), key);
+                replay(res, stored);
+            },
+            None => self.handle_new(&key, fingerprint, req, res, next),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "idempotency"
+    }
+}
+
+/// A key whose request is being handled, until it's dropped, also if the handler panics.
+struct InFlight<'s> {
+    keys: &'s Mutex<HashSet<String>>,
+    key: String,
+}
+
+impl<'s> InFlight<'s> {
+    fn start(keys: &'s Mutex<HashSet<String>>, key: &str) -> Option<InFlight<'s>> {
+        if !keys.lock().unwrap().insert(key.to_owned()) {
+            return None;
+        }
+        Some(InFlight { keys: keys, key: key.to_owned() })
+    }
+}
+
+impl<'s> Drop for InFlight<'s> {
+    fn drop(&mut self) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.remove(&self.key);
+        }
+    }
+}
+
+fn fingerprint(method: &Method, path: &str, body: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    hasher.write(method.as_ref().as_bytes());
+    hasher.write(path.as_bytes());
+    hasher.write(body);
+    hasher.finish()
+}
+
+/// The response to store, without the headers that are only true for this response.
+fn stored(fingerprint: u64, captured: Captured) -> StoredResponse {
+    StoredResponse {
+        fingerprint: fingerprint,
+        status: captured.status,
+        headers: replayed_headers(&captured.headers),
+        body: captured.body,
+    }
+}
+
+fn replayed_headers(headers: &Headers) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter(|header| {
+            !header.is::<SetCookie>() && !header.name().eq_ignore_ascii_case("Date") &&
+                !header.name().eq_ignore_ascii_case("Content-Length")
+        })
+        .map(|header| (header.name().to_owned(), header.value_string()))
+        .collect()
+}
+
+fn replay(mut res: Response, stored: StoredResponse) {
+    for &(ref name, ref value) in &stored.headers {
+        res.set_raw_header(name, value);
+    }
+    res.set_raw_header("Idempotent-Replayed", "true");
+    let _ = res.send((stored.body, stored.status));
+}
+
+#[test]
+fn responses_are_stored_by_key() {
+    let mut headers = Headers::new();
+    headers.set_raw("Location", vec![b"/payments/1".to_vec()]);
+    headers.set_raw("Date", vec![b"Tue, 15 Nov 1994 08:12:31 GMT".to_vec()]);
+    let captured = Captured { status: StatusCode::Created, headers: headers, body: b"paid".to_vec() };
+    let paid = fingerprint(&Method::Post, "/payments", b"amount=10");
+    assert!(paid != fingerprint(&Method::Post, "/payments", b"amount=100"));
+    let response = stored(paid, captured);
+    assert_eq!(response.headers, vec![("Location".to_owned(), "/payments/1".to_owned())]);
+
+    let store = MemoryIdempotencyStore::new();
+    store.put("a", response.clone(), Duration::from_secs(60));
+    store.put("b", response.clone(), Duration::from_secs(0));
+    assert_eq!(store.get("a"), Some(response));
+    assert_eq!(store.get("b"), None);
+    assert_eq!(store.get("c"), None);
+
+    let keys = Mutex::new(HashSet::new());
+    let first = InFlight::start(&keys, "a");
+    assert!(first.is_some());
+    assert!(InFlight::start(&keys, "a").is_none());
+    drop(first);
+    assert!(InFlight::start(&keys, "a").is_some());
+}
@@ -0,0 +1,295 @@
+//! Middleware, code that runs around the handlers of all requests, see `Rask::wrap`.
+
+use std::marker::PhantomData;
+
+use request::Request;
+use response::{Response, Sendable};
+use routing::is_below;
+use Rask;
+
+pub use self::access_log::AccessLog;
+pub use self::basic_auth::BasicAuth;
+pub use self::concurrency_limit::ConcurrencyLimit;
+pub use self::csrf::{Csrf, CsrfToken};
+pub use self::https_redirect::HttpsRedirect;
+pub use self::idempotency::{Idempotency, IdempotencyStore, MemoryIdempotencyStore, StoredResponse};
+pub use self::proxy_fix::ProxyFix;
+pub use self::response_cache::ResponseCache;
+pub use self::response_time::{ResponseTime, ResponseTimer};
+
+mod access_log;
+mod basic_auth;
+mod concurrency_limit;
+mod csrf;
+mod https_redirect;
+mod idempotency;
+mod proxy_fix;
+mod response_cache;
+mod response_time;
+
+/// Code that runs for every request before it's routed, e.g. to check or change it, answer
+/// it without reaching a handler, or do something after the handler has sent the response.
+/// Middleware runs by its `phase`, and in the order it's added with `Rask::wrap` within a
+/// phase, see `Rask::middleware`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::{Rask, StatusCode};
+/// use rask::middleware::{Middleware, Next};
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// struct Maintenance;
+///
+/// impl Middleware for Maintenance {
+///     fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+///         if req.path.as_ref().map_or(false, |path| path.starts_with("/admin")) {
+///             next.run(req, res);
+///         } else {
+///             let _ = res.send(("Back soon!", StatusCode::ServiceUnavailable));
+///         }
+///     }
+/// }
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(Maintenance);
+/// ```
+pub trait Middleware: Send + Sync {
+    /// Handles the request, usually by calling `next.run` to pass it on to the next
+    /// middleware and finally its handler. `Response::error` uses the application's error
+    /// handlers, as it does in handlers.
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>);
+
+    /// The name to refer to the middleware by, e.g. with `Rask::wrap_before`. Empty by
+    /// default.
+    fn name(&self) -> &str {
+        ""
+    }
+
+    /// When the middleware runs, relative to other middleware. `Phase::Default` by default.
+    fn phase(&self) -> Phase {
+        Phase::Default
+    }
+}
+
+/// When middleware runs, relative to other middleware. Middleware in an earlier phase runs
+/// before, and so around, the middleware in later phases: it sees the request first and the
+/// response last.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Phase {
+    /// Sees every request and response as they are at the server, e.g. to log and time
+    /// them: `AccessLog` and `ResponseTimer`.
+    Outer,
+    /// Makes the request what the rest of the application sees, or turns it away early, e.g.
+    /// `ProxyFix`, `HttpsRedirect` and `ConcurrencyLimit`.
+    Request,
+    /// Checks the request, e.g. `BasicAuth`, `Csrf` and `Rask::before_request` guards.
+    Default,
+    /// Runs right around routing, and so sees the response of the handler first, e.g. to
+    /// change it before the other middleware sees it.
+    Response,
+}
+
+impl<F> Middleware for F
+    where F: for<'a, 'b> Fn(&mut Request<'a, 'b>, Response<'a>, Next<'a>) + Sync + Send
+{
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+        (*self)(req, res, next);
+    }
+}
+
+/// The middleware of an application, ordered by phase and then as it's added.
+#[doc(hidden)]
+pub struct Chain {
+    entries: Vec<Entry>,
+}
+
+#[doc(hidden)]
+pub struct Entry {
+    name: String,
+    phase: Phase,
+    prefix: Option<String>,
+    middleware: Box<Middleware>,
+}
+
+impl Entry {
+    /// Whether the middleware runs for requests to `path`.
+    fn applies_to(&self, path: Option<&str>) -> bool {
+        match self.prefix {
+            Some(ref prefix) => path.map_or(false, |path| is_below(path, prefix)),
+            None => true,
+        }
+    }
+}
+
+impl Chain {
+    pub fn new() -> Chain {
+        Chain { entries: Vec::new() }
+    }
+
+    /// Adds `middleware` after the middleware of its phase.
+    pub fn push(&mut self, name: &str, phase: Phase, middleware: Box<Middleware>) {
+        self.push_under(None, name, phase, middleware);
+    }
+
+    /// Adds `middleware` after the middleware of its phase, only running for requests to
+    /// `prefix` and paths below it if given.
+    pub fn push_under(&mut self, prefix: Option<&str>, name: &str, phase: Phase, middleware: Box<Middleware>) {
+        let index = self.entries.iter().position(|entry| entry.phase > phase).unwrap_or(self.entries.len());
+        self.insert(index, prefix, name, phase, middleware);
+    }
+
+    /// Adds `middleware` right before the first middleware named `existing`, in its phase.
+    /// Returns `false` if there's none.
+    pub fn push_before(&mut self, existing: &str, name: &str, middleware: Box<Middleware>) -> bool {
+        match self.position(existing) {
+            Some(index) => {
+                let phase = self.entries[index].phase;
+                self.insert(index, None, name, phase, middleware);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Adds `middleware` right after the first middleware named `existing`, in its phase.
+    /// Returns `false` if there's none.
+    pub fn push_after(&mut self, existing: &str, name: &str, middleware: Box<Middleware>) -> bool {
+        match self.position(existing) {
+            Some(index) => {
+                let phase = self.entries[index].phase;
+                self.insert(index + 1, None, name, phase, middleware);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// The names of the middleware, in the order it runs.
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|entry| &entry.name[..]).collect()
+    }
+
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    fn position(&self, name: &str) -> Option<usize> {
+        self.entries.iter().position(|entry| entry.name == name)
+    }
+
+    fn insert(&mut self, index: usize, prefix: Option<&str>, name: &str, phase: Phase, middleware: Box<Middleware>) {
+        self.entries.insert(index, Entry {
+            name: name.to_owned(),
+            phase: phase,
+            prefix: prefix.map(|prefix| prefix.to_owned()),
+            middleware: middleware,
+        });
+    }
+}
+
+/// The rest of the middleware after the one it's passed to, followed by routing the request
+/// to its handler.
+pub struct Next<'a> {
+    app: &'a Rask,
+    middleware: &'a [Entry],
+}
+
+impl<'a> Next<'a> {
+    #[doc(hidden)]
+    pub fn new(app: &'a Rask, middleware: &'a [Entry]) -> Next<'a> {
+        Next {
+            app: app,
+            middleware: middleware,
+        }
+    }
+
+    /// Passes the request on to the next middleware, or routes it to its handler after the
+    /// last one.
+    pub fn run<'b>(self, req: &mut Request<'a, 'b>, mut res: Response<'a>) {
+        let (entry, rest) = match self.middleware.split_first() {
+            Some(first) => first,
+            None => return self.app.route_request(req, res),
+        };
+        if !entry.applies_to(req.path.as_ref().map(|path| &path[..])) {
+            return Next::new(self.app, rest).run(req, res);
+        }
+        let handoff = res.handoff();
+        entry.middleware.handle(req, res, Next::new(self.app, rest));
+        // handed off before the middleware returns, so middleware before it sees the error
+        let handed_off = handoff.borrow_mut().take();
+        if let Some((status, res)) = handed_off {
+            let path = req.path.clone();
+            match self.app.find_error_handler(path.as_ref().map(|path| &path[..]), &status) {
+                Some(error_handler) => error_handler.handle(req, res),
+                None => {
+                    let _ = res.send(status);
+                },
+            }
+        }
+    }
+}
+
+/// Middleware that answers a request with the response of `guard`, if it returns one, see
+/// `Rask::before_request`.
+#[doc(hidden)]
+pub struct Guard<F, S> {
+    guard: F,
+    _response: PhantomData<fn() -> S>,
+}
+
+impl<F, S> Guard<F, S> {
+    pub fn new(guard: F) -> Guard<F, S> {
+        Guard {
+            guard: guard,
+            _response: PhantomData,
+        }
+    }
+}
+
+impl<F, S> Middleware for Guard<F, S>
+    where F: Fn(&Request) -> Option<S> + Send + Sync, S: 'static + Sendable<'static>
+{
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+        match (self.guard)(req) {
+            Some(response) => {
+                debug!("[{}] Answered before routing.", req.id());
+                let _ = res.send(response);
+            },
+            None => next.run(req, res),
+        }
+    }
+
+    fn name(&self) -> &str {
+        "before_request"
+    }
+}
+
+#[test]
+fn middleware_order() {
+    fn pass<'a, 'b>(req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+        next.run(req, res);
+    }
+
+    let mut chain = Chain::new();
+    chain.push("csrf", Phase::Default, Box::new(pass));
+    chain.push("log", Phase::Outer, Box::new(pass));
+    chain.push("compress", Phase::Response, Box::new(pass));
+    chain.push("auth", Phase::Default, Box::new(pass));
+    chain.push("proxy", Phase::Request, Box::new(pass));
+    assert_eq!(chain.names(), vec!["log", "proxy", "csrf", "auth", "compress"]);
+
+    assert!(chain.push_before("csrf", "session", Box::new(pass)));
+    assert!(chain.push_after("log", "timer", Box::new(pass)));
+    assert!(!chain.push_after("missing", "never", Box::new(pass)));
+    chain.push("metrics", Phase::Outer, Box::new(pass));
+    assert_eq!(chain.names(), vec!["log", "timer", "metrics", "proxy", "session", "csrf", "auth", "compress"]);
+
+    chain.push_under(Some("/api/"), "token", Phase::Default, Box::new(pass));
+    let token = chain.entries().iter().find(|entry| entry.name == "token").unwrap();
+    assert!(token.applies_to(Some("/api/users")));
+    assert!(token.applies_to(Some("/api")));
+    assert!(!token.applies_to(Some("/static/app.js")));
+    assert!(!token.applies_to(None));
+}
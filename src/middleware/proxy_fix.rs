@@ -0,0 +1,193 @@
+use std::net::IpAddr;
+
+use hyper::header::Host;
+
+use middleware::{Middleware, Next, Phase};
+use request::{Request, forwarded_values, parse_ip};
+use response::Response;
+
+/// Makes requests that come through reverse proxies, e.g. nginx, look like they came
+/// straight from the client: the scheme, host and client address the proxies give in the
+/// `Forwarded` or `X-Forwarded-Proto`, `X-Forwarded-Host` and `X-Forwarded-For` headers
+/// become the ones of `Request::scheme`, `Request::host` and `Request::client_ip`, so e.g.
+/// `Request::is_secure` and `Request::absolute_url_for` work behind a proxy terminating
+/// https. The `Host` header is replaced before the request is routed.
+///
+/// The headers can be forged by clients, so only the values added by trusted proxies are
+/// used: by a number of proxies in front of the application, or by proxies in trusted
+/// networks.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::middleware::ProxyFix;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(ProxyFix::networks(&["10.0.0.0/8", "127.0.0.1"]));
+/// ```
+pub struct ProxyFix {
+    trusted: Trusted,
+}
+
+enum Trusted {
+    Hops(usize),
+    /// Addresses with the number of leading bits that must match.
+    Networks(Vec<(IpAddr, u32)>),
+}
+
+impl ProxyFix {
+    /// Trusts the `hops` proxies nearest to the application, whatever their addresses, e.g.
+    /// 1 for a single nginx. Requests must not be able to reach the application without going
+    /// through them.
+    pub fn hops(hops: usize) -> ProxyFix {
+        ProxyFix { trusted: Trusted::Hops(hops) }
+    }
+
+    /// Trusts proxies with addresses in `networks`, like "10.0.0.0/8", "fd00::/8" or a single
+    /// address like "127.0.0.1".
+    ///
+    /// # Panics
+    ///
+    /// Panics if a network can't be parsed.
+    pub fn networks(networks: &[&str]) -> ProxyFix {
+        let networks = networks
+            .iter()
+            .map(|network| parse_network(network).unwrap_or_else(|| panic!("Invalid network {:?}.", network)))
+            .collect();
+        ProxyFix { trusted: Trusted::Networks(networks) }
+    }
+
+    /// The number of trusted proxies the request went through, nearest first, given the
+    /// address of the peer and the client addresses the proxies forwarded for.
+    fn trusted_hops(&self, remote: IpAddr, forwarded_for: &[String]) -> usize {
+        let networks = match self.trusted {
+            Trusted::Hops(hops) => return hops,
+            Trusted::Networks(ref networks) => networks,
+        };
+        let trusted = |ip: IpAddr| networks.iter().any(|&(network, bits)| in_network(ip, network, bits));
+        if !trusted(remote) {
+            return 0;
+        }
+        let mut hops = 1;
+        // the first address is the client's, even if it's in a trusted network
+        for address in forwarded_for.iter().rev().take(forwarded_for.len().saturating_sub(1)) {
+            match parse_ip(address) {
+                Some(ip) if trusted(ip) => hops += 1,
+                _ => break,
+            }
+        }
+        hops
+    }
+}
+
+impl Middleware for ProxyFix {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, res: Response<'a>, next: Next<'a>) {
+        let forwarded_for = forwarded_values(req.headers(), "for", "X-Forwarded-For");
+        let hops = self.trusted_hops(req.remote_addr().ip(), &forwarded_for);
+        if hops > 0 {
+            let client = added_by(&forwarded_for, hops).and_then(|address| parse_ip(address));
+            let proto = forwarded_values(req.headers(), "proto", "X-Forwarded-Proto");
+            let scheme = added_by(&proto, hops).map(|proto| {
+                if proto.eq_ignore_ascii_case("https") { "https" } else { "http" }
+            });
+            let host = forwarded_values(req.headers(), "host", "X-Forwarded-Host");
+            let host = added_by(&host, hops).and_then(|host| parse_host(host));
+            debug!("[{}] Forwarded by {} proxies: {:?}, {:?}, {:?}.", req.id(), hops, scheme, host, client);
+            req.set_forwarded(scheme, host, client);
+        }
+        next.run(req, res);
+    }
+
+    fn name(&self) -> &str {
+        "proxy_fix"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Request
+    }
+}
+
+/// The value added by the farthest of the `hops` trusted proxies, or `None` if there are
+/// fewer values, as the rest may be forged.
+fn added_by(values: &[String], hops: usize) -> Option<&String> {
+    if hops == 0 || values.len() < hops {
+        return None;
+    }
+    Some(&values[values.len() - hops]).filter(|value| !value.is_empty())
+}
+
+/// Parses a network like "10.0.0.0/8" or a single address.
+fn parse_network(network: &str) -> Option<(IpAddr, u32)> {
+    let mut split = network.splitn(2, '/');
+    let ip: IpAddr = match split.next().and_then(|ip| ip.trim().parse().ok()) {
+        Some(ip) => ip,
+        None => return None,
+    };
+    let max = if ip.is_ipv4() { 32 } else { 128 };
+    match split.next() {
+        Some(bits) => bits.trim().parse().ok().filter(|&bits| bits <= max).map(|bits| (ip, bits)),
+        None => Some((ip, max)),
+    }
+}
+
+/// Whether the first `bits` bits of `ip` and `network` are the same.
+fn in_network(ip: IpAddr, network: IpAddr, bits: u32) -> bool {
+    let (ip, network) = match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) => (ip.octets().to_vec(), network.octets().to_vec()),
+        (IpAddr::V6(ip), IpAddr::V6(network)) => (ip.octets().to_vec(), network.octets().to_vec()),
+        _ => return false,
+    };
+    ip.iter().zip(&network).enumerate().all(|(i, (a, b))| {
+        let bits = bits.saturating_sub(i as u32 * 8).min(8);
+        let mask = if bits == 0 { 0 } else { 0xffu8 << (8 - bits) };
+        a & mask == b & mask
+    })
+}
+
+/// Parses a host like "example.com", "example.com:8080" or "[2001:db8::1]:8080".
+fn parse_host(host: &str) -> Option<Host> {
+    let host = host.trim();
+    if host.is_empty() {
+        return None;
+    }
+    let mut split = host.rsplitn(2, ':');
+    let (last, rest) = (split.next(), split.next());
+    match (last.and_then(|port| port.parse().ok()), rest) {
+        (Some(port), Some(hostname)) => Some(Host { hostname: hostname.to_owned(), port: Some(port) }),
+        _ => Some(Host { hostname: host.to_owned(), port: None }),
+    }
+}
+
+#[test]
+fn trusted_networks() {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    let (network, bits) = parse_network("10.0.0.0/8").unwrap();
+    assert!(in_network(ip("10.20.30.40"), network, bits));
+    assert!(!in_network(ip("11.0.0.1"), network, bits));
+    let (network, bits) = parse_network("fd00::/7").unwrap();
+    assert!(in_network(ip("fdab::1"), network, bits));
+    assert!(!in_network(ip("fe80::1"), network, bits));
+    assert!(!in_network(ip("10.0.0.1"), network, bits));
+    assert_eq!(parse_network("127.0.0.1"), Some((ip("127.0.0.1"), 32)));
+    assert_eq!(parse_network("10.0.0.0/33"), None);
+}
+
+#[test]
+fn forwarded_values_of_trusted_hops() {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    let list = |s: &str| s.split(", ").map(|s| s.to_owned()).collect::<Vec<_>>();
+    let proxies = ProxyFix::networks(&["10.0.0.0/8"]);
+
+    assert_eq!(proxies.trusted_hops(ip("203.0.113.7"), &list("198.51.100.2")), 0);
+    assert_eq!(proxies.trusted_hops(ip("10.0.0.1"), &list("198.51.100.2, 10.0.0.2")), 2);
+    assert_eq!(proxies.trusted_hops(ip("10.0.0.1"), &list("10.0.0.3, 10.0.0.2")), 2);
+    assert_eq!(ProxyFix::hops(1).trusted_hops(ip("203.0.113.7"), &[]), 1);
+
+    let forged = list("192.0.2.1, 198.51.100.2");
+    assert_eq!(added_by(&forged, 1).map(|s| &s[..]), Some("198.51.100.2"));
+    assert_eq!(added_by(&forged, 3), None);
+    assert_eq!(parse_host("example.com:8080").map(|host| (host.hostname, host.port)),
+               Some(("example.com".to_owned(), Some(8080))));
+    assert_eq!(parse_host("[2001:db8::1]").map(|host| host.port), Some(None));
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use hyper::header::{CacheControl, CacheDirective, Headers, SetCookie};
+use hyper::method::Method;
+use hyper::status::StatusCode;
+
+use middleware::{Middleware, Next};
+use request::Request;
+use response::{Captured, Response};
+use routing::is_below;
+
+/// Keeps whole responses to GET requests in memory for a while and sends them again for the
+/// same path and query, without calling the handler.
+///
+/// Only 200 (OK) responses sent whole are kept, and not the ones that set cookies, read the
+/// cookies or session, vary on every header or have a `Cache-Control` of `no-store` or
+/// `private`. Responses that depend on other request headers must have them added with
+/// `vary_on`, so they're part of the key.
+///
+/// The cache is shared by its clones, so handlers that change data can drop the responses
+/// showing it with `invalidate` or `invalidate_below`.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use rask::Rask;
+/// use rask::middleware::ResponseCache;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// let cache = ResponseCache::new(Duration::from_secs(60)).under("/articles");
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(cache.clone());
+/// app.get("/articles", |_: &Request, res: Response| {
+///     res.send("All the articles");
+/// });
+/// app.post("/articles", move |_: &Request, res: Response| {
+///     cache.invalidate_below("/articles");
+///     res.send("Saved");
+/// });
+/// ```
+#[derive(Clone)]
+pub struct ResponseCache {
+    ttl: Duration,
+    prefixes: Vec<String>,
+    headers: Vec<String>,
+    entries: Arc<Mutex<HashMap<Key, Entry>>>,
+}
+
+/// The path and query of a request, with the values of the headers the cache varies on.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Key {
+    target: String,
+    headers: Vec<Option<Vec<u8>>>,
+}
+
+struct Entry {
+    stored: Instant,
+    headers: Headers,
+    body: Vec<u8>,
+}
+
+impl ResponseCache {
+    /// Keeps responses for `ttl`.
+    pub fn new(ttl: Duration) -> ResponseCache {
+        ResponseCache {
+            ttl: ttl,
+            prefixes: Vec::new(),
+            headers: Vec::new(),
+            entries: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Only keeps responses for requests to `prefix` and paths below it. Can be called
+    /// several times. Without it responses to all GET requests are kept.
+    pub fn under(mut self, prefix: &str) -> ResponseCache {
+        self.prefixes.push(prefix.to_owned());
+        self
+    }
+
+    /// Keeps a response for each value of the request header `name`, e.g. "Accept-Language"
+    /// for responses in the client's language.
+    pub fn vary_on(mut self, name: &str) -> ResponseCache {
+        self.headers.push(name.to_owned());
+        self
+    }
+
+    /// Drops the responses for `target`, a path with its query if it has one, e.g.
+    /// "/articles?page=2".
+    pub fn invalidate(&self, target: &str) {
+        self.entries.lock().unwrap().retain(|key, _| key.target != target);
+    }
+
+    /// Drops the responses for `prefix` and the paths below it, whatever their query.
+    pub fn invalidate_below(&self, prefix: &str) {
+        self.entries.lock().unwrap().retain(|key, _| !is_below(path(&key.target), prefix));
+    }
+
+    /// Drops all responses.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    fn caches(&self, path: &str) -> bool {
+        self.prefixes.is_empty() || self.prefixes.iter().any(|prefix| is_below(path, prefix))
+    }
+
+    fn key(&self, target: String, headers: &Headers) -> Key {
+        Key {
+            target: target,
+            headers: self.headers
+                .iter()
+                .map(|name| headers.get_raw(name).map(|values| values.join(&b", "[..])))
+                .collect(),
+        }
+    }
+
+    /// The response for `key` with its age, unless it's expired.
+    fn lookup(&self, key: &Key) -> Option<(Headers, Vec<u8>, Duration)> {
+        let mut entries = self.entries.lock().unwrap();
+        let age = match entries.get(key) {
+            Some(entry) if entry.stored.elapsed() < self.ttl => entry.stored.elapsed(),
+            Some(_) => {
+                entries.remove(key);
+                return None;
+            },
+            None => return None,
+        };
+        entries.get(key).map(|entry| (entry.headers.clone(), entry.body.clone(), age))
+    }
+
+    fn store(&self, key: Key, captured: Captured) {
+        if !cacheable(&captured) {
+            return;
+        }
+        let ttl = self.ttl;
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, entry| entry.stored.elapsed() < ttl);
+        entries.insert(key, Entry {
+            stored: Instant::now(),
+            headers: captured.headers,
+            body: captured.body,
+        });
+    }
+}
+
+impl Middleware for ResponseCache {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        let target = match req.path {
+            Some(ref path) if *req.method() == Method::Get && self.caches(path) => {
+                match req.query_string() {
+                    Some(query) => format!("{}?{}", path, query),
+                    None => path.clone(),
+                }
+            },
+            _ => return next.run(req, res),
+        };
+        let key = self.key(target, req.headers());
+
+        if let Some((headers, body, age)) = self.lookup(&key) {
+            debug!("[{}] Sending the cached response for {:?}.", req.id(), key.target);
+            for header in headers.iter() {
+                if !header.is::<SetCookie>() && !header.name().eq_ignore_ascii_case("Date") &&
+                    !header.name().eq_ignore_ascii_case("Content-Length")
+                {
+                    res.set_raw_header(header.name(), &header.value_string());
+                }
+            }
+            res.set_raw_header("Age", &age.as_secs().to_string());
+            let _ = res.send((body, StatusCode::Ok));
+            return;
+        }
+
+        let capture = res.capture();
+        next.run(req, res);
+        let captured = capture.borrow_mut().take();
+        if let Some(captured) = captured {
+            self.store(key, captured);
+        }
+    }
+
+    fn name(&self) -> &str {
+        "response_cache"
+    }
+}
+
+/// Whether a response can be sent again to other requests for the same key.
+fn cacheable(captured: &Captured) -> bool {
+    let headers = &captured.headers;
+    let sets_cookies = headers.get::<SetCookie>().map_or(false, |&SetCookie(ref cookies)| !cookies.is_empty());
+    let varies = headers.get_raw("Vary").map_or(false, |values| values.iter().any(|value| {
+        String::from_utf8_lossy(value)
+            .split(',')
+            .map(|name| name.trim())
+            .any(|name| name == "*" || name.eq_ignore_ascii_case("Cookie"))
+    }));
+    let private = headers.get::<CacheControl>().map_or(false, |&CacheControl(ref directives)| {
+        directives.iter().any(|directive| *directive == CacheDirective::NoStore || *directive == CacheDirective::Private)
+    });
+    captured.status == StatusCode::Ok && !sets_cookies && !varies && !private
+}
+
+/// The path of a target, without the query.
+fn path(target: &str) -> &str {
+    target.split('?').next().unwrap_or(target)
+}
+
+#[test]
+fn responses_are_kept_and_invalidated() {
+    let captured = |status, headers| Captured { status: status, headers: headers, body: b"articles".to_vec() };
+    let cache = ResponseCache::new(Duration::from_secs(60)).under("/articles").vary_on("Accept-Language");
+    assert!(cache.caches("/articles/1"));
+    assert!(!cache.caches("/about"));
+
+    let mut request_headers = Headers::new();
+    request_headers.set_raw("Accept-Language", vec![b"nb".to_vec()]);
+    let key = |target: &str| cache.key(target.to_owned(), &request_headers);
+    cache.store(key("/articles?page=2"), captured(StatusCode::Ok, Headers::new()));
+    cache.store(key("/articles/1"), captured(StatusCode::Ok, Headers::new()));
+    cache.store(key("/articles/2"), captured(StatusCode::NotFound, Headers::new()));
+    assert_eq!(cache.lookup(&key("/articles?page=2")).map(|(_, body, _)| body), Some(b"articles".to_vec()));
+    assert!(cache.lookup(&cache.key("/articles?page=2".to_owned(), &Headers::new())).is_none());
+    assert!(cache.lookup(&key("/articles/2")).is_none());
+
+    cache.invalidate("/articles/1");
+    assert!(cache.lookup(&key("/articles/1")).is_none());
+    assert!(cache.lookup(&key("/articles?page=2")).is_some());
+    cache.clone().invalidate_below("/articles");
+    assert!(cache.lookup(&key("/articles?page=2")).is_none());
+
+    let mut private = Headers::new();
+    private.set(CacheControl(vec![CacheDirective::Private]));
+    assert!(!cacheable(&captured(StatusCode::Ok, private)));
+    let mut per_user = Headers::new();
+    per_user.set_raw("Vary", vec![b"Accept-Encoding, Cookie".to_vec()]);
+    assert!(!cacheable(&captured(StatusCode::Ok, per_user)));
+}
@@ -0,0 +1,76 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use middleware::{Middleware, Next, Phase};
+use request::Request;
+use response::Response;
+
+/// Sets the `X-Response-Time` header of every response to the time it took to handle the
+/// request, up to sending the response, e.g. "12.345ms".
+///
+/// The measurement is also stored as a `ResponseTime` in the request's extensions for
+/// middleware added before this one, e.g. for metrics, and `AccessLog` logs it when it's
+/// added before it.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::middleware::{AccessLog, ResponseTimer};
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.wrap(AccessLog::common());
+/// app.wrap(ResponseTimer::new());
+/// ```
+pub struct ResponseTimer;
+
+impl ResponseTimer {
+    /// Creates the middleware.
+    pub fn new() -> ResponseTimer {
+        ResponseTimer
+    }
+}
+
+/// The time it took to handle a request, measured by `ResponseTimer`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseTime(pub Duration);
+
+impl Middleware for ResponseTimer {
+    fn handle<'a, 'b>(&self, req: &mut Request<'a, 'b>, mut res: Response<'a>, next: Next<'a>) {
+        let started = Instant::now();
+        let measured = Rc::new(Cell::new(None));
+        let slot = measured.clone();
+        res.before_send(move |res| {
+            let elapsed = started.elapsed();
+            slot.set(Some(elapsed));
+            res.set_raw_header("X-Response-Time", &milliseconds(elapsed));
+        });
+        next.run(req, res);
+
+        if let Some(elapsed) = measured.get() {
+            debug!("[{}] Handled in {}.", req.id(), milliseconds(elapsed));
+            req.extensions_mut().insert(ResponseTime(elapsed));
+        }
+    }
+
+    fn name(&self) -> &str {
+        "response_timer"
+    }
+
+    fn phase(&self) -> Phase {
+        Phase::Outer
+    }
+}
+
+/// `duration` in milliseconds with three decimals.
+fn milliseconds(duration: Duration) -> String {
+    let micros = duration.as_secs() * 1000000 + duration.subsec_nanos() as u64 / 1000;
+    format!("{}.{:03}ms", micros / 1000, micros % 1000)
+}
+
+#[test]
+fn durations_in_milliseconds() {
+    assert_eq!(milliseconds(Duration::new(0, 12345678)), "12.345ms");
+    assert_eq!(milliseconds(Duration::new(2, 5000)), "2000.005ms");
+}
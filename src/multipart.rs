@@ -0,0 +1,443 @@
+use std::cell::{Cell, RefCell};
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::rc::Rc;
+use std::str;
+use std::sync::atomic::{AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+
+use hyper::mime::Mime;
+
+use request::BodyError;
+
+/// Headers of a part longer than this are considered malformed.
+const MAX_HEADERS_SIZE: usize = 16 * 1024;
+
+static TEMP_FILE_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
+/// A part of a `multipart/form-data` body, i.e. a form field or an uploaded file. See
+/// `Request::files`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Part {
+    /// The name of the form field.
+    pub name: String,
+    /// The name of the uploaded file, `None` for other form fields.
+    pub filename: Option<String>,
+    /// The content type of the part, if given.
+    pub content_type: Option<Mime>,
+    /// The contents of the part, empty if they were written to `file`.
+    pub data: Vec<u8>,
+    /// The temporary file holding the contents of an uploaded file larger than
+    /// `Rask::spool_uploads_above`.
+    pub file: Option<SavedFile>,
+}
+
+impl Part {
+    /// A reader over the contents of the part, whether they're in memory or in a temporary
+    /// file.
+    pub fn reader<'p>(&'p self) -> io::Result<Box<Read + 'p>> {
+        match self.file {
+            Some(ref file) => Ok(Box::new(try!(file.open()))),
+            None => Ok(Box::new(Cursor::new(&self.data[..]))),
+        }
+    }
+
+    /// Saves the contents of the part to `path`. A temporary file is moved there instead of
+    /// copied when possible.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        match self.file {
+            Some(ref file) => file.persist(path),
+            None => File::create(path).and_then(|mut f| f.write_all(&self.data)),
+        }
+    }
+}
+
+/// An uploaded file written to a temporary file. The temporary file is removed when the last
+/// clone of the handle is dropped, unless it's been persisted.
+#[derive(Debug, Clone)]
+pub struct SavedFile {
+    inner: Rc<TempPath>,
+}
+
+#[derive(Debug)]
+struct TempPath {
+    /// The temporary file, or where it's been persisted to.
+    path: RefCell<PathBuf>,
+    persisted: Cell<bool>,
+}
+
+impl Drop for TempPath {
+    fn drop(&mut self) {
+        if !self.persisted.get() {
+            let _ = fs::remove_file(&*self.path.borrow());
+        }
+    }
+}
+
+impl PartialEq for SavedFile {
+    fn eq(&self, other: &SavedFile) -> bool {
+        self.inner.path == other.inner.path
+    }
+}
+
+impl SavedFile {
+    fn create() -> io::Result<(SavedFile, File)> {
+        loop {
+            let name = format!("rask-upload-{}-{}", process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst));
+            let path = env::temp_dir().join(name);
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    let inner = TempPath { path: RefCell::new(path), persisted: Cell::new(false) };
+                    let saved = SavedFile { inner: Rc::new(inner) };
+                    return Ok((saved, file));
+                },
+                Err(ref err) if err.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// The path of the temporary file, or of where it's been persisted to.
+    pub fn path(&self) -> PathBuf {
+        self.inner.path.borrow().clone()
+    }
+
+    /// Opens the file for reading.
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&*self.inner.path.borrow())
+    }
+
+    /// Moves the file to `path`, so it's kept after the request. Falls back to copying when
+    /// `path` is on another filesystem. Once persisted, the file is copied from where it was
+    /// persisted to.
+    pub fn persist<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let current = self.path();
+        if self.inner.persisted.get() {
+            return fs::copy(&current, path).map(|_| ());
+        }
+        if fs::rename(&current, path).is_err() {
+            try!(fs::copy(&current, path));
+            let _ = fs::remove_file(&current);
+        }
+        *self.inner.path.borrow_mut() = path.to_path_buf();
+        self.inner.persisted.set(true);
+        Ok(())
+    }
+}
+
+/// The error returned when a `multipart/form-data` body can't be parsed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MultipartError {
+    /// The content type doesn't give a boundary.
+    MissingBoundary,
+    /// The body doesn't follow the multipart format.
+    Malformed,
+    /// A part doesn't have a `Content-Disposition` header with a name.
+    MissingName,
+}
+
+impl fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for MultipartError {
+    fn description(&self) -> &str {
+        match *self {
+            MultipartError::MissingBoundary => "missing multipart boundary",
+            MultipartError::Malformed => "malformed multipart body",
+            MultipartError::MissingName => "multipart part without a name",
+        }
+    }
+}
+
+/// Parses a `multipart/form-data` body whose parts are separated by `boundary`.
+pub fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>, MultipartError> {
+    match read(&mut &body[..], boundary, None) {
+        Ok(parts) => Ok(parts),
+        Err(BodyError::Multipart(err)) => Err(err),
+        Err(_) => Err(MultipartError::Malformed),
+    }
+}
+
+/// Parses a `multipart/form-data` body from `reader` without buffering all of it. The
+/// contents of files larger than `spool_above` bytes are written to temporary files.
+#[doc(hidden)]
+pub fn read<R: Read>(reader: &mut R, boundary: &str, spool_above: Option<u64>) -> Result<Vec<Part>, BodyError> {
+    let mut buffer = Buffer { reader: reader, data: Vec::new() };
+
+    let delimiter = format!("--{}", boundary).into_bytes();
+    try!(buffer.skip_past(&delimiter, &mut io::sink()));
+
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+    let mut parts = Vec::new();
+    loop {
+        if !try!(buffer.fill_to(2)) {
+            return Err(BodyError::Multipart(MultipartError::Malformed));
+        }
+        if buffer.data.starts_with(b"--") {
+            return Ok(parts);
+        }
+        if !buffer.data.starts_with(b"\r\n") {
+            return Err(BodyError::Multipart(MultipartError::Malformed));
+        }
+        buffer.consume(2);
+
+        let headers = try!(buffer.headers());
+        let mut part = try!(parse_headers(&headers));
+
+        let limit = match part.filename {
+            Some(_) => spool_above,
+            None => None,
+        };
+        let mut sink = Sink { data: Vec::new(), file: None, limit: limit };
+        try!(buffer.skip_past(&delimiter, &mut sink));
+        part.data = sink.data;
+        part.file = sink.file.map(|(saved, _)| saved);
+        parts.push(part);
+    }
+}
+
+/// The unparsed part of the body.
+struct Buffer<'r, R: 'r> {
+    reader: &'r mut R,
+    data: Vec<u8>,
+}
+
+impl<'r, R: Read> Buffer<'r, R> {
+    /// Reads more of the body. Returns `false` at the end of the body.
+    fn fill(&mut self) -> io::Result<bool> {
+        let mut chunk = [0; 8 * 1024];
+        let n = try!(self.reader.read(&mut chunk));
+        self.data.extend_from_slice(&chunk[..n]);
+        Ok(n > 0)
+    }
+
+    /// Reads until at least `len` bytes are buffered. Returns `false` if the body ends
+    /// before.
+    fn fill_to(&mut self, len: usize) -> io::Result<bool> {
+        while self.data.len() < len {
+            if !try!(self.fill()) {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
+    fn consume(&mut self, len: usize) {
+        self.data.drain(..len);
+    }
+
+    /// Writes everything before `needle` to `out` and consumes it, including `needle`.
+    fn skip_past<W: Write>(&mut self, needle: &[u8], out: &mut W) -> Result<(), BodyError> {
+        loop {
+            if let Some(pos) = find(&self.data, needle) {
+                try!(out.write_all(&self.data[..pos]));
+                self.consume(pos + needle.len());
+                return Ok(());
+            }
+            // keep what could be the start of `needle`
+            let safe = self.data.len().saturating_sub(needle.len() - 1);
+            try!(out.write_all(&self.data[..safe]));
+            self.consume(safe);
+            if !try!(self.fill()) {
+                return Err(BodyError::Multipart(MultipartError::Malformed));
+            }
+        }
+    }
+
+    /// Reads and consumes the headers of a part, including the empty line after them.
+    fn headers(&mut self) -> Result<String, BodyError> {
+        loop {
+            if let Some(end) = find(&self.data, b"\r\n\r\n") {
+                let headers = match str::from_utf8(&self.data[..end]) {
+                    Ok(headers) => headers.to_owned(),
+                    Err(_) => return Err(BodyError::Multipart(MultipartError::Malformed)),
+                };
+                self.consume(end + 4);
+                return Ok(headers);
+            }
+            if self.data.len() > MAX_HEADERS_SIZE || !try!(self.fill()) {
+                return Err(BodyError::Multipart(MultipartError::Malformed));
+            }
+        }
+    }
+}
+
+/// Collects the contents of a part in memory, moving them to a temporary file once they're
+/// larger than `limit`.
+struct Sink {
+    data: Vec<u8>,
+    file: Option<(SavedFile, File)>,
+    limit: Option<u64>,
+}
+
+impl Write for Sink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if let Some(limit) = self.limit {
+            if self.file.is_none() && (self.data.len() + buf.len()) as u64 > limit {
+                let (saved, mut file) = try!(SavedFile::create());
+                try!(file.write_all(&self.data));
+                self.data = Vec::new();
+                self.file = Some((saved, file));
+            }
+        }
+        match self.file {
+            Some((_, ref mut file)) => file.write(buf),
+            None => self.data.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.file {
+            Some((_, ref mut file)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+fn parse_headers(headers: &str) -> Result<Part, MultipartError> {
+    let mut name = None;
+    let mut filename = None;
+    let mut content_type = None;
+    for line in headers.split("\r\n") {
+        let mut split = line.splitn(2, ':');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) => (key.trim().to_lowercase(), value.trim()),
+            _ => return Err(MultipartError::Malformed),
+        };
+        if key == "content-disposition" {
+            for param in value.split(';').skip(1) {
+                let mut split = param.splitn(2, '=');
+                let (key, value) = match (split.next(), split.next()) {
+                    (Some(key), Some(value)) => (key.trim(), value.trim().trim_matches('"')),
+                    _ => continue,
+                };
+                match key {
+                    "name" => name = Some(value.to_owned()),
+                    "filename" => filename = Some(value.to_owned()),
+                    _ => {},
+                }
+            }
+        } else if key == "content-type" {
+            content_type = value.parse().ok();
+        }
+    }
+
+    match name {
+        Some(name) => Ok(Part {
+            name: name,
+            filename: filename,
+            content_type: content_type,
+            data: Vec::new(),
+            file: None,
+        }),
+        None => Err(MultipartError::MissingName),
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+#[test]
+fn parse_fields_and_files() {
+    let body = b"--XyZ\r\n\
+        Content-Disposition: form-data; name=\"title\"\r\n\
+        \r\n\
+        Holiday\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"photo\"; filename=\"beach.txt\"\r\n\
+        Content-Type: text/plain\r\n\
+        \r\n\
+        sand\r\nand sea\r\n\
+        --XyZ--\r\n";
+
+    let parts = parse(body, "XyZ").unwrap();
+    assert_eq!(parts.len(), 2);
+    assert_eq!(parts[0].name, "title");
+    assert_eq!(parts[0].filename, None);
+    assert_eq!(parts[0].data, b"Holiday");
+    assert_eq!(parts[1].filename, Some("beach.txt".to_owned()));
+    assert_eq!(parts[1].content_type, Some("text/plain".parse().unwrap()));
+    assert_eq!(parts[1].data, b"sand\r\nand sea");
+    assert_eq!(parse(b"--XyZ\r\nno end", "XyZ"), Err(MultipartError::Malformed));
+}
+
+#[test]
+fn spool_large_files() {
+    let body = b"--XyZ\r\n\
+        Content-Disposition: form-data; name=\"note\"\r\n\
+        \r\n\
+        a note longer than the limit\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"small\"; filename=\"a.txt\"\r\n\
+        \r\n\
+        tiny\r\n\
+        --XyZ\r\n\
+        Content-Disposition: form-data; name=\"big\"; filename=\"b.txt\"\r\n\
+        \r\n\
+        0123456789\r\n\
+        --XyZ--\r\n";
+
+    // a reader returning one byte at a time, to exercise the buffering
+    struct Trickle<'a>(&'a [u8]);
+    impl<'a> Read for Trickle<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = if self.0.is_empty() { 0 } else { 1 };
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    let parts = read(&mut Trickle(body), "XyZ", Some(4)).unwrap();
+    assert_eq!(parts[0].data, b"a note longer than the limit");
+    assert_eq!(parts[1].data, b"tiny");
+    assert!(parts[1].file.is_none());
+    assert!(parts[2].data.is_empty());
+
+    let path = parts[2].file.as_ref().unwrap().path();
+    let mut contents = String::new();
+    parts[2].reader().unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "0123456789");
+
+    drop(parts);
+    assert!(!path.exists());
+}
+
+#[test]
+fn persisted_files_stay_readable() {
+    let body = b"--XyZ\r\n\
+        Content-Disposition: form-data; name=\"big\"; filename=\"b.txt\"\r\n\
+        \r\n\
+        0123456789\r\n\
+        --XyZ--\r\n";
+    let parts = read(&mut &body[..], "XyZ", Some(4)).unwrap();
+    let file = parts[0].file.clone().unwrap();
+    let temp = file.path();
+    let dir = env::temp_dir();
+    let first = dir.join(format!("rask-persisted-{}-a.txt", process::id()));
+    let second = dir.join(format!("rask-persisted-{}-b.txt", process::id()));
+
+    parts[0].save(&first).unwrap();
+    assert!(!temp.exists());
+    assert_eq!(file.path(), first);
+    let mut contents = String::new();
+    file.open().unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "0123456789");
+
+    file.persist(&second).unwrap();
+    drop(parts);
+    drop(file);
+    assert_eq!(fs::read(&first).unwrap(), b"0123456789");
+    assert_eq!(fs::read(&second).unwrap(), b"0123456789");
+    fs::remove_file(&first).unwrap();
+    fs::remove_file(&second).unwrap();
+}
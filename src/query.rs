@@ -0,0 +1,244 @@
+//! Deserialization of query strings and forms into user types. Requires the `query` feature.
+//!
+//! Every key maps to a field. A key that occurs once deserializes like a single value, a
+//! key that occurs several times like a sequence, e.g. `?tag=a&tag=b` into `Vec<String>`.
+//! Numbers and booleans are parsed from the text of the value.
+
+use std::vec;
+
+use multimap::MultiMap;
+
+use serde::de::{self, Deserialize, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess, Unexpected, Visitor};
+use serde::de::value::SeqDeserializer;
+
+/// Error returned when a query string or form doesn't fit the target type.
+pub type Error = de::value::Error;
+
+/// Deserializes the pairs of `map` into `T`.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate multimap;
+/// extern crate rask;
+///
+/// use std::collections::HashMap;
+/// use multimap::MultiMap;
+///
+/// fn main() {
+///     let mut map = MultiMap::new();
+///     map.insert("page".to_owned(), "2".to_owned());
+///     map.insert("tag".to_owned(), "web".to_owned());
+///     map.insert("tag".to_owned(), "http".to_owned());
+///
+///     let parsed: HashMap<String, Vec<String>> = rask::query::from_multimap(&map).unwrap();
+///     assert_eq!(parsed["page"], vec!["2"]);
+///     assert_eq!(parsed["tag"], vec!["web", "http"]);
+/// }
+/// ```
+pub fn from_multimap<'de, T: Deserialize<'de>>(map: &MultiMap<String, String>) -> Result<T, Error> {
+    let pairs: Vec<_> = map.iter_all().map(|(k, v)| (k.clone(), v.clone())).collect();
+    T::deserialize(Pairs { pairs: pairs.into_iter(), values: None })
+}
+
+struct Pairs {
+    pairs: vec::IntoIter<(String, Vec<String>)>,
+    values: Option<Vec<String>>,
+}
+
+impl<'de> Deserializer<'de> for Pairs {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 u8 u16 u32 u64 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de> MapAccess<'de> for Pairs {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, Error> {
+        match self.pairs.next() {
+            Some((key, values)) => {
+                self.values = Some(values);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let values = self.values.take().unwrap_or(Vec::new());
+        seed.deserialize(Values(values))
+    }
+}
+
+/// All the values of one key.
+struct Values(Vec<String>);
+
+impl Values {
+    /// The value used when a single value is expected. The last one wins, like a form
+    /// field that's been overridden.
+    fn last(mut self) -> Text {
+        Text(self.0.pop().unwrap_or(String::new()))
+    }
+
+    fn seq(self) -> SeqDeserializer<vec::IntoIter<Text>, Error> {
+        let texts: Vec<_> = self.0.into_iter().map(Text).collect();
+        SeqDeserializer::new(texts.into_iter())
+    }
+}
+
+macro_rules! forward_to_last {
+    ($($method:ident)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                self.last().$method(visitor)
+            }
+        )*
+    }
+}
+
+impl<'de> Deserializer<'de> for Values {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        if self.0.len() == 1 {
+            self.last().deserialize_any(visitor)
+        } else {
+            visitor.visit_seq(self.seq())
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.seq())
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_seq(self.seq())
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        variants: &'static [&'static str],
+        visitor: V) -> Result<V::Value, Error>
+    {
+        self.last().deserialize_enum(name, variants, visitor)
+    }
+
+    forward_to_last! {
+        deserialize_bool deserialize_i8 deserialize_i16 deserialize_i32 deserialize_i64
+        deserialize_u8 deserialize_u16 deserialize_u32 deserialize_u64 deserialize_f32
+        deserialize_f64 deserialize_char deserialize_str deserialize_string
+        deserialize_identifier
+    }
+
+    forward_to_deserialize_any! {
+        bytes byte_buf unit unit_struct tuple_struct map struct ignored_any
+    }
+}
+
+/// A single value.
+struct Text(String);
+
+impl<'de> IntoDeserializer<'de, Error> for Text {
+    type Deserializer = Text;
+
+    fn into_deserializer(self) -> Text {
+        self
+    }
+}
+
+macro_rules! parse_value {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+                match self.0.parse() {
+                    Ok(value) => visitor.$visit(value),
+                    Err(_) => Err(de::Error::invalid_value(Unexpected::Str(&self.0), &visitor)),
+                }
+            }
+        )*
+    }
+}
+
+impl<'de> Deserializer<'de> for Text {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_string(self.0)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V) -> Result<V::Value, Error>
+    {
+        visitor.visit_enum(self.0.into_deserializer())
+    }
+
+    parse_value! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    forward_to_deserialize_any! {
+        str string bytes byte_buf unit unit_struct seq tuple tuple_struct map struct
+        identifier ignored_any
+    }
+}
+
+#[test]
+fn repeated_keys_and_parsed_values() {
+    use std::collections::HashMap;
+
+    let mut map = MultiMap::new();
+    map.insert("page".to_owned(), "3".to_owned());
+    map.insert("ids".to_owned(), "1".to_owned());
+    map.insert("ids".to_owned(), "2".to_owned());
+    map.insert("single".to_owned(), "7".to_owned());
+
+    let parsed: HashMap<String, Vec<u32>> = from_multimap(&map).unwrap();
+    assert_eq!(parsed["page"], vec![3]);
+    assert_eq!(parsed["ids"], vec![1, 2]);
+
+    let parsed: HashMap<String, Option<u32>> = from_multimap(&map).unwrap();
+    assert_eq!(parsed["page"], Some(3));
+    assert_eq!(parsed["ids"], Some(2));
+
+    map.insert("page".to_owned(), "three".to_owned());
+    assert!(from_multimap::<HashMap<String, u32>>(&map).is_err());
+}
@@ -1,61 +1,1370 @@
+use std::any::{Any, TypeId};
+use std::cell::{Ref, RefCell, RefMut};
 use std::collections::HashMap;
-
-use regex::Captures;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, Read};
+use std::marker::PhantomData;
+use std::net::{IpAddr, SocketAddr};
+use std::ops::Deref;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering, ATOMIC_USIZE_INIT};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use hyper::server::request::Request as HttpRequest;
 use hyper::method::Method;
+use hyper::uri::RequestUri;
+use hyper::version::HttpVersion;
+use hyper::header::{AcceptLanguage, ContentType, Header, HeaderFormat, Headers, Host, ByteRangeSpec, HttpDate, IfMatch, IfModifiedSince, IfNoneMatch, Range};
+use hyper::mime::{Attr, Mime, TopLevel, SubLevel};
 
 use multimap::MultiMap;
 
+use cookie::{Cookie, CookieJar};
+
+use rustc_serialize::base64::FromBase64;
+
+use url::form_urlencoded;
+
+#[cfg(any(feature = "json", feature = "query"))]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "json")]
+use serde_json;
+
+use inflate::{self, InflateError};
+use middleware::CsrfToken;
+use multipart::{self, MultipartError, Part};
+#[cfg(feature = "query")]
+use query;
+use session::Session;
+use routing::{RouteInfo, UrlError};
+use Rask;
+
 pub enum RequestMarker {}
 
+static REQUEST_COUNTER: AtomicUsize = ATOMIC_USIZE_INIT;
+
 /// The struct that holds information about the incoming Request. The handlers will borrow this
 /// struct.
 pub struct Request<'a, 'b: 'a> {
-    inner: HttpRequest<'a, 'b>,
+    _stream: PhantomData<HttpRequest<'a, 'b>>,
+    app: &'a Rask,
+    id: String,
+    remote_addr: SocketAddr,
+    /// The scheme and client address given by proxies, see `middleware::ProxyFix`.
+    forwarded_scheme: Option<&'static str>,
+    forwarded_client: Option<IpAddr>,
+    method: Method,
+    uri: RequestUri,
+    version: HttpVersion,
+    headers: Headers,
+    cookies: Cookies,
+    session: Session,
+    /// Set when the cookies or the session are read, which makes the response vary by them.
+    cookies_read: Arc<AtomicBool>,
+    reader: RefCell<Box<Read + 'a>>,
+    body: RefCell<Option<Vec<u8>>>,
+    parts: RefCell<Option<Vec<Part>>>,
+    route: Option<RouteInfo<'a>>,
+    query_string: Option<String>,
+    extensions: Extensions,
     pub path: Option<String>,
     pub gets: MultiMap<String, String>,
-    pub vars: HashMap<String, String>,
+    pub vars: Vars,
+}
+
+/// The error returned when the body of a request can't be read or parsed.
+#[derive(Debug)]
+pub enum BodyError {
+    /// The body couldn't be read from the connection.
+    Io(io::Error),
+    /// The body doesn't have the content type expected by the parser.
+    UnsupportedMediaType,
+    /// The body is larger than allowed by `Rask::max_body_size`.
+    TooLarge,
+    /// The body isn't valid utf-8, or isn't valid in the charset of the request.
+    Encoding,
+    /// The charset of the request isn't supported by `Request::text`.
+    UnsupportedCharset(String),
+    /// The `Content-Encoding` of the body isn't supported.
+    UnsupportedEncoding(String),
+    /// The compressed body can't be decompressed.
+    Compression,
+    /// The `multipart/form-data` body can't be parsed.
+    Multipart(MultipartError),
+    /// The body isn't valid json for the expected type.
+    #[cfg(feature = "json")]
+    Json(serde_json::Error),
+}
+
+impl fmt::Display for BodyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            BodyError::Io(ref err) => write!(f, "Couldn't read the body: {}", err),
+            BodyError::UnsupportedMediaType => write!(f, "Unsupported content type."),
+            BodyError::TooLarge => write!(f, "The body is too large."),
+            BodyError::Encoding => write!(f, "The body isn't valid in its charset."),
+            BodyError::UnsupportedCharset(ref charset) => write!(f, "Unsupported charset: {}", charset),
+            BodyError::UnsupportedEncoding(ref encoding) => write!(f, "Unsupported content encoding: {}", encoding),
+            BodyError::Compression => write!(f, "The compressed body is corrupt."),
+            BodyError::Multipart(ref err) => write!(f, "Invalid multipart body: {}", err),
+            #[cfg(feature = "json")]
+            BodyError::Json(ref err) => write!(f, "Invalid json: {}", err),
+        }
+    }
+}
+
+impl Error for BodyError {
+    fn description(&self) -> &str {
+        match *self {
+            BodyError::Io(_) => "io error",
+            BodyError::UnsupportedMediaType => "unsupported media type",
+            BodyError::TooLarge => "body too large",
+            BodyError::Encoding => "invalid encoding",
+            BodyError::UnsupportedCharset(_) => "unsupported charset",
+            BodyError::UnsupportedEncoding(_) => "unsupported content encoding",
+            BodyError::Compression => "corrupt compressed body",
+            BodyError::Multipart(_) => "invalid multipart body",
+            #[cfg(feature = "json")]
+            BodyError::Json(_) => "invalid json",
+        }
+    }
+}
+
+impl From<io::Error> for BodyError {
+    fn from(err: io::Error) -> BodyError {
+        if err.get_ref().map_or(false, |err| err.is::<TooLarge>()) {
+            return BodyError::TooLarge;
+        }
+        BodyError::Io(err)
+    }
+}
+
+/// The error a `LimitedReader` fails with when the limit is exceeded.
+#[derive(Debug)]
+struct TooLarge;
+
+impl fmt::Display for TooLarge {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.description())
+    }
+}
+
+impl Error for TooLarge {
+    fn description(&self) -> &str {
+        "body too large"
+    }
+}
+
+/// Fails reading once more than `remaining` bytes are read, and sets `exceeded`.
+struct LimitedReader<R> {
+    inner: R,
+    remaining: Option<u64>,
+    exceeded: Arc<AtomicBool>,
+}
+
+impl<R: Read> Read for LimitedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = try!(self.inner.read(buf));
+        if let Some(ref mut remaining) = self.remaining {
+            if n as u64 > *remaining {
+                self.exceeded.store(true, Ordering::Relaxed);
+                return Err(io::Error::new(io::ErrorKind::Other, TooLarge));
+            }
+            *remaining -= n as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl From<InflateError> for BodyError {
+    fn from(err: InflateError) -> BodyError {
+        match err {
+            InflateError::Corrupt => BodyError::Compression,
+            InflateError::TooLarge => BodyError::TooLarge,
+        }
+    }
+}
+
+impl From<MultipartError> for BodyError {
+    fn from(err: MultipartError) -> BodyError {
+        BodyError::Multipart(err)
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<serde_json::Error> for BodyError {
+    fn from(err: serde_json::Error) -> BodyError {
+        BodyError::Json(err)
+    }
+}
+
+/// Reads the body of a request without buffering it. See `Request::body_reader`.
+pub struct BodyReader<'r, 'a: 'r> {
+    inner: BodyReaderInner<'r, 'a>,
+}
+
+enum BodyReaderInner<'r, 'a: 'r> {
+    Buffered(Ref<'r, Option<Vec<u8>>>, usize),
+    Stream(RefMut<'r, Box<Read + 'a>>),
+}
+
+impl<'r, 'a> Read for BodyReader<'r, 'a> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.inner {
+            BodyReaderInner::Buffered(ref body, ref mut pos) => {
+                let mut rest = &body.as_ref().unwrap()[*pos..];
+                let n = try!(rest.read(buf));
+                *pos += n;
+                Ok(n)
+            },
+            BodyReaderInner::Stream(ref mut reader) => reader.read(buf),
+        }
+    }
+}
+
+/// The cookies sent with a request. See `Request::cookies`. Use `Response::cookies` to set
+/// cookies.
+pub struct Cookies {
+    jar: CookieJar<'static>,
+    /// The cookies keyed with the previous secrets, see `Rask::previous_secrets`.
+    previous: Vec<CookieJar<'static>>,
+}
+
+impl Cookies {
+    /// The cookie `name`.
+    pub fn find(&self, name: &str) -> Option<Cookie> {
+        self.jar.find(name)
+    }
+
+    /// The cookie `name`, if it's signed with the application's secret, i.e. it was set with
+    /// `Response::cookies().add_signed()` and hasn't been tampered with. The value is
+    /// returned without the signature. Cookies signed with a previous secret are accepted
+    /// too, see `Rask::previous_secrets`.
+    pub fn find_signed(&self, name: &str) -> Option<Cookie> {
+        self.jar.signed().find(name).or_else(|| self.previous.iter().filter_map(|jar| jar.signed().find(name)).next())
+    }
+
+    /// The cookie `name`, if it's encrypted with the application's secret, i.e. it was set
+    /// with `Response::cookies().add_encrypted()`. Cookies encrypted with a previous secret
+    /// are accepted too.
+    pub fn find_encrypted(&self, name: &str) -> Option<Cookie> {
+        self.jar.encrypted().find(name).or_else(|| self.previous.iter().filter_map(|jar| jar.encrypted().find(name)).next())
+    }
+
+    /// All cookies, as sent by the client.
+    pub fn all(&self) -> Vec<Cookie> {
+        self.jar.iter().collect()
+    }
+}
+
+/// The variables captured from the route, e.g. `name` for "/profile/{name}". Derefs to a
+/// `HashMap<String, String>`.
+pub struct Vars {
+    inner: HashMap<String, String>,
+}
+
+impl Vars {
+    /// Gets the variable `name` converted to `T`. Returns `None` if the variable doesn't
+    /// exist or can't be converted.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// // registered as "/user/{id:int}"
+    /// fn user(req: &Request, res: Response) {
+    ///     let id: i64 = req.vars.get_as("id").unwrap();
+    ///     res.send(format!("User #{}", id));
+    /// }
+    /// ```
+    pub fn get_as<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.inner.get(name).and_then(|v| v.parse().ok())
+    }
+}
+
+impl Deref for Vars {
+    type Target = HashMap<String, String>;
+
+    fn deref(&self) -> &HashMap<String, String> {
+        &self.inner
+    }
+}
+
+/// Values of any type, at most one per type, attached to a request, e.g. the authenticated
+/// user. See `Request::extensions`.
+pub struct Extensions {
+    map: HashMap<TypeId, Box<Any>>,
+}
+
+impl Extensions {
+    fn new() -> Extensions {
+        Extensions { map: HashMap::new() }
+    }
+
+    /// Stores `value`, returning the previous value of the same type.
+    pub fn insert<T: Any>(&mut self, value: T) -> Option<T> {
+        self.map
+            .insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|old| old.downcast().ok().map(|old| *old))
+    }
+
+    /// The value of type `T`.
+    pub fn get<T: Any>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref())
+    }
+
+    /// The value of type `T`, mutably.
+    pub fn get_mut<T: Any>(&mut self) -> Option<&mut T> {
+        self.map.get_mut(&TypeId::of::<T>()).and_then(|value| value.downcast_mut())
+    }
+
+    /// Removes and returns the value of type `T`.
+    pub fn remove<T: Any>(&mut self) -> Option<T> {
+        self.map
+            .remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast().ok().map(|value| *value))
+    }
+
+    /// Whether a value of type `T` is stored.
+    pub fn contains<T: Any>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
 }
 
 impl<'a, 'b> Request<'a, 'b> {
     #[doc(hidden)]
-    pub fn new(req: HttpRequest<'a, 'b>, captures: Option<Captures>, path: Option<String>, query_string: Option<String>) -> Request<'a, 'b> {
+    pub fn new(app: &'a Rask, req: HttpRequest<'a, 'b>, id: String, route: Option<RouteInfo<'a>>, vars: HashMap<String, String>, path: Option<String>, query_string: Option<String>, session: Session, cookies_read: Arc<AtomicBool>, body_too_large: Arc<AtomicBool>) -> Request<'a, 'b> {
+        let (remote_addr, method, headers, uri, version, reader) = req.deconstruct();
+        let cookies = Cookies { jar: app.cookie_jar(&headers), previous: app.previous_cookie_jars(&headers) };
         Request {
-            inner: req,
+            _stream: PhantomData,
+            app: app,
+            id: id,
+            remote_addr: remote_addr,
+            forwarded_scheme: None,
+            forwarded_client: None,
+            method: method,
+            uri: uri,
+            version: version,
+            cookies: cookies,
+            session: session,
+            cookies_read: cookies_read,
+            headers: headers,
+            reader: RefCell::new(Box::new(LimitedReader {
+                inner: reader,
+                remaining: app.max_body_size,
+                exceeded: body_too_large,
+            })),
+            body: RefCell::new(None),
+            parts: RefCell::new(None),
+            route: route,
             path: path,
             gets: query_string
-                .map(|s| parse_query_string(&s))
+                .as_ref()
+                .map(|s| parse_query_string(s))
                 .unwrap_or(MultiMap::new()),
-            vars: captures
-                .map(|c| c
-                     .iter_named()
-                     .map(|(k,v)| (k.to_string(), v.unwrap().to_string())).collect())
-                .unwrap_or(HashMap::new()),
+            query_string: query_string,
+            extensions: Extensions::new(),
+            vars: Vars { inner: vars },
         }
     }
 
+    #[doc(hidden)]
+    pub fn app(&self) -> &'a Rask {
+        self.app
+    }
+
+    /// An id identifying the request in logs, e.g. to correlate the lines logged for one
+    /// request. Taken from the `X-Request-ID` header if the client or a proxy sent one, else
+    /// generated.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// The http method of the request. For a POST overridden with `Rask::method_override`
+    /// that's the overriding method.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Method;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn item(req: &Request, res: Response) {
+    ///     match *req.method() {
+    ///         Method::Get => res.send("the item"),
+    ///         Method::Delete => res.send("deleted"),
+    ///         _ => res.send("something else"),
+    ///     };
+    /// }
+    /// ```
     pub fn method<'c>(&'c self) -> &'c Method {
-        &self.inner.method
+        &self.method
+    }
+
+    /// Overrides the http method, see `Rask::method_override`.
+    #[doc(hidden)]
+    pub fn set_method(&mut self, method: Method) {
+        self.method = method;
+    }
+
+    /// The request target, as sent in the request line, e.g. `/search?q=rust`. Use `path`
+    /// and `gets` for the decoded parts.
+    pub fn uri(&self) -> &RequestUri {
+        &self.uri
+    }
+
+    /// The http version of the request, e.g. `HttpVersion::Http11`.
+    pub fn version(&self) -> &HttpVersion {
+        &self.version
+    }
+
+    /// The scheme the client used, "http" or "https". Rask itself only speaks http, but a
+    /// request from one of the `Rask::trusted_proxies` is "https" if the proxy says so in the
+    /// `Forwarded` or `X-Forwarded-Proto` header, as is one `middleware::ProxyFix` found to
+    /// be.
+    pub fn scheme(&self) -> &'static str {
+        if let Some(scheme) = self.forwarded_scheme {
+            return scheme;
+        }
+        if !self.app.trusted_proxies.contains(&self.remote_addr.ip()) {
+            return "http";
+        }
+        match forwarded_values(&self.headers, "proto", "X-Forwarded-Proto").pop() {
+            Some(ref proto) if proto.eq_ignore_ascii_case("https") => "https",
+            _ => "http",
+        }
+    }
+
+    /// Whether the client used https, see `scheme`.
+    pub fn is_secure(&self) -> bool {
+        self.scheme() == "https"
+    }
+
+    /// Whether the request was sent by javascript, i.e. has a `X-Requested-With:
+    /// XMLHttpRequest` header, as set by most javascript libraries.
+    pub fn is_xhr(&self) -> bool {
+        self.headers
+            .get_raw("X-Requested-With")
+            .and_then(|values| values.first())
+            .map(|value| value.eq_ignore_ascii_case(b"XMLHttpRequest"))
+            .unwrap_or(false)
+    }
+
+    /// The query string as it was sent, without the leading `?`. `gets` holds the decoded
+    /// pairs; this is for the odd case where the encoding itself matters.
+    pub fn query_string(&self) -> Option<&str> {
+        self.query_string.as_ref().map(|s| &s[..])
+    }
+
+    /// The address of the peer the request came from. Behind a proxy that's the address of
+    /// the proxy.
+    pub fn remote_addr(&self) -> SocketAddr {
+        self.remote_addr
+    }
+
+    /// The address of the client. Same as the ip of `remote_addr`, unless the request comes
+    /// from one of the proxies set with `Rask::trusted_proxies`. Then it's the address the
+    /// proxies forwarded the request for, according to the `Forwarded` or `X-Forwarded-For`
+    /// header, or the one `middleware::ProxyFix` found.
+    pub fn client_ip(&self) -> IpAddr {
+        if let Some(client) = self.forwarded_client {
+            return client;
+        }
+        let forwarded = forwarded_values(&self.headers, "for", "X-Forwarded-For");
+        client_ip(self.remote_addr.ip(), &self.app.trusted_proxies, &forwarded)
+    }
+
+    /// The host the request was sent to, from the `Host` header, e.g. "example.com:8080".
+    pub fn host(&self) -> Option<String> {
+        self.headers.get::<Host>().map(|host| match host.port {
+            Some(port) => format!("{}:{}", host.hostname, port),
+            None => host.hostname.clone(),
+        })
+    }
+
+    /// Sets the scheme, host and client address given by trusted proxies.
+    #[doc(hidden)]
+    pub fn set_forwarded(&mut self, scheme: Option<&'static str>, host: Option<Host>, client: Option<IpAddr>) {
+        if scheme.is_some() {
+            self.forwarded_scheme = scheme;
+        }
+        if let Some(host) = host {
+            self.headers.set(host);
+        }
+        if client.is_some() {
+            self.forwarded_client = client;
+        }
+    }
+
+    /// The headers of the request.
+    pub fn headers(&self) -> &Headers {
+        &self.headers
+    }
+
+    /// The header `H` of the request, or `None` if it's missing or invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::header::UserAgent;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn index(req: &Request, res: Response) {
+    ///     match req.header::<UserAgent>() {
+    ///         Some(&UserAgent(ref agent)) => res.send(format!("Hello, {}", agent)),
+    ///         None => res.send("Hello, stranger"),
+    ///     };
+    /// }
+    /// ```
+    pub fn header<H: Header + HeaderFormat>(&self) -> Option<&H> {
+        self.headers.get::<H>()
+    }
+
+    /// The entity tags of the `If-None-Match` header. `None` if the header is missing or
+    /// invalid.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::header::{EntityTag, IfNoneMatch};
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn report(req: &Request, res: Response) {
+    ///     let etag = EntityTag::strong("v42".to_owned());
+    ///     match req.if_none_match() {
+    ///         Some(&IfNoneMatch::Any) => res.send(StatusCode::NotModified),
+    ///         Some(&IfNoneMatch::Items(ref tags)) if tags.iter().any(|t| t.weak_eq(&etag)) => {
+    ///             res.send(StatusCode::NotModified)
+    ///         },
+    ///         _ => res.send("the report"),
+    ///     };
+    /// }
+    /// ```
+    pub fn if_none_match(&self) -> Option<&IfNoneMatch> {
+        self.headers.get()
+    }
+
+    /// The entity tags of the `If-Match` header. `None` if the header is missing or invalid.
+    pub fn if_match(&self) -> Option<&IfMatch> {
+        self.headers.get()
+    }
+
+    /// The byte ranges of the `Range` header, as sent. `None` if the header is missing,
+    /// invalid or uses another unit than bytes.
+    pub fn range(&self) -> Option<&[ByteRangeSpec]> {
+        match self.headers.get::<Range>() {
+            Some(&Range::Bytes(ref ranges)) => Some(ranges),
+            _ => None,
+        }
+    }
+
+    /// The byte ranges of the `Range` header as inclusive `(first, last)` offsets into a body
+    /// of `len` bytes. Ranges that don't overlap the body are left out, so an empty vector
+    /// means the request should be answered with 416 Range Not Satisfiable. `None` if there's
+    /// no usable `Range` header, i.e. the whole body should be sent.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn download(req: &Request, res: Response) {
+    ///     let data = b"0123456789".to_vec();
+    ///     match req.byte_ranges(data.len() as u64) {
+    ///         None => res.send(data),
+    ///         Some(ref ranges) if ranges.is_empty() => res.send(StatusCode::RangeNotSatisfiable),
+    ///         Some(ranges) => {
+    ///             let (first, last) = ranges[0];
+    ///             res.send(data[first as usize..last as usize + 1].to_vec())
+    ///         },
+    ///     };
+    /// }
+    /// ```
+    pub fn byte_ranges(&self, len: u64) -> Option<Vec<(u64, u64)>> {
+        self.range().map(|specs| satisfiable_ranges(specs, len))
+    }
+
+    /// The date of the `If-Modified-Since` header. `None` if the header is missing or
+    /// invalid.
+    pub fn if_modified_since(&self) -> Option<HttpDate> {
+        self.headers.get::<IfModifiedSince>().map(|header| header.0)
+    }
+
+    /// The cookies sent with the request.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn index(req: &Request, res: Response) {
+    ///     match req.cookies().find_signed("username") {
+    ///         Some(cookie) => res.send(format!("Hello, {}", cookie.value)),
+    ///         None => res.send("Hello, stranger"),
+    ///     };
+    /// }
+    /// ```
+    pub fn cookies(&self) -> &Cookies {
+        self.cookies_read.store(true, Ordering::Relaxed);
+        &self.cookies
+    }
+
+    /// The session of the client, see `session::Session`. Change it with
+    /// `Response::session`.
+    pub fn session(&self) -> &Session {
+        self.cookies_read.store(true, Ordering::Relaxed);
+        &self.session
+    }
+
+    /// The username and password of the `Authorization: Basic` header. `None` if the header
+    /// is missing, uses another scheme or isn't valid base64 encoded utf-8.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn admin(req: &Request, res: Response) {
+    ///     match req.basic_auth() {
+    ///         Some((ref user, ref password)) if user == "admin" && password == "hunter2" => {
+    ///             res.send("Welcome")
+    ///         },
+    ///         _ => res.send(StatusCode::Unauthorized),
+    ///     };
+    /// }
+    /// ```
+    pub fn basic_auth(&self) -> Option<(String, String)> {
+        self.headers
+            .get_raw("Authorization")
+            .and_then(|values| values.first())
+            .and_then(|value| parse_basic_auth(value))
+    }
+
+    /// The token of the `Authorization: Bearer` header. `None` if the header is missing or
+    /// uses another scheme.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn api(req: &Request, res: Response) {
+    ///     match req.bearer_token() {
+    ///         Some("s3cr3t") => res.send("{}"),
+    ///         _ => res.send(StatusCode::Unauthorized),
+    ///     };
+    /// }
+    /// ```
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.headers
+            .get_raw("Authorization")
+            .and_then(|values| values.first())
+            .and_then(|value| auth_credentials(value, "bearer"))
+    }
+
+    /// Values attached to the request by code that ran before the handler, keyed by their
+    /// type.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// struct User {
+    ///     name: String,
+    /// }
+    ///
+    /// fn profile(req: &Request, res: Response) {
+    ///     match req.extensions().get::<User>() {
+    ///         Some(user) => res.send(format!("Hello, {}", user.name)),
+    ///         None => res.send("Hello, stranger"),
+    ///     };
+    /// }
+    /// ```
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// The token to send back with forms and scripts, from the session of the client. `None`
+    /// unless the `middleware::Csrf` middleware is used.
+    pub fn csrf_token(&self) -> Option<&str> {
+        self.extensions.get::<CsrfToken>().map(|token| &token.0[..])
+    }
+
+    /// Mutable access to the extensions, for code that has the request before the handler.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Sets the route the request was routed to, and the variables of its path.
+    #[doc(hidden)]
+    pub fn set_route(&mut self, route: RouteInfo<'a>, vars: HashMap<String, String>) {
+        self.route = Some(route);
+        self.vars = Vars { inner: vars };
+    }
+
+    /// The media type of the body, from the `Content-Type` header.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::mime::{Mime, TopLevel, SubLevel};
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn upload(req: &Request, res: Response) {
+    ///     match req.content_type() {
+    ///         Some(&Mime(TopLevel::Image, _, _)) => res.send(StatusCode::Created),
+    ///         _ => res.send(StatusCode::UnsupportedMediaType),
+    ///     };
+    /// }
+    /// ```
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.headers.get::<ContentType>().map(|content_type| &content_type.0)
+    }
+
+    /// The charset of the body, lowercased, from the `Content-Type` header, e.g. "utf-8".
+    pub fn charset(&self) -> Option<String> {
+        self.content_type()
+            .and_then(|mime| mime.get_param(Attr::Charset))
+            .map(|charset| charset.to_string().to_lowercase())
+    }
+
+    /// The languages from the `Accept-Language` header, most preferred first, e.g.
+    /// `["da", "en-GB", "en"]` for "da, en-gb;q=0.8, en;q=0.7". Languages with quality 0 are
+    /// left out.
+    pub fn preferred_languages(&self) -> Vec<String> {
+        let mut languages = match self.headers.get::<AcceptLanguage>() {
+            Some(&AcceptLanguage(ref languages)) => languages.iter().filter(|l| l.quality.0 > 0).collect(),
+            None => Vec::new(),
+        };
+        // stable, so languages with the same quality keep their order
+        languages.sort_by(|a, b| b.quality.cmp(&a.quality));
+        languages.into_iter().map(|language| language.item.to_string()).collect()
+    }
+
+    /// The body of the request. It's read from the connection the first time it's accessed.
+    /// Fails with `BodyError::TooLarge` if the body is larger than `Rask::max_body_size`, and
+    /// the response is then a 413 (Payload Too Large) error, see `Rask::max_body_size`.
+    ///
+    /// A body with a `gzip` or `deflate` `Content-Encoding` is decompressed, failing with
+    /// `BodyError::TooLarge` if it decompresses to more than
+    /// `Rask::max_decompressed_body_size`, and with `BodyError::UnsupportedEncoding` for other
+    /// encodings.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::{BodyError, Request};
+    /// use rask::response::Response;
+    ///
+    /// fn upload(req: &Request, res: Response) {
+    ///     match req.body() {
+    ///         Ok(body) => res.send(format!("Got {} bytes", body.len())),
+    ///         Err(BodyError::TooLarge) => res.send(StatusCode::PayloadTooLarge),
+    ///         Err(_) => res.send(StatusCode::BadRequest),
+    ///     };
+    /// }
+    /// ```
+    pub fn body(&self) -> Result<Ref<[u8]>, BodyError> {
+        if self.body.borrow().is_none() {
+            let mut body = Vec::new();
+            try!(self.reader.borrow_mut().read_to_end(&mut body));
+            let body = try!(self.decode_content(body));
+            *self.body.borrow_mut() = Some(body);
+        }
+        Ok(Ref::map(self.body.borrow(), |body| &body.as_ref().unwrap()[..]))
+    }
+
+    /// Undoes the `Content-Encoding` of `body`, in reverse order of the listed encodings.
+    fn decode_content(&self, mut body: Vec<u8>) -> Result<Vec<u8>, BodyError> {
+        let limit = self.app.max_decompressed_body_size;
+        for encoding in self.content_encodings().iter().rev() {
+            body = match &encoding[..] {
+                "identity" => body,
+                "gzip" | "x-gzip" => try!(inflate::gunzip(&body, limit)),
+                "deflate" => try!(inflate::zlib_or_raw(&body, limit)),
+                _ => return Err(BodyError::UnsupportedEncoding(encoding.clone())),
+            };
+        }
+        Ok(body)
+    }
+
+    fn content_encodings(&self) -> Vec<String> {
+        self.headers
+            .get_raw("Content-Encoding")
+            .map(|values| parse_list(values).into_iter().map(|e| e.to_lowercase()).filter(|e| e != "").collect())
+            .unwrap_or(Vec::new())
+    }
+
+    /// A reader over the body of the request, for streaming large bodies instead of
+    /// buffering them with `body`. What's read with the reader isn't available from `body`
+    /// afterwards. Reading more than `Rask::max_body_size` fails. The body isn't decompressed,
+    /// unless it was already read with `body`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::fs::File;
+    /// use std::io;
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn upload(req: &Request, res: Response) {
+    ///     let saved = File::create("upload.bin")
+    ///         .and_then(|mut file| io::copy(&mut req.body_reader(), &mut file));
+    ///     match saved {
+    ///         Ok(_) => res.send(StatusCode::Created),
+    ///         Err(_) => res.send(StatusCode::InternalServerError),
+    ///     };
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if another reader over the body is still alive.
+    pub fn body_reader<'r>(&'r self) -> BodyReader<'r, 'a> {
+        let body = self.body.borrow();
+        let inner = if body.is_some() {
+            BodyReaderInner::Buffered(body, 0)
+        } else {
+            BodyReaderInner::Stream(self.reader.borrow_mut())
+        };
+        BodyReader { inner: inner }
+    }
+
+    /// The body of the request as a string. Fails with `BodyError::Encoding` if the body
+    /// isn't valid utf-8.
+    pub fn body_string(&self) -> Result<String, BodyError> {
+        let body = try!(self.body());
+        match String::from_utf8(body.to_vec()) {
+            Ok(body) => Ok(body),
+            Err(_) => Err(BodyError::Encoding),
+        }
+    }
+
+    /// The body of the request decoded according to its charset, utf-8 if it has none.
+    /// Supports utf-8, us-ascii and iso-8859-1. Fails with `BodyError::Encoding` if the body
+    /// isn't valid in its charset, and with `BodyError::UnsupportedCharset` for other charsets.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn note(req: &Request, res: Response) {
+    ///     match req.text() {
+    ///         Ok(text) => res.send(format!("{} characters", text.chars().count())),
+    ///         Err(_) => res.send(StatusCode::BadRequest),
+    ///     };
+    /// }
+    /// ```
+    pub fn text(&self) -> Result<String, BodyError> {
+        let body = try!(self.body());
+        decode(&body, self.charset().as_ref().map(|s| &s[..]), false)
+    }
+
+    /// Same as `text`, but replaces invalid sequences with U+FFFD, and decodes unsupported
+    /// charsets as utf-8.
+    pub fn text_lossy(&self) -> Result<String, BodyError> {
+        let body = try!(self.body());
+        decode(&body, self.charset().as_ref().map(|s| &s[..]), true)
+    }
+
+    /// The route the request was dispatched to, or `None` if no route matched, e.g. in a
+    /// 404 handler. Useful to group requests by route pattern instead of by path.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn user(req: &Request, res: Response) {
+    ///     let pattern = req.route().map(|route| route.pattern).unwrap_or("");
+    ///     res.send(format!("Matched {}", pattern));
+    /// }
+    /// ```
+    pub fn route(&self) -> Option<RouteInfo<'a>> {
+        self.route
+    }
+
+    /// Parses the `application/x-www-form-urlencoded` or `multipart/form-data` body of the
+    /// request, e.g. the fields of a submitted html form. Uploaded files are left out, see
+    /// `files`. Fails with `BodyError::UnsupportedMediaType` for other content types.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn login(req: &Request, res: Response) {
+    ///     let form = match req.form() {
+    ///         Ok(form) => form,
+    ///         Err(_) => { let _ = res.send(StatusCode::BadRequest); return; },
+    ///     };
+    ///     match form.get("username") {
+    ///         Some(username) => res.send(format!("Hello, {}", username)),
+    ///         None => res.send(StatusCode::BadRequest),
+    ///     };
+    /// }
+    /// ```
+    pub fn form(&self) -> Result<MultiMap<String, String>, BodyError> {
+        let mut form = MultiMap::new();
+        match self.content_type() {
+            Some(&Mime(TopLevel::Application, SubLevel::WwwFormUrlEncoded, _)) => {
+                let body = try!(self.body());
+                for (key, value) in form_urlencoded::parse(&body) {
+                    form.insert(key, value);
+                }
+            },
+            Some(&Mime(TopLevel::Multipart, SubLevel::FormData, _)) => {
+                for part in try!(self.multipart()).into_iter().filter(|part| part.filename.is_none()) {
+                    form.insert(part.name, String::from_utf8_lossy(&part.data).into_owned());
+                }
+            },
+            _ => return Err(BodyError::UnsupportedMediaType),
+        }
+        Ok(form)
+    }
+
+    /// The files uploaded with a `multipart/form-data` body, e.g. from an html form with
+    /// `<input type="file">`. Fails with `BodyError::UnsupportedMediaType` for other content
+    /// types. Unless the body was already read, it's parsed while it's read, so files larger
+    /// than `Rask::spool_uploads_above` never have to be kept in memory.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn upload(req: &Request, res: Response) {
+    ///     for file in req.files().unwrap_or(Vec::new()) {
+    ///         let _ = file.save(file.filename.clone().unwrap());
+    ///     }
+    ///     let _ = res.send(StatusCode::Created);
+    /// }
+    /// ```
+    pub fn files(&self) -> Result<Vec<Part>, BodyError> {
+        Ok(try!(self.multipart()).into_iter().filter(|part| part.filename.is_some()).collect())
+    }
+
+    fn multipart(&self) -> Result<Vec<Part>, BodyError> {
+        if let Some(ref parts) = *self.parts.borrow() {
+            return Ok(parts.clone());
+        }
+        let boundary = match self.content_type() {
+            Some(mime @ &Mime(TopLevel::Multipart, SubLevel::FormData, _)) => {
+                match mime.get_param(Attr::Boundary) {
+                    Some(boundary) => boundary.to_string(),
+                    None => return Err(BodyError::Multipart(MultipartError::MissingBoundary)),
+                }
+            },
+            _ => return Err(BodyError::UnsupportedMediaType),
+        };
+        let spool_above = self.app.spool_uploads_above;
+        let parts = if self.body.borrow().is_some() || !self.content_encodings().is_empty() {
+            let body = try!(self.body());
+            try!(multipart::read(&mut &body[..], &boundary, spool_above))
+        } else {
+            try!(multipart::read(&mut *self.reader.borrow_mut(), &boundary, spool_above))
+        };
+        *self.parts.borrow_mut() = Some(parts.clone());
+        Ok(parts)
+    }
+
+    /// Deserializes the json body of the request. Fails with
+    /// `BodyError::UnsupportedMediaType` if the content type isn't `application/json` (or
+    /// `application/*+json`). Requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn create(req: &Request, res: Response) {
+    ///     match req.json::<HashMap<String, String>>() {
+    ///         Ok(item) => res.send(format!("Created {}", item["name"])),
+    ///         Err(_) => res.send(StatusCode::BadRequest),
+    ///     };
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, BodyError> {
+        if !is_json(self.content_type()) {
+            return Err(BodyError::UnsupportedMediaType);
+        }
+        let body = try!(self.body());
+        Ok(try!(serde_json::from_slice(&body)))
+    }
+
+    /// Deserializes the query string into `T`, see the `query` module for how keys and
+    /// values are mapped. Requires the `query` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// // "/search?tag=web&tag=http"
+    /// fn search(req: &Request, res: Response) {
+    ///     match req.query::<HashMap<String, Vec<String>>>() {
+    ///         Ok(filters) => res.send(format!("{} filters", filters.len())),
+    ///         Err(_) => res.send(StatusCode::BadRequest),
+    ///     };
+    /// }
+    /// ```
+    #[cfg(feature = "query")]
+    pub fn query<T: DeserializeOwned>(&self) -> Result<T, query::Error> {
+        query::from_multimap(&self.gets)
+    }
+
+    /// Builds the url for a named route, see `Rask::url_for`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn login(req: &Request, res: Response) {
+    ///     let profile = req.url_for("profile", &[("name", "bob")]).unwrap();
+    ///     res.redirect(&profile);
+    /// }
+    /// ```
+    pub fn url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        self.app.url_for(name, params)
+    }
+
+    /// Builds the absolute url for a named route, with the scheme and host of the request,
+    /// e.g. for links in emails. Falls back to `url_for` without a `Host` header.
+    pub fn absolute_url_for(&self, name: &str, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let path = try!(self.app.url_for(name, params));
+        Ok(match self.host() {
+            Some(host) => format!("{}://{}{}", self.scheme(), host, path),
+            None => path,
+        })
     }
 }
 
-fn parse_query_string(query_string: &str) -> MultiMap<String, String> {
-    let mut map = MultiMap::new();
-    for (key, value) in query_string
-        .split('&')
-        .map(|p| {
-            let pair: Vec<_> = p.splitn(2, '=').collect();
-            (pair.get(0).map(|s| (*s).into()).unwrap(),
-            pair.get(1).map(|s| (*s).into()).unwrap_or(String::new()))
+/// The id of a request: the `X-Request-ID` header if it's a reasonable id, otherwise a new
+/// id made of the current time and a counter.
+#[doc(hidden)]
+pub fn request_id(headers: &Headers) -> String {
+    let sent = headers
+        .get_raw("X-Request-ID")
+        .and_then(|values| values.first())
+        .and_then(|value| String::from_utf8(value.clone()).ok())
+        .map(|value| value.trim().to_owned())
+        .filter(|value| !value.is_empty() && value.len() <= 200 && value.chars().all(|c| c.is_ascii_graphic()));
+    sent.unwrap_or_else(|| {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        format!("{:x}{:08x}-{:x}", now.as_secs(), now.subsec_nanos(), REQUEST_COUNTER.fetch_add(1, Ordering::SeqCst))
+    })
+}
+
+#[cfg_attr(not(feature = "json"), allow(dead_code))]
+fn is_json(content_type: Option<&Mime>) -> bool {
+    match content_type {
+        Some(&Mime(TopLevel::Application, SubLevel::Json, _)) => true,
+        Some(&Mime(TopLevel::Application, SubLevel::Ext(ref sub), _)) => sub.ends_with("+json"),
+        _ => false,
+    }
+}
+
+/// The first address of the chain `remote`, `forwarded` reversed, that isn't a trusted proxy.
+/// Stops at an invalid address, as it can't be known if it was added by a trusted proxy.
+fn client_ip(remote: IpAddr, trusted: &[IpAddr], forwarded: &[String]) -> IpAddr {
+    let mut client = remote;
+    for address in forwarded.iter().rev() {
+        if !trusted.contains(&client) {
+            break;
+        }
+        match parse_ip(address) {
+            Some(ip) => client = ip,
+            None => break,
+        }
+    }
+    client
+}
+
+/// The `param` values of the `Forwarded` header, or else the values of the `x_header`, e.g.
+/// "X-Forwarded-For". Ordered from the proxy farthest away to the nearest.
+#[doc(hidden)]
+pub fn forwarded_values(headers: &Headers, param: &str, x_header: &str) -> Vec<String> {
+    match headers.get_raw("Forwarded") {
+        Some(values) => parse_forwarded(values, param),
+        None => headers.get_raw(x_header).map(|values| parse_list(values)).unwrap_or(Vec::new()),
+    }
+}
+
+/// Parses an address like "192.0.2.60", "192.0.2.60:4711", "2001:db8::1" or
+/// "[2001:db8::1]:4711".
+#[doc(hidden)]
+pub fn parse_ip(address: &str) -> Option<IpAddr> {
+    let address = address.trim().trim_matches('"');
+    if let Ok(ip) = address.parse() {
+        return Some(ip);
+    }
+    if address.starts_with('[') {
+        return address[1..].split(']').next().and_then(|ip| ip.parse().ok());
+    }
+    address.split(':').next().and_then(|ip| ip.parse().ok())
+}
+
+/// The elements of a comma separated header like `X-Forwarded-For`, over all its values.
+fn parse_list(values: &[Vec<u8>]) -> Vec<String> {
+    values
+        .iter()
+        .flat_map(|value| String::from_utf8_lossy(value).split(',').map(|s| s.trim().to_owned()).collect::<Vec<_>>())
+        .collect()
+}
+
+/// The `param` values of a `Forwarded` header, one per element, e.g. the `for` addresses of
+/// `for=192.0.2.60;proto=http, for=10.0.0.1`. Empty for elements without `param`.
+fn parse_forwarded(values: &[Vec<u8>], param: &str) -> Vec<String> {
+    parse_list(values)
+        .into_iter()
+        .map(|element| {
+            element
+                .split(';')
+                .filter_map(|pair| {
+                    let mut split = pair.splitn(2, '=');
+                    match (split.next(), split.next()) {
+                        (Some(key), Some(value)) if key.trim().eq_ignore_ascii_case(param) => {
+                            Some(value.trim().trim_matches('"').to_owned())
+                        },
+                        _ => None,
+                    }
+                })
+                .next()
+                .unwrap_or(String::new())
         })
-        .filter(|&(ref k,_)| k != "")
+        .collect()
+}
+
+/// The credentials of an `Authorization` header value, if it uses `scheme`, e.g. the token of
+/// `Bearer mF_9.B5f-4.1JqM`. The scheme is case-insensitive.
+fn auth_credentials<'v>(value: &'v [u8], scheme: &str) -> Option<&'v str> {
+    let value = match ::std::str::from_utf8(value) {
+        Ok(value) => value.trim(),
+        Err(_) => return None,
+    };
+    let mut split = value.splitn(2, ' ');
+    match (split.next(), split.next()) {
+        (Some(s), Some(credentials)) if s.eq_ignore_ascii_case(scheme) && credentials.trim() != "" => {
+            Some(credentials.trim())
+        },
+        _ => None,
+    }
+}
+
+/// Parses the credentials of an `Authorization` header like `Basic dXNlcjpwYXNz`. Unlike
+/// hyper's `Basic`, the password may contain `:`.
+fn parse_basic_auth(value: &[u8]) -> Option<(String, String)> {
+    let decoded = match auth_credentials(value, "basic")
+        .and_then(|credentials| credentials.from_base64().ok())
+        .and_then(|credentials| String::from_utf8(credentials).ok())
     {
-        map.insert(key, value);
+        Some(decoded) => decoded,
+        None => return None,
+    };
+    let mut split = decoded.splitn(2, ':');
+    let user = split.next().unwrap_or("").to_owned();
+    let password = split.next().unwrap_or("").to_owned();
+    Some((user, password))
+}
+
+/// Decodes `bytes` in `charset`, utf-8 if `None`. `lossy` replaces what can't be decoded.
+fn decode(bytes: &[u8], charset: Option<&str>, lossy: bool) -> Result<String, BodyError> {
+    match charset.unwrap_or("utf-8") {
+        "iso-8859-1" | "latin1" | "l1" => Ok(bytes.iter().map(|&b| b as char).collect()),
+        "us-ascii" | "ascii" if bytes.is_ascii() => Ok(bytes.iter().map(|&b| b as char).collect()),
+        "us-ascii" | "ascii" if lossy => Ok(bytes.iter().map(|&b| if b < 0x80 { b as char } else { '\u{fffd}' }).collect()),
+        "us-ascii" | "ascii" => Err(BodyError::Encoding),
+        "utf-8" | "utf8" => decode_utf8(bytes, lossy),
+        _ if lossy => decode_utf8(bytes, true),
+        charset => Err(BodyError::UnsupportedCharset(charset.to_owned())),
+    }
+}
+
+fn decode_utf8(bytes: &[u8], lossy: bool) -> Result<String, BodyError> {
+    if lossy {
+        return Ok(String::from_utf8_lossy(bytes).into_owned());
+    }
+    match String::from_utf8(bytes.to_vec()) {
+        Ok(text) => Ok(text),
+        Err(_) => Err(BodyError::Encoding),
+    }
+}
+
+/// Resolves `specs` against a body of `len` bytes, dropping the ones that can't be satisfied.
+fn satisfiable_ranges(specs: &[ByteRangeSpec], len: u64) -> Vec<(u64, u64)> {
+    specs
+        .iter()
+        .filter_map(|spec| {
+            let (first, last) = match *spec {
+                ByteRangeSpec::FromTo(first, last) => (first, last),
+                ByteRangeSpec::AllFrom(first) => (first, len.saturating_sub(1)),
+                ByteRangeSpec::Last(0) => return None,
+                ByteRangeSpec::Last(n) => (len.saturating_sub(n), len.saturating_sub(1)),
+            };
+            if len == 0 || first >= len {
+                None
+            } else {
+                Some((first, ::std::cmp::min(last, len - 1)))
+            }
+        })
+        .collect()
+}
+
+/// Parses the pairs of a query string, decoding `%XX` sequences and `+` in both keys and
+/// values. Pairs with an empty key are skipped.
+fn parse_query_string(query_string: &str) -> MultiMap<String, String> {
+    let mut map = MultiMap::new();
+    for (key, value) in form_urlencoded::parse(query_string.as_bytes()) {
+        if key != "" {
+            map.insert(key, value);
+        }
     }
     map
 }
 
+#[test]
+fn json_content_types() {
+    let content_type = |s: &str| s.parse::<Mime>().unwrap();
+
+    assert!(is_json(Some(&content_type("application/json; charset=utf-8"))));
+    assert!(is_json(Some(&content_type("application/vnd.api+json"))));
+    assert!(!is_json(Some(&content_type("text/plain"))));
+    assert!(!is_json(None));
+}
+
+#[test]
+fn client_ip_only_trusts_proxies() {
+    let ip = |s: &str| s.parse::<IpAddr>().unwrap();
+    let proxy = ip("10.0.0.1");
+    let forwarded = parse_forwarded(&[b"for=192.0.2.60;proto=http, for=\"[2001:db8::1]:4711\"".to_vec()], "for");
+
+    assert_eq!(client_ip(proxy, &[], &forwarded), proxy);
+    assert_eq!(client_ip(proxy, &[proxy], &forwarded), ip("2001:db8::1"));
+    assert_eq!(client_ip(proxy, &[proxy, ip("2001:db8::1")], &forwarded), ip("192.0.2.60"));
+    assert_eq!(client_ip(proxy, &[proxy], &parse_list(&[b"203.0.113.7, 198.51.100.2".to_vec()])),
+               ip("198.51.100.2"));
+}
+
+#[test]
+fn forwarded_parameters() {
+    let forwarded = [b"for=192.0.2.60;proto=http, proto=\"https\";by=10.0.0.1".to_vec()];
+
+    assert_eq!(parse_forwarded(&forwarded, "for"), vec!["192.0.2.60".to_owned(), "".to_owned()]);
+    assert_eq!(parse_forwarded(&forwarded, "PROTO"), vec!["http".to_owned(), "https".to_owned()]);
+}
+
+#[test]
+fn extensions_are_keyed_by_type() {
+    struct User(&'static str);
+
+    let mut extensions = Extensions::new();
+    assert!(extensions.insert(User("bob")).is_none());
+    assert!(extensions.insert(42u32).is_none());
+    assert_eq!(extensions.get::<User>().map(|u| u.0), Some("bob"));
+
+    *extensions.get_mut::<u32>().unwrap() += 1;
+    assert_eq!(extensions.insert(7u32), Some(43));
+    assert_eq!(extensions.remove::<u32>(), Some(7));
+    assert!(!extensions.contains::<u32>());
+    assert!(extensions.get::<u64>().is_none());
+}
+
+#[test]
+fn basic_auth_credentials() {
+    assert_eq!(parse_basic_auth(b"Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ=="),
+               Some(("Aladdin".to_owned(), "open sesame".to_owned())));
+    // "user:pass:word"
+    assert_eq!(parse_basic_auth(b"basic dXNlcjpwYXNzOndvcmQ="),
+               Some(("user".to_owned(), "pass:word".to_owned())));
+    assert_eq!(parse_basic_auth(b"Bearer QWxhZGRpbjpvcGVuIHNlc2FtZQ=="), None);
+    assert_eq!(parse_basic_auth(b"Basic !!!"), None);
+}
+
+#[test]
+fn bearer_tokens() {
+    assert_eq!(auth_credentials(b"Bearer mF_9.B5f-4.1JqM", "bearer"), Some("mF_9.B5f-4.1JqM"));
+    assert_eq!(auth_credentials(b"bearer  abc ", "bearer"), Some("abc"));
+    assert_eq!(auth_credentials(b"Bearer ", "bearer"), None);
+    assert_eq!(auth_credentials(b"Basic QWxhZGRpbjpvcGVuIHNlc2FtZQ==", "bearer"), None);
+}
+
+#[test]
+fn byte_ranges_within_body() {
+    use hyper::header::ByteRangeSpec::*;
+
+    assert_eq!(satisfiable_ranges(&[FromTo(0, 4), AllFrom(8), Last(3)], 10), vec![(0, 4), (8, 9), (7, 9)]);
+    assert_eq!(satisfiable_ranges(&[FromTo(5, 100), Last(100)], 10), vec![(5, 9), (0, 9)]);
+    assert_eq!(satisfiable_ranges(&[FromTo(10, 12), AllFrom(10), Last(0)], 10), vec![]);
+    assert_eq!(satisfiable_ranges(&[Last(5)], 0), vec![]);
+}
+
+#[test]
+fn decode_charsets() {
+    let text = |bytes: &[u8], charset, lossy| decode(bytes, charset, lossy).ok();
+
+    assert_eq!(text("caf\u{e9}".as_bytes(), None, false), Some("caf\u{e9}".to_owned()));
+    assert_eq!(text(b"caf\xe9", Some("iso-8859-1"), false), Some("caf\u{e9}".to_owned()));
+    assert_eq!(text(b"caf\xe9", None, false), None);
+    assert_eq!(text(b"caf\xe9", Some("us-ascii"), true), Some("caf\u{fffd}".to_owned()));
+    assert_eq!(text(b"cafe", Some("koi8-r"), true), Some("cafe".to_owned()));
+    match decode(b"cafe", Some("koi8-r"), false) {
+        Err(BodyError::UnsupportedCharset(ref charset)) => assert_eq!(charset, "koi8-r"),
+        _ => panic!("koi8-r shouldn't be supported"),
+    }
+}
+
+#[test]
+fn request_ids() {
+    let mut headers = Headers::new();
+    assert!(request_id(&headers) != request_id(&headers));
+
+    headers.set_raw("X-Request-ID", vec![b" abc-123 ".to_vec()]);
+    assert_eq!(request_id(&headers), "abc-123");
+
+    headers.set_raw("X-Request-ID", vec![b"two words".to_vec()]);
+    assert!(request_id(&headers) != "two words");
+}
+
 #[test]
 fn create_multimap_one_key_value_pair() {
     let m = parse_query_string("key=value");
@@ -83,3 +1392,32 @@ fn create_multimap_one_key_multiple_values() {
     assert_eq!(m.get_vec("key"), Some(&vec!["value".into(), "value2".into(), "value3".into()]));
 }
 
+
+#[test]
+fn query_string_is_decoded() {
+    let m = parse_query_string("name=hello+world%21&caf%C3%A9=%E2%9C%93&=skipped&flag");
+
+    assert_eq!(m.len(), 3);
+    assert_eq!(m["name"], "hello world!".to_string());
+    assert_eq!(m["café"], "✓".to_string());
+    assert_eq!(m["flag"], "".to_string());
+}
+
+#[test]
+fn chunked_bodies_over_the_limit() {
+    use hyper::http::h1::HttpReader;
+
+    let chunked = &b"5\r\nhello\r\n6\r\n world\r\n0\r\n\r\n"[..];
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let mut reader = LimitedReader { inner: HttpReader::ChunkedReader(chunked, None), remaining: Some(8), exceeded: exceeded.clone() };
+    let err = reader.read_to_end(&mut Vec::new()).unwrap_err();
+    assert!(match BodyError::from(err) { BodyError::TooLarge => true, _ => false });
+    assert!(exceeded.load(Ordering::Relaxed));
+
+    let exceeded = Arc::new(AtomicBool::new(false));
+    let mut reader = LimitedReader { inner: HttpReader::ChunkedReader(chunked, None), remaining: Some(11), exceeded: exceeded.clone() };
+    let mut body = Vec::new();
+    reader.read_to_end(&mut body).unwrap();
+    assert_eq!(body, b"hello world");
+    assert!(!exceeded.load(Ordering::Relaxed));
+}
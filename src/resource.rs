@@ -0,0 +1,117 @@
+use hyper::method::Method;
+use hyper::mime::Mime;
+
+use routing::{Route, parse_media_range};
+use {Handler, Rask};
+
+/// Registers handlers for a single path, one per set of http methods. Created by
+/// `Rask::route`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn list(_: &Request, _: Response) {
+/// }
+///
+/// fn create(_: &Request, _: Response) {
+/// }
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.route("/items").named("items").get(list).post(create);
+/// ```
+pub struct Resource<'a> {
+    app: &'a mut Rask,
+    route: String,
+    name: Option<String>,
+    accepts: Vec<Mime>,
+}
+
+impl<'a> Resource<'a> {
+    #[doc(hidden)]
+    pub fn new(app: &'a mut Rask, route: &str) -> Resource<'a> {
+        Resource {
+            app: app,
+            route: route.to_owned(),
+            name: None,
+            accepts: Vec::new(),
+        }
+    }
+
+    /// Gives the routes registered from now on a name. See `Rask::register_named`.
+    pub fn named(mut self, name: &str) -> Resource<'a> {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Makes the routes registered from now on accept request bodies of `media_type`, e.g.
+    /// "application/json" or "text/*", in addition to the types given before. Requests with a
+    /// body of another type get a 415 (Unsupported Media Type) error. See `Route::accepting`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::Rask;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn create(_: &Request, _: Response) {
+    /// }
+    ///
+    /// let mut app = Rask::new("SUPER SECRET KEY");
+    /// app.route("/items").accepts("application/json").post(create);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if `media_type` isn't a valid media type.
+    pub fn accepts(mut self, media_type: &str) -> Resource<'a> {
+        match parse_media_range(media_type) {
+            Some(media_type) => self.accepts.push(media_type),
+            None => panic!("Invalid media type {:?}.", media_type),
+        }
+        self
+    }
+
+    /// Registers `handler` for the given http methods.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the route can't be compiled to a valid regex or uses an unknown type.
+    pub fn methods<H: 'static + Handler>(self, methods: &[Method], handler: H) -> Resource<'a> {
+        let mut route = Route::with_methods(&self.route, handler, methods).accepting(&self.accepts);
+        if let Some(ref name) = self.name {
+            route = route.named(name);
+        }
+        self.app.router.add(route);
+        self
+    }
+
+    /// Registers `handler` for GET requests.
+    pub fn get<H: 'static + Handler>(self, handler: H) -> Resource<'a> {
+        self.methods(&[Method::Get], handler)
+    }
+
+    /// Registers `handler` for POST requests.
+    pub fn post<H: 'static + Handler>(self, handler: H) -> Resource<'a> {
+        self.methods(&[Method::Post], handler)
+    }
+
+    /// Registers `handler` for PUT requests.
+    pub fn put<H: 'static + Handler>(self, handler: H) -> Resource<'a> {
+        self.methods(&[Method::Put], handler)
+    }
+
+    /// Registers `handler` for DELETE requests.
+    pub fn delete<H: 'static + Handler>(self, handler: H) -> Resource<'a> {
+        self.methods(&[Method::Delete], handler)
+    }
+
+    /// Registers `handler` for PATCH requests.
+    pub fn patch<H: 'static + Handler>(self, handler: H) -> Resource<'a> {
+        self.methods(&[Method::Patch], handler)
+    }
+}
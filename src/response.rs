@@ -1,42 +1,235 @@
 use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{self, ErrorKind, Read, Write};
 use std::io::Result as IoResult;
 use std::borrow::Cow;
+use std::mem;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::UNIX_EPOCH;
 
 use hyper::server::response::Response as HttpResponse;
+use hyper::method::Method;
 use hyper::status::StatusCode;
-use hyper::net::Fresh;
+use hyper::net::{Fresh, Streaming};
 use hyper::header;
 use hyper::header::Header;
 use hyper::header::HeaderFormat;
+use hyper::mime::{Attr, Mime, SubLevel, TopLevel, Value};
 
-use cookie::CookieJar;
+use cookie::{Cookie, CookieJar};
+
+use time::{self, Timespec};
+
+#[cfg(any(feature = "json", feature = "templates"))]
+use serde::Serialize;
+#[cfg(any(feature = "json", feature = "templates"))]
+use serde_json;
+
+use request::Request;
+use routing::parse_media_range;
+use deflate;
+use cookies::ResponseCookies;
+use session::{Session, SessionStore};
+use static_files::content_type;
+#[cfg(feature = "templates")]
+use templates::TemplateEngine;
 
 /// The struct that holds information about the response.
 pub struct Response<'a, W: Any = Fresh> {
     inner: HttpResponse<'a, W>,
-    cookie_jar: CookieJar<'static>
+    cookie_jar: ResponseCookies,
+    session: Session,
+    session_store: Arc<Box<SessionStore>>,
+    cookies_read: Arc<AtomicBool>,
+    body_too_large: Arc<AtomicBool>,
+    handoff: Option<Handoff<'a>>,
+    sent: Sent,
+    capture: Option<Capture>,
+    before_send: Vec<Box<FnOnce(&mut Response<'a>) + 'a>>,
+    compress_above: Option<u64>,
+    accepted_encoding: Option<header::Encoding>,
+    compress: bool,
+    send_cookies: bool,
+    #[cfg(feature = "json")]
+    pretty_json: bool,
+    #[cfg(feature = "templates")]
+    template_engine: Option<Arc<Box<TemplateEngine>>>,
+}
+
+/// Where a response handed off with `Response::error` waits for the error handler.
+#[doc(hidden)]
+pub type Handoff<'a> = Rc<RefCell<Option<(StatusCode, Response<'a>)>>>;
+
+/// Where a response captured with `Response::capture` is put when it's sent.
+#[doc(hidden)]
+pub type Capture = Rc<RefCell<Option<Captured>>>;
+
+/// A response as it was sent, before compression.
+#[doc(hidden)]
+pub struct Captured {
+    pub status: StatusCode,
+    pub headers: header::Headers,
+    pub body: Vec<u8>,
+}
+
+/// The status and body size of a response once it's sent, see `Response::sent`.
+#[derive(Debug, Clone)]
+pub struct Sent {
+    sent: Rc<Cell<Option<(StatusCode, u64)>>>,
+}
+
+impl Sent {
+    /// The status the response was sent with, `None` until it's sent.
+    pub fn status(&self) -> Option<StatusCode> {
+        self.sent.get().map(|(status, _)| status)
+    }
+
+    /// The number of bytes of the body sent so far.
+    pub fn bytes(&self) -> u64 {
+        self.sent.get().map_or(0, |(_, bytes)| bytes)
+    }
+
+    fn start(&self, status: StatusCode, bytes: u64) {
+        self.sent.set(Some((status, bytes)));
+    }
+
+    fn add(&self, bytes: u64) {
+        if let Some((status, sent)) = self.sent.get() {
+            self.sent.set(Some((status, sent + bytes)));
+        }
+    }
 }
 
+/// A value that can be sent as a response with `Response::send`: text, binary data or a
+/// status, optionally paired with a status and headers. Text is sent as
+/// "text/plain; charset=utf-8" unless another Content-Type is set.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::StatusCode;
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn pixel(_: &Request, res: Response) {
+///     let gif: &[u8] = b"GIF89a\x01\x00\x01\x00\x00\x00\x00;";
+///     res.send((gif, StatusCode::Ok));
+/// }
+///
+/// fn create(_: &Request, res: Response) {
+///     res.send(("created", StatusCode::Created, vec![("Location", "/items/1".to_owned())]));
+/// }
+/// ```
 pub trait Sendable<'a> {
     fn decode(self) -> (Cow<'a, [u8]>, StatusCode);
+
+    /// Sets the status and headers of `res` for the value and returns the body, or the
+    /// status of the error to hand the response off with, see `Response::error`. Defaults to
+    /// the status and body of `decode`.
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> where Self: Sized {
+        let (content, status) = self.decode();
+        res.status(status);
+        Ok(content)
+    }
 }
 
 impl<'a> Sendable<'a> for String {
     fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
         (Cow::Owned(self.into_bytes()), StatusCode::Ok)
     }
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> {
+        res.status(StatusCode::Ok);
+        res.default_content_type(plain_text());
+        Ok(Cow::Owned(self.into_bytes()))
+    }
+}
+
+impl<'a> Sendable<'a> for Vec<u8> {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        (Cow::Owned(self), StatusCode::Ok)
+    }
+}
+
+impl<'a> Sendable<'a> for &'a [u8] {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        (Cow::Borrowed(self), StatusCode::Ok)
+    }
+}
+
+impl<'a> Sendable<'a> for (Vec<u8>, StatusCode) {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        (Cow::Owned(self.0), self.1)
+    }
+}
+
+impl<'a> Sendable<'a> for (&'a [u8], StatusCode) {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        (Cow::Borrowed(self.0), self.1)
+    }
 }
 
 impl<'a> Sendable<'a> for &'a str {
     fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
         (Cow::Borrowed(self.as_bytes()), StatusCode::Ok)
     }
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> {
+        res.status(StatusCode::Ok);
+        res.default_content_type(plain_text());
+        Ok(Cow::Borrowed(self.as_bytes()))
+    }
+}
+
+impl<'a> Sendable<'a> for (String, StatusCode) {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        (Cow::Owned(self.0.into_bytes()), self.1)
+    }
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> {
+        res.status(self.1);
+        res.default_content_type(plain_text());
+        Ok(Cow::Owned(self.0.into_bytes()))
+    }
+}
+
+/// A body, a status and headers, e.g.
+/// `("created", StatusCode::Created, vec![("Location", "/items/1".to_owned())])`. Headers
+/// with the same name are all sent.
+impl<'a, B: Sendable<'a>> Sendable<'a> for (B, StatusCode, Vec<(&'a str, String)>) {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        (self.0.decode().0, self.1)
+    }
+
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> {
+        let (body, status, headers) = self;
+        let content = try!(body.prepare(res));
+        res.status(status);
+        let mut names: Vec<&str> = Vec::new();
+        for (name, value) in headers {
+            if names.contains(&name) {
+                res.append_raw_header(name, &value);
+            } else {
+                res.inner.headers_mut().set_raw(name.to_owned(), vec![escape_line_breaks(&value).into_bytes()]);
+                names.push(name);
+            }
+        }
+        Ok(content)
+    }
 }
 
 impl<'a> Sendable<'a> for (&'a str, StatusCode) {
     fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
         (Cow::Borrowed(self.0.as_bytes()), self.1)
     }
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> {
+        res.status(self.1);
+        res.default_content_type(plain_text());
+        Ok(Cow::Borrowed(self.0.as_bytes()))
+    }
 }
 
 impl<'a> Sendable<'a> for StatusCode {
@@ -45,41 +238,1471 @@ impl<'a> Sendable<'a> for StatusCode {
     }
 }
 
+/// Serializes the value to a json body with serde_json. Failing to serialize the value
+/// hands the response off to the 500 error handler. Requires the `json` feature.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use rask::request::Request;
+/// use rask::response::{Json, Response};
+///
+/// fn item(_: &Request, res: Response) {
+///     let mut item = HashMap::new();
+///     item.insert("name", "lamp");
+///     res.send(Json(item));
+/// }
+/// ```
+#[cfg(feature = "json")]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<'a, T: Serialize> Sendable<'a> for Json<T> {
+    fn decode(self) -> (Cow<'a, [u8]>, StatusCode) {
+        match serde_json::to_vec(&self.0) {
+            Ok(body) => (Cow::Owned(body), StatusCode::Ok),
+            Err(_) => (Cow::Borrowed(&[]), StatusCode::InternalServerError),
+        }
+    }
+
+    fn prepare(self, res: &mut Response) -> Result<Cow<'a, [u8]>, StatusCode> {
+        match res.to_json(&self.0) {
+            Ok(body) => {
+                res.status(StatusCode::Ok);
+                res.set_header(header::ContentType::json());
+                Ok(Cow::Owned(body))
+            },
+            Err(err) => {
+                error!("Couldn't serialize the json response: {}", err);
+                Err(StatusCode::InternalServerError)
+            },
+        }
+    }
+}
+
+fn plain_text() -> Mime {
+    Mime(TopLevel::Text, SubLevel::Plain, vec![(Attr::Charset, Value::Utf8)])
+}
+
+/// What `Response::download` sends: a file, or the bytes of one.
+pub enum Download<'b> {
+    /// The file at the path, from e.g. `Path::new("./report.pdf")`.
+    File(&'b Path),
+    /// The bytes, from e.g. a `Vec<u8>` or a `String`.
+    Bytes(Cow<'b, [u8]>),
+}
+
+impl<'b> From<&'b Path> for Download<'b> {
+    fn from(path: &'b Path) -> Download<'b> {
+        Download::File(path)
+    }
+}
+
+impl<'b> From<&'b PathBuf> for Download<'b> {
+    fn from(path: &'b PathBuf) -> Download<'b> {
+        Download::File(path)
+    }
+}
+
+impl<'b> From<&'b [u8]> for Download<'b> {
+    fn from(bytes: &'b [u8]) -> Download<'b> {
+        Download::Bytes(Cow::Borrowed(bytes))
+    }
+}
+
+impl<'b> From<Vec<u8>> for Download<'b> {
+    fn from(bytes: Vec<u8>) -> Download<'b> {
+        Download::Bytes(Cow::Owned(bytes))
+    }
+}
+
+impl<'b> From<String> for Download<'b> {
+    fn from(text: String) -> Download<'b> {
+        Download::Bytes(Cow::Owned(text.into_bytes()))
+    }
+}
+
+/// The `Content-Disposition` of an attachment named `filename`. Clients that don't support
+/// the utf-8 `filename*` parameter (RFC 5987) get an ascii `filename`.
+fn content_disposition(filename: &str) -> String {
+    let ascii: String = filename.chars().map(|c| match c {
+        '"' | '\\' => '_',
+        c if c.is_ascii() && !c.is_ascii_control() => c,
+        _ => '_',
+    }).collect();
+    let mut disposition = format!("attachment; filename=\"{}\"", ascii);
+    if ascii != filename {
+        disposition.push_str("; filename*=UTF-8''");
+        for &byte in filename.as_bytes() {
+            match byte {
+                b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' |
+                b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|' | b'~' => {
+                    disposition.push(byte as char)
+                },
+                _ => disposition.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+    }
+    disposition
+}
+
+/// Whether bodies of the content type are worth compressing: text, but not e.g. images that
+/// are compressed already.
+fn compressible(content_type: Option<&header::ContentType>) -> bool {
+    match content_type {
+        Some(&header::ContentType(Mime(TopLevel::Text, _, _))) => true,
+        Some(&header::ContentType(Mime(TopLevel::Application, ref sub, _))) => match *sub {
+            SubLevel::Json | SubLevel::Javascript | SubLevel::Xml | SubLevel::WwwFormUrlEncoded => true,
+            SubLevel::Ext(ref sub) => sub.ends_with("+json") || sub.ends_with("+xml"),
+            _ => false,
+        },
+        Some(&header::ContentType(Mime(TopLevel::Image, SubLevel::Ext(ref sub), _))) => sub == "svg+xml",
+        _ => false,
+    }
+}
+
+/// The encoding of the `Accept-Encoding` header to compress bodies with, gzip or deflate, or
+/// brotli with the `brotli` feature.
+#[doc(hidden)]
+pub fn accepted_encoding(headers: &header::Headers) -> Option<header::Encoding> {
+    let accepted = match headers.get::<header::AcceptEncoding>() {
+        Some(&header::AcceptEncoding(ref accepted)) => accepted,
+        None => return None,
+    };
+    let star = header::Encoding::EncodingExt("*".to_owned());
+    let quality = |encoding: &header::Encoding| {
+        accepted.iter()
+            .find(|item| item.item == *encoding)
+            .or_else(|| accepted.iter().find(|item| item.item == star))
+            .map_or(0, |item| item.quality.0)
+    };
+    let (gzip, deflate) = (quality(&header::Encoding::Gzip), quality(&header::Encoding::Deflate));
+    if cfg!(feature = "brotli") {
+        // Only for clients listing `br`, as not every one accepting `*` can decode it.
+        let brotli = header::Encoding::EncodingExt("br".to_owned());
+        let br = accepted.iter().find(|item| item.item == brotli).map_or(0, |item| item.quality.0);
+        if br > 0 && br >= gzip && br >= deflate {
+            return Some(brotli);
+        }
+    }
+    if gzip > 0 && gzip >= deflate {
+        Some(header::Encoding::Gzip)
+    } else if deflate > 0 {
+        Some(header::Encoding::Deflate)
+    } else {
+        None
+    }
+}
+
+/// Whether `name` is a javascript function name, possibly dotted like `app.show`, and
+/// nothing a jsonp callback could be abused with.
+#[cfg(feature = "json")]
+fn is_callback_name(name: &str) -> bool {
+    let identifier = |part: &str| {
+        let mut chars = part.chars();
+        let first = chars.next().map_or(false, |c| c.is_ascii_alphabetic() || c == '_' || c == '$');
+        first && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '$')
+    };
+    name.len() <= 128 && name.split('.').all(identifier)
+}
+
+/// Adds a header by name, after the values it already has. hyper writes the raw values of a
+/// header back to back, so they're joined into one.
+fn append_raw_header(headers: &mut header::Headers, name: &str, value: &str) {
+    let separator: &[u8] = if name.eq_ignore_ascii_case("Set-Cookie") { b"\r\nSet-Cookie: " } else { b", " };
+    let mut joined = headers.get_raw(name).map(|values| values.join(separator)).unwrap_or(Vec::new());
+    if !joined.is_empty() {
+        joined.extend_from_slice(separator);
+    }
+    joined.extend_from_slice(escape_line_breaks(value).as_bytes());
+    headers.set_raw(name.to_owned(), vec![joined]);
+}
+
+/// Adds `name` to the Vary header unless it's listed already or the header is `*`.
+fn add_vary(headers: &mut header::Headers, name: &str) {
+    let listed = headers.get_raw("Vary").map_or(false, |values| values.iter().any(|value| {
+        String::from_utf8_lossy(value)
+            .split(',')
+            .map(|listed| listed.trim())
+            .any(|listed| listed == "*" || listed.eq_ignore_ascii_case(name))
+    }));
+    if !listed {
+        append_raw_header(headers, "Vary", name);
+    }
+}
+
+/// The formatted value of `header` with its line breaks replaced, or `None` if it has none.
+/// The cookies of `SetCookie` are written on lines of their own, so only the line breaks
+/// within a cookie are replaced.
+fn without_line_breaks<H: Header + HeaderFormat>(header: &H) -> Option<String> {
+    let value = header::HeaderFormatter(header).to_string();
+    let separator = "\r\nSet-Cookie: ";
+    let lines: Vec<&str> = if H::header_name().eq_ignore_ascii_case(header::SetCookie::header_name()) {
+        value.split(separator).collect()
+    } else {
+        vec![&value]
+    };
+    if lines.iter().any(|line| line.contains(|c| c == '\r' || c == '\n')) {
+        let lines: Vec<String> = lines.iter().map(|line| escape_line_breaks(line)).collect();
+        Some(lines.join(separator))
+    } else {
+        None
+    }
+}
+
+fn escape_line_breaks(value: &str) -> String {
+    value.replace(|c| c == '\r' || c == '\n', " ")
+}
+
+fn html() -> Mime {
+    Mime(TopLevel::Text, SubLevel::Html, vec![(Attr::Charset, Value::Utf8)])
+}
+
+/// A media type for `Response::content_type`: a `Mime` or a str like "application/json".
+pub trait IntoMime {
+    /// Converts the value to a `Mime`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a str isn't a valid media type.
+    fn into_mime(self) -> Mime;
+}
+
+impl IntoMime for Mime {
+    fn into_mime(self) -> Mime {
+        self
+    }
+}
+
+impl<'b> IntoMime for &'b str {
+    fn into_mime(self) -> Mime {
+        match self.parse() {
+            Ok(mime) => mime,
+            Err(_) => panic!("Invalid media type {:?}.", self),
+        }
+    }
+}
+
 impl<'a> Response<'a, Fresh> {
-    pub fn new(res: HttpResponse<'a, Fresh>, cookie_jar: CookieJar<'static>) -> Response<'a, Fresh> {
+    pub fn new(res: HttpResponse<'a, Fresh>, cookie_jar: CookieJar<'static>, session: Session, session_store: Arc<Box<SessionStore>>) -> Response<'a, Fresh> {
         Response {
             inner: res,
-            cookie_jar: cookie_jar
+            session: session,
+            session_store: session_store,
+            cookies_read: Arc::new(AtomicBool::new(false)),
+            body_too_large: Arc::new(AtomicBool::new(false)),
+            cookie_jar: ResponseCookies::new(cookie_jar),
+            handoff: None,
+            sent: Sent { sent: Rc::new(Cell::new(None)) },
+            capture: None,
+            before_send: Vec::new(),
+            compress_above: None,
+            accepted_encoding: None,
+            compress: true,
+            send_cookies: true,
+            #[cfg(feature = "json")]
+            pretty_json: false,
+            #[cfg(feature = "templates")]
+            template_engine: None,
         }
     }
 
+    /// Makes bodies larger than `above` bytes compressed with `encoding`, see
+    /// `Rask::compress_responses_above`.
+    #[doc(hidden)]
+    pub fn set_compression(&mut self, above: Option<u64>, encoding: Option<header::Encoding>) {
+        self.compress_above = above;
+        self.accepted_encoding = encoding;
+    }
+
+    /// The flag the request sets when its cookies or session are read, which adds Cookie to
+    /// the Vary header.
+    #[doc(hidden)]
+    pub fn cookies_read(&self) -> Arc<AtomicBool> {
+        self.cookies_read.clone()
+    }
+
+    /// The flag the request sets when its body is larger than `Rask::max_body_size`, which
+    /// hands the response off as 413 (Payload Too Large) instead of sending it.
+    #[doc(hidden)]
+    pub fn body_too_large(&self) -> Arc<AtomicBool> {
+        self.body_too_large.clone()
+    }
+
+    /// Sets the engine `render` renders templates with.
+    #[doc(hidden)]
+    #[cfg(feature = "templates")]
+    pub fn set_template_engine(&mut self, engine: Option<Arc<Box<TemplateEngine>>>) {
+        self.template_engine = engine;
+    }
+
+    /// Makes `error` hand the response off through the returned slot instead of sending it.
+    #[doc(hidden)]
+    pub fn handoff(&mut self) -> Handoff<'a> {
+        let handoff = Rc::new(RefCell::new(None));
+        self.handoff = Some(handoff.clone());
+        handoff
+    }
+
+    /// The status and body size of the response once it's sent, for middleware that runs
+    /// after the handler, e.g. `middleware::AccessLog`.
+    pub fn sent(&self) -> Sent {
+        self.sent.clone()
+    }
+
+    /// Makes the response, if it's sent whole rather than streamed, be put in the returned
+    /// slot as it's sent, e.g. for `middleware::ResponseCache`.
+    #[doc(hidden)]
+    pub fn capture(&mut self) -> Capture {
+        let capture = Rc::new(RefCell::new(None));
+        self.capture = Some(capture.clone());
+        capture
+    }
+
+    /// Makes `error` send the response again, undoing `handoff`.
+    #[doc(hidden)]
+    pub fn end_handoff(&mut self) {
+        self.handoff = None;
+    }
+
     pub fn status(&mut self, status: StatusCode) {
         *self.inner.status_mut() = status;
     }
 
+    /// Sets a header, replacing an earlier value of it. Line breaks in the value, e.g. from
+    /// user input in a redirect target, are replaced by spaces so they can't add headers.
     pub fn set_header<H: Header + HeaderFormat>(&mut self, header: H) {
-        self.inner.headers_mut().set(header);
+        match without_line_breaks(&header) {
+            Some(value) => {
+                warn!("Replaced line breaks in the value of the {} header.", H::header_name());
+                self.inner.headers_mut().set_raw(H::header_name(), vec![value.into_bytes()]);
+            },
+            None => self.inner.headers_mut().set(header),
+        }
+    }
+
+    /// Sets the header `name` to `value`, replacing an earlier value of it, for headers
+    /// without a type in `header`. Line breaks in the value are replaced by spaces.
+    pub fn set_raw_header(&mut self, name: &str, value: &str) {
+        self.inner.headers_mut().remove_raw(name);
+        self.append_raw_header(name, value);
+    }
+
+    /// Calls `hook` with the response right before it's sent, after the handler, e.g. for
+    /// middleware to add headers that depend on how the response was handled. Hooks are
+    /// called in the order they're added.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn index(_: &Request, mut res: Response) {
+    ///     res.before_send(|res| res.set_raw_header("X-Cache", "miss"));
+    ///     res.send("Hello");
+    /// }
+    /// ```
+    pub fn before_send<F: 'a + FnOnce(&mut Response<'a>)>(&mut self, hook: F) {
+        self.before_send.push(Box::new(hook));
+    }
+
+    /// The headers set on the response so far.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::header::ContentType;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn index(_: &Request, mut res: Response) {
+    ///     if !res.headers().has::<ContentType>() {
+    ///         res.content_type("text/html");
+    ///     }
+    ///     res.send("<h1>Hello</h1>");
+    /// }
+    /// ```
+    pub fn headers(&self) -> &header::Headers {
+        self.inner.headers()
+    }
+
+    /// Removes the header `H`, returning whether it was set. Removing `SetCookie` drops
+    /// the cookies and session changes of the response, which are otherwise sent with it.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::header::SetCookie;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn cached_page(_: &Request, mut res: Response) {
+    ///     // shared caches mustn't store anyone's cookies
+    ///     res.remove_header::<SetCookie>();
+    ///     res.send("the same for everyone");
+    /// }
+    /// ```
+    pub fn remove_header<H: Header + HeaderFormat>(&mut self) -> bool {
+        if H::header_name().eq_ignore_ascii_case(header::SetCookie::header_name()) {
+            self.send_cookies = false;
+        }
+        self.inner.headers_mut().remove::<H>()
+    }
+
+    /// Sets the Content-Type header.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::mime::{Mime, SubLevel, TopLevel};
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn feed(_: &Request, mut res: Response) {
+    ///     res.content_type("application/atom+xml; charset=utf-8");
+    ///     res.send("<feed/>");
+    /// }
+    ///
+    /// fn config(_: &Request, mut res: Response) {
+    ///     res.content_type(Mime(TopLevel::Application, SubLevel::Json, vec![]));
+    ///     res.send("{}");
+    /// }
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if a str isn't a valid media type.
+    pub fn content_type<M: IntoMime>(&mut self, media_type: M) {
+        self.set_header(header::ContentType(media_type.into_mime()));
+    }
+
+    /// Sets the Content-Type header to `media_type` unless it's already set.
+    fn default_content_type(&mut self, media_type: Mime) {
+        if !self.inner.headers().has::<header::ContentType>() {
+            self.content_type(media_type);
+        }
     }
 
-    pub fn cookies<'b>(&'b mut self) -> &'b mut CookieJar<'static> {
+    /// Whether the body may be compressed, see `Rask::compress_responses_above`. On by
+    /// default, turn it off e.g. for bodies that mustn't be cached in a compressed form.
+    pub fn compress(&mut self, compress: bool) {
+        self.compress = compress;
+    }
+
+    /// The cookies of the response. Cookies added, e.g. built with `cookies::CookieBuilder`
+    /// for their attributes, or deleted are sent with the response.
+    pub fn cookies<'b>(&'b mut self) -> &'b mut ResponseCookies {
+        self.cookies_read.store(true, Ordering::Relaxed);
         &mut self.cookie_jar
     }
 
-    pub fn send<S: 'a + Sendable<'a>>(mut self, s: S) -> IoResult<()> {
-        let cookie = header::SetCookie::from_cookie_jar(&self.cookie_jar);
+    /// The session of the client, see `session::Session`. Changes are sent with the response.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn login(_: &Request, mut res: Response) {
+    ///     res.session().set("username", "bob");
+    ///     res.redirect("/");
+    /// }
+    /// ```
+    pub fn session(&mut self) -> &mut Session {
+        self.cookies_read.store(true, Ordering::Relaxed);
+        &mut self.session
+    }
+
+    pub fn send<'s, S: 's + Sendable<'s>>(mut self, s: S) -> IoResult<()> {
+        let content = match s.prepare(&mut self) {
+            Ok(content) => content,
+            Err(status) => return self.error(status),
+        };
+        self.finish(content)
+    }
+
+    /// Adds `header` to the Vary header, which tells caches that the response depends on
+    /// the value of that request header. Headers already listed aren't added again. Accept,
+    /// Accept-Encoding and Cookie are added when the response is negotiated, compressed or
+    /// reads the cookies or session.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn greeting(req: &Request, mut res: Response) {
+    ///     res.vary("Accept-Language");
+    ///     match req.preferred_languages().first() {
+    ///         Some(language) if language.starts_with("nb") => res.send("Hei"),
+    ///         _ => res.send("Hello"),
+    ///     };
+    /// }
+    /// ```
+    pub fn vary(&mut self, header: &str) {
+        add_vary(self.inner.headers_mut(), header);
+    }
+
+    /// Sets the ETag header, e.g. for `send_with_etag` to compare instead of computing one.
+    pub fn etag(&mut self, tag: header::EntityTag) {
+        self.set_header(header::ETag(tag));
+    }
+
+    /// Sends `body` with an ETag, or just 304 (Not Modified) if the ETag matches the
+    /// `If-None-Match` header of a GET or HEAD request. The ETag is a hash of the body,
+    /// unless one was set with `etag`. Only successful responses get an ETag.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn report(req: &Request, res: Response) {
+    ///     let report = "...an expensive report".to_owned();
+    ///     res.send_with_etag(&report[..], req);
+    /// }
+    /// ```
+    pub fn send_with_etag<'s, S: 's + Sendable<'s>>(mut self, body: S, req: &Request) -> IoResult<()> {
+        let content = match body.prepare(&mut self) {
+            Ok(content) => content,
+            Err(status) => return self.error(status),
+        };
+        if !self.inner.status().is_success() {
+            return self.finish(content);
+        }
+
+        let tag = match self.inner.headers().get::<header::ETag>() {
+            Some(&header::ETag(ref tag)) => tag.clone(),
+            None => {
+                let mut hasher = DefaultHasher::new();
+                hasher.write(&content);
+                header::EntityTag::strong(format!("{:016x}", hasher.finish()))
+            },
+        };
+        self.etag(tag.clone());
+
+        let cacheable = *req.method() == Method::Get || *req.method() == Method::Head;
+        let matches = match req.if_none_match() {
+            Some(&header::IfNoneMatch::Any) => true,
+            Some(&header::IfNoneMatch::Items(ref tags)) => tags.iter().any(|t| t.weak_eq(&tag)),
+            None => false,
+        };
+        if cacheable && matches {
+            self.status(StatusCode::NotModified);
+            self.finish(Cow::Borrowed(&[]))
+        } else {
+            self.finish(content)
+        }
+    }
+
+    /// Starts building the response, for setting its status, headers and cookies in any
+    /// order before sending the body. See `ResponseBuilder`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::{Cookie, StatusCode};
+    /// use rask::header::Location;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn create(_: &Request, res: Response) {
+    ///     let _ = res.build()
+    ///         .status(StatusCode::Created)
+    ///         .header(Location("/items/1".to_owned()))
+    ///         .cookie(Cookie::new("last".to_owned(), "1".to_owned()))
+    ///         .body("created");
+    /// }
+    /// ```
+    pub fn build(self) -> ResponseBuilder<'a> {
+        ResponseBuilder { res: self, status: None }
+    }
+
+    /// Sends the head of the response and returns a writer for the body, which is sent in
+    /// chunks as it's written unless a `ContentLength` header was set. See `ResponseStream`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::io::Write;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn export(_: &Request, res: Response) {
+    ///     let mut stream = res.stream().unwrap();
+    ///     for i in 0..1000 {
+    ///         writeln!(stream, "{},item {}", i, i).unwrap();
+    ///     }
+    ///     stream.end().unwrap();
+    /// }
+    /// ```
+    pub fn stream(mut self) -> IoResult<ResponseStream<'a>> {
+        self.run_before_send();
+        self.write_cookies();
+        self.sent.start(self.inner.status(), 0);
+        let inner = try!(self.inner.start());
+        Ok(ResponseStream { inner: inner, sent: self.sent })
+    }
+
+    /// Streams everything read from `reader` as the body, see `Response::stream`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::process::{Command, Stdio};
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn log(_: &Request, res: Response) {
+    ///     let child = Command::new("journalctl").stdout(Stdio::piped()).spawn().unwrap();
+    ///     res.send_reader(child.stdout.unwrap());
+    /// }
+    /// ```
+    pub fn send_reader<R: Read>(self, mut reader: R) -> IoResult<()> {
+        let mut stream = try!(self.stream());
+        try!(io::copy(&mut reader, &mut stream));
+        stream.end()
+    }
+
+    /// Starts a `text/event-stream` body for sending Server-Sent Events to the client, e.g.
+    /// with the `EventSource` of a browser. See `EventStream`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::thread;
+    /// use std::time::Duration;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn clock(_: &Request, res: Response) {
+    ///     let mut events = res.event_stream().unwrap();
+    ///     for tick in 0..10 {
+    ///         if events.send_event("tick", &tick.to_string()).is_err() {
+    ///             // the client is gone
+    ///             return;
+    ///         }
+    ///         thread::sleep(Duration::from_secs(1));
+    ///     }
+    /// }
+    /// ```
+    pub fn event_stream(mut self) -> IoResult<EventStream<'a>> {
+        self.content_type(Mime(TopLevel::Text, SubLevel::EventStream, vec![]));
+        self.set_header(header::CacheControl(vec![header::CacheDirective::NoCache]));
+        // keeps proxies like nginx from holding the events back
+        self.append_raw_header("X-Accel-Buffering", "no");
+        let stream = try!(self.stream());
+        Ok(EventStream { stream: stream })
+    }
+
+    /// Sends the representation of the response the client prefers according to the
+    /// `Accept` header of `req`, see `RespondTo`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn item(req: &Request, res: Response) {
+    ///     res.respond_to(req)
+    ///         .html(|res| res.send("<h1>lamp</h1>"))
+    ///         .json(|res| res.send(r#"{"name":"lamp"}"#))
+    ///         .send();
+    /// }
+    /// ```
+    pub fn respond_to<'c>(self, req: &Request) -> RespondTo<'a, 'c> {
+        let accept = req.headers().get_raw("Accept").map(|values| {
+            values.iter().filter_map(|value| ::std::str::from_utf8(value).ok()).collect::<Vec<_>>().join(",")
+        });
+        RespondTo {
+            res: self,
+            ranges: accept.map(|accept| media_ranges(&accept)),
+            responders: Vec::new(),
+        }
+    }
+
+    /// Streams the file at `path` as the body, with its Content-Type guessed from the
+    /// extension unless another one is set, its Content-Length and, if the file system has
+    /// it, its Last-Modified time. A missing file or a directory is handed off as 404 (Not
+    /// Found), other errors as 500 (Internal Server Error), see `Response::error`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn report(_: &Request, res: Response) {
+    ///     res.send_file("./reports/latest.pdf");
+    /// }
+    /// ```
+    pub fn send_file<P: AsRef<Path>>(mut self, path: P) -> IoResult<()> {
+        let path = path.as_ref();
+        let opened = File::open(path).and_then(|file| file.metadata().map(|metadata| (file, metadata)));
+        let (file, metadata) = match opened {
+            Ok((_, ref metadata)) if metadata.is_dir() => return self.error(StatusCode::NotFound),
+            Ok(opened) => opened,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return self.error(StatusCode::NotFound),
+            Err(err) => {
+                error!("Couldn't open {:?}: {}", path, err);
+                return self.error(StatusCode::InternalServerError);
+            },
+        };
+
+        self.default_content_type(content_type(path));
+        self.set_header(header::ContentLength(metadata.len()));
+        if let Some(modified) = metadata.modified().ok().and_then(|time| time.duration_since(UNIX_EPOCH).ok()) {
+            let modified = time::at_utc(Timespec::new(modified.as_secs() as i64, 0));
+            self.set_header(header::LastModified(header::HttpDate(modified)));
+        }
+        self.send_reader(file)
+    }
+
+    /// Sends a file, or bytes, for the client to save as `filename` instead of displaying it.
+    /// The Content-Type is guessed from the extension of `filename` unless another one is
+    /// set. A file is sent like with `send_file`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::path::{Path, PathBuf};
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn export(_: &Request, res: Response) {
+    ///     let csv = b"id,name\n1,lamp\n".to_vec();
+    ///     res.download(csv, "items.csv");
+    /// }
+    ///
+    /// fn invoice(_: &Request, res: Response) {
+    ///     res.download(Path::new("./invoices/1042.pdf"), "Faktura 1042.pdf");
+    /// }
+    ///
+    /// fn report(req: &Request, res: Response) {
+    ///     let name = req.vars.get("name").unwrap();
+    ///     let path = PathBuf::from("./reports").join(format!("{}.csv", name));
+    ///     res.download(&path, &format!("{}.csv", name));
+    /// }
+    /// ```
+    pub fn download<'d, D: Into<Download<'d>>>(mut self, download: D, filename: &str) -> IoResult<()> {
+        self.default_content_type(content_type(Path::new(filename)));
+        self.inner.headers_mut().set_raw("Content-Disposition", vec![content_disposition(filename).into_bytes()]);
+        match download.into() {
+            Download::File(path) => self.send_file(path),
+            Download::Bytes(bytes) => self.send(bytes.into_owned()),
+        }
+    }
+
+    /// Compresses `content` if compression is on, it's large enough, its Content-Type is
+    /// worth compressing and the client accepts a compressed body.
+    fn compressed<'c>(&mut self, content: Cow<'c, [u8]>) -> Cow<'c, [u8]> {
+        let large_enough = self.compress_above.map_or(false, |above| content.len() as u64 > above);
+        let status = self.inner.status();
+        if !self.compress || !large_enough || status == StatusCode::NoContent || status == StatusCode::NotModified ||
+            self.inner.headers().has::<header::ContentEncoding>() ||
+            !compressible(self.inner.headers().get::<header::ContentType>())
+        {
+            return content;
+        }
+
+        self.vary("Accept-Encoding");
+        let compressed = match self.accepted_encoding {
+            Some(header::Encoding::Gzip) => deflate::gzip(&content),
+            Some(header::Encoding::Deflate) => deflate::zlib(&content),
+            #[cfg(feature = "brotli")]
+            Some(header::Encoding::EncodingExt(ref ext)) if ext == "br" => deflate::brotli(&content),
+            _ => return content,
+        };
+        if compressed.len() >= content.len() {
+            return content;
+        }
+
+        let encoding = self.accepted_encoding.clone().unwrap();
+        self.set_header(header::ContentEncoding(vec![encoding]));
+        // the compressed body isn't byte for byte the one the tag was made for
+        let weakened = self.inner.headers().get::<header::ETag>().map(|&header::ETag(ref tag)| {
+            header::EntityTag::weak(tag.tag().to_owned())
+        });
+        if let Some(tag) = weakened {
+            self.etag(tag);
+        }
+        Cow::Owned(compressed)
+    }
+
+    /// Adds a header by name, after the values it already has.
+    fn append_raw_header(&mut self, name: &str, value: &str) {
+        append_raw_header(self.inner.headers_mut(), name, value);
+    }
+
+    /// Calls the `before_send` hooks, including the ones they add.
+    fn run_before_send(&mut self) {
+        while !self.before_send.is_empty() {
+            for hook in mem::replace(&mut self.before_send, Vec::new()) {
+                hook(self);
+            }
+        }
+    }
+
+    fn write_cookies(&mut self) {
+        if self.cookies_read.load(Ordering::Relaxed) {
+            self.vary("Cookie");
+        }
+        if !self.send_cookies {
+            return;
+        }
+        if let Err(err) = self.session.save(&**self.session_store, &self.cookie_jar) {
+            error!("Couldn't save the session: {}", err);
+        }
+        let cookie = header::SetCookie(self.cookie_jar.delta());
         self.set_header(cookie);
+    }
+
+    fn finish(mut self, content: Cow<[u8]>) -> IoResult<()> {
+        if self.handoff.is_some() && self.body_too_large.load(Ordering::Relaxed) {
+            return self.error(StatusCode::PayloadTooLarge);
+        }
+        self.run_before_send();
+        self.write_cookies();
+        if let Some(capture) = self.capture.take() {
+            *capture.borrow_mut() = Some(Captured {
+                status: self.inner.status(),
+                headers: self.inner.headers().clone(),
+                body: content.to_vec(),
+            });
+        }
+        let content = self.compressed(content);
+
+        let status = self.inner.status();
+        if status == StatusCode::NoContent || status == StatusCode::NotModified {
+            // may not have a body, or a Content-Length that says it has none
+            if content.len() > 0 {
+                debug!("Dropped the body of a {} response.", status);
+            }
+            self.inner.headers_mut().remove::<header::ContentLength>();
+            self.sent.start(status, 0);
+            return self.inner.start().and_then(|stream| stream.end());
+        }
 
-        let (content, status) = s.decode();
-        self.status(status);
         if content.len() > 0 {
             self.set_header(header::ContentLength(content.len() as u64));
         }
+        self.sent.start(status, content.len() as u64);
         self.inner.send(&content)
     }
 
-    pub fn redirect(mut self, path: &str) -> IoResult<()> {
+    /// Sends `body` as html, with the Content-Type "text/html; charset=utf-8" unless another
+    /// one is set.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn index(_: &Request, res: Response) {
+    ///     res.html("<h1>Hello, wörld</h1>");
+    /// }
+    /// ```
+    pub fn html<'s, S: 's + Sendable<'s>>(mut self, body: S) -> IoResult<()> {
+        self.default_content_type(html());
+        self.send(body)
+    }
+
+    /// Sends `body` as plain text, with the Content-Type "text/plain; charset=utf-8" unless
+    /// another one is set. Strings are sent as plain text by `send` too, but `text` also
+    /// marks e.g. bytes as text.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn robots(_: &Request, res: Response) {
+    ///     res.text(b"User-agent: *\nDisallow: /admin\n".to_vec());
+    /// }
+    /// ```
+    pub fn text<'s, S: 's + Sendable<'s>>(mut self, body: S) -> IoResult<()> {
+        self.default_content_type(plain_text());
+        self.send(body)
+    }
+
+    /// Serializes `value` to a json body, see `Json`. Requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn item(_: &Request, res: Response) {
+    ///     let mut item = HashMap::new();
+    ///     item.insert("name", "lamp");
+    ///     res.json(&item);
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn json<T: Serialize>(mut self, value: &T) -> IoResult<()> {
+        match self.to_json(value) {
+            Ok(body) => {
+                self.set_header(header::ContentType::json());
+                self.send(body)
+            },
+            Err(err) => {
+                error!("Couldn't serialize the json response: {}", err);
+                self.error(StatusCode::InternalServerError)
+            },
+        }
+    }
+
+    /// Renders the template `name` with `context` as the body, see `Rask::templates`. The
+    /// Content-Type is guessed from the extension of `name`, defaulting to html, unless
+    /// another one is set. Failing to render the template hands the response off to the 500
+    /// error handler. Requires the `templates` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn profile(req: &Request, res: Response) {
+    ///     let mut context = HashMap::new();
+    ///     context.insert("name", req.vars.get("name").unwrap());
+    ///     res.render("profile.html", &context);
+    /// }
+    /// ```
+    #[cfg(feature = "templates")]
+    pub fn render<T: Serialize>(mut self, name: &str, context: &T) -> IoResult<()> {
+        let rendered = match (self.template_engine.clone(), serde_json::to_value(context)) {
+            (Some(engine), Ok(context)) => engine.render(name, &context).map_err(|err| err.to_string()),
+            (None, _) => Err("No template engine is registered.".to_owned()),
+            (_, Err(err)) => Err(format!("Couldn't serialize the context: {}", err)),
+        };
+        match rendered {
+            Ok(body) => {
+                let media_type = match content_type(Path::new(name)) {
+                    Mime(TopLevel::Application, SubLevel::Ext(ref ext), _) if ext == "octet-stream" => html(),
+                    media_type => media_type,
+                };
+                self.default_content_type(media_type);
+                self.send(body)
+            },
+            Err(err) => {
+                error!("Couldn't render the template {:?}: {}", name, err);
+                self.error(StatusCode::InternalServerError)
+            },
+        }
+    }
+
+    /// Serializes `value` to json wrapped in a call to the function named by the query
+    /// string parameter `callback_param` of `req`, for clients that load it with a script tag.
+    /// Without the parameter the json is sent as is, with an invalid function name the
+    /// response is handed off as 400 (Bad Request). Requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// // GET /items/1?callback=showItem -> /**/ showItem({"name":"lamp"});
+    /// fn item(req: &Request, res: Response) {
+    ///     let mut item = HashMap::new();
+    ///     item.insert("name", "lamp");
+    ///     res.jsonp(&item, req, "callback");
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn jsonp<T: Serialize>(mut self, value: &T, req: &Request, callback_param: &str) -> IoResult<()> {
+        let callback = match req.gets.get(callback_param) {
+            Some(callback) => callback,
+            None => return self.json(value),
+        };
+        if !is_callback_name(callback) {
+            debug!("Invalid jsonp callback {:?}.", callback);
+            return self.error(StatusCode::BadRequest);
+        }
+        let json = match self.to_json(value) {
+            Ok(json) => String::from_utf8(json).unwrap(),
+            Err(err) => {
+                error!("Couldn't serialize the json response: {}", err);
+                return self.error(StatusCode::InternalServerError);
+            },
+        };
+        // valid in json, but line breaks in older javascript
+        let json = json.replace('\u{2028}', "\\u2028").replace('\u{2029}', "\\u2029");
+
+        self.content_type(Mime(TopLevel::Application, SubLevel::Javascript, vec![(Attr::Charset, Value::Utf8)]));
+        self.append_raw_header("X-Content-Type-Options", "nosniff");
+        // the comment keeps the body from starting with bytes of the client's choosing
+        self.send(format!("/**/ {}({});", callback, json))
+    }
+
+    /// Whether json bodies are indented for reading them, see `Rask::pretty_json`.
+    /// Requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn item(req: &Request, mut res: Response) {
+    ///     let mut item = HashMap::new();
+    ///     item.insert("name", "lamp");
+    ///     res.pretty_json(req.gets.get("pretty").is_some());
+    ///     res.json(&item);
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn pretty_json(&mut self, pretty: bool) {
+        self.pretty_json = pretty;
+    }
+
+    #[cfg(feature = "json")]
+    fn to_json<T: Serialize>(&self, value: &T) -> serde_json::Result<Vec<u8>> {
+        if self.pretty_json {
+            serde_json::to_vec_pretty(value)
+        } else {
+            serde_json::to_vec(value)
+        }
+    }
+
+    /// Hands the response off to the error handler registered for `status`, see
+    /// `Rask::register_error_handler`. Without a registered handler, or when called from an
+    /// error handler, just `status` is sent.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::StatusCode;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn user(req: &Request, res: Response) {
+    ///     match req.vars.get("name") {
+    ///         Some(name) if name == "bob" => res.send("Hello, bob"),
+    ///         _ => res.error(StatusCode::NotFound),
+    ///     };
+    /// }
+    /// ```
+    pub fn error(mut self, status: StatusCode) -> IoResult<()> {
+        match self.handoff.take() {
+            Some(handoff) => {
+                *handoff.borrow_mut() = Some((status, self));
+                Ok(())
+            },
+            None => self.send(status),
+        }
+    }
+
+    /// Sends 204 (No Content), e.g. for a successful DELETE.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn delete(_: &Request, res: Response) {
+    ///     // ...delete the item
+    ///     res.no_content();
+    /// }
+    /// ```
+    pub fn no_content(self) -> IoResult<()> {
+        self.send(StatusCode::NoContent)
+    }
+
+    /// Sends 304 (Not Modified), for a conditional request whose cached copy is still fresh.
+    /// See `send_with_etag` for doing it automatically.
+    pub fn not_modified(self) -> IoResult<()> {
+        self.send(StatusCode::NotModified)
+    }
+
+    /// Sends 202 (Accepted) without a body, for a request that's processed later.
+    pub fn accepted(self) -> IoResult<()> {
+        self.send(StatusCode::Accepted)
+    }
+
+    /// Redirects to `path` with 302 (Found).
+    pub fn redirect(self, path: &str) -> IoResult<()> {
+        self.redirect_with_status(path, StatusCode::Found)
+    }
+
+    /// Redirects to `path` with 301 (Moved Permanently), for resources that have moved for
+    /// good.
+    pub fn redirect_permanent(self, path: &str) -> IoResult<()> {
+        self.redirect_with_status(path, StatusCode::MovedPermanently)
+    }
+
+    /// Redirects to `path` with 303 (See Other), which clients follow with a GET, e.g. after
+    /// handling a form POST.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn create(_: &Request, res: Response) {
+    ///     // ...store the item
+    ///     res.redirect_see_other("/items/1");
+    /// }
+    /// ```
+    pub fn redirect_see_other(self, path: &str) -> IoResult<()> {
+        self.redirect_with_status(path, StatusCode::SeeOther)
+    }
+
+    /// Redirects to `path` with `status`, e.g. 307 (Temporary Redirect).
+    pub fn redirect_with_status(mut self, path: &str, status: StatusCode) -> IoResult<()> {
         self.set_header(header::Location(path.to_owned()));
-        self.send(StatusCode::Found)
+        self.send(status)
+    }
+}
+
+/// The body of a response being sent, created with `Response::stream`. Writes are sent to
+/// the client as they're made.
+pub struct ResponseStream<'a> {
+    inner: HttpResponse<'a, Streaming>,
+    sent: Sent,
+}
+
+impl<'a> ResponseStream<'a> {
+    /// Finishes the body. Dropping the stream finishes it too, but ignores errors.
+    pub fn end(self) -> IoResult<()> {
+        self.inner.end()
+    }
+}
+
+impl<'a> Write for ResponseStream<'a> {
+    fn write(&mut self, buf: &[u8]) -> IoResult<usize> {
+        let written = try!(self.inner.write(buf));
+        self.sent.add(written as u64);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> IoResult<()> {
+        self.inner.flush()
+    }
+}
+
+/// The representations of a response to choose from by the `Accept` header of the request,
+/// created with `Response::respond_to`. Each responder sends the response, with the
+/// Content-Type already set to its media type. The responder for the media type the client
+/// prefers most is called, the first one if the request doesn't say, or if the client
+/// accepts none of them the response is handed off as 406 (Not Acceptable).
+pub struct RespondTo<'a, 'c> {
+    res: Response<'a>,
+    ranges: Option<Vec<(Mime, f32)>>,
+    responders: Vec<(Mime, Box<FnOnce(Response<'a>) -> IoResult<()> + 'c>)>,
+}
+
+impl<'a, 'c> RespondTo<'a, 'c> {
+    /// Adds the responder for `media_type`.
+    pub fn on<M: IntoMime, F>(mut self, media_type: M, responder: F) -> RespondTo<'a, 'c>
+        where F: FnOnce(Response<'a>) -> IoResult<()> + 'c
+    {
+        self.responders.push((media_type.into_mime(), Box::new(responder)));
+        self
+    }
+
+    /// Adds the responder for "application/json".
+    pub fn json<F: FnOnce(Response<'a>) -> IoResult<()> + 'c>(self, responder: F) -> RespondTo<'a, 'c> {
+        self.on(Mime(TopLevel::Application, SubLevel::Json, vec![]), responder)
+    }
+
+    /// Adds the responder for html.
+    pub fn html<F: FnOnce(Response<'a>) -> IoResult<()> + 'c>(self, responder: F) -> RespondTo<'a, 'c> {
+        self.on(html(), responder)
+    }
+
+    /// Adds the responder for plain text.
+    pub fn text<F: FnOnce(Response<'a>) -> IoResult<()> + 'c>(self, responder: F) -> RespondTo<'a, 'c> {
+        self.on(plain_text(), responder)
+    }
+
+    /// Calls the responder for the preferred media type.
+    pub fn send(self) -> IoResult<()> {
+        let RespondTo { mut res, ranges, responders } = self;
+        let chosen = match ranges {
+            Some(ref ranges) => preferred(ranges, responders.iter().map(|&(ref media_type, _)| media_type)),
+            None if responders.is_empty() => None,
+            None => Some(0),
+        };
+        match chosen {
+            Some(index) => {
+                let (media_type, responder) = responders.into_iter().nth(index).unwrap();
+                res.vary("Accept");
+                res.content_type(media_type);
+                responder(res)
+            },
+            None => res.error(StatusCode::NotAcceptable),
+        }
+    }
+}
+
+/// The media ranges of an `Accept` header with their quality. Ranges that can't be parsed
+/// are left out.
+fn media_ranges(accept: &str) -> Vec<(Mime, f32)> {
+    accept.split(',').filter_map(|item| {
+        let mut params = item.split(';');
+        let range = match params.next().map(|range| range.trim()).and_then(parse_media_range) {
+            Some(Mime(top, sub, _)) => Mime(top, sub, vec![]),
+            None => return None,
+        };
+        let quality = params
+            .filter_map(|param| {
+                let param = param.trim();
+                if param.starts_with("q=") { param[2..].parse().ok() } else { None }
+            })
+            .next()
+            .unwrap_or(1.0);
+        Some((range, quality))
+    }).collect()
+}
+
+/// The index of the media type the ranges prefer most, by the quality of the most specific
+/// range matching each. The first one wins a tie.
+fn preferred<'m, I: Iterator<Item = &'m Mime>>(ranges: &[(Mime, f32)], media_types: I) -> Option<usize> {
+    let mut best: Option<(usize, f32)> = None;
+    for (index, &Mime(ref top, ref sub, _)) in media_types.enumerate() {
+        let quality = ranges.iter()
+            .filter_map(|&(Mime(ref range_top, ref range_sub, _), quality)| {
+                let specificity = match (range_top, range_sub) {
+                    (&TopLevel::Star, _) => 0,
+                    (range_top, &SubLevel::Star) if range_top == top => 1,
+                    (range_top, range_sub) if range_top == top && range_sub == sub => 2,
+                    _ => return None,
+                };
+                Some((specificity, quality))
+            })
+            .fold(None, |most: Option<(u8, f32)>, (specificity, quality)| match most {
+                Some((most_specific, _)) if most_specific >= specificity => most,
+                _ => Some((specificity, quality)),
+            })
+            .map_or(0.0, |(_, quality)| quality);
+        if quality > 0.0 && best.map_or(true, |(_, best_quality)| quality > best_quality) {
+            best = Some((index, quality));
+        }
+    }
+    best.map(|(index, _)| index)
+}
+
+/// A stream of Server-Sent Events, created with `Response::event_stream`. Every event is
+/// flushed to the client when it's sent. Proxies and clients may close a connection that's
+/// idle for long, which `keep_alive` prevents when called now and then.
+pub struct EventStream<'a> {
+    stream: ResponseStream<'a>,
+}
+
+impl<'a> EventStream<'a> {
+    /// Sends an event named `name` with `data`, which may span several lines. Fails when the
+    /// client has gone away.
+    pub fn send_event(&mut self, name: &str, data: &str) -> IoResult<()> {
+        let mut event = format!("event: {}\n", escape_line_breaks(name));
+        push_data(&mut event, data);
+        self.write(&event)
+    }
+
+    /// Sends an unnamed event, which the `onmessage` handler of an `EventSource` receives.
+    pub fn send_data(&mut self, data: &str) -> IoResult<()> {
+        let mut event = String::new();
+        push_data(&mut event, data);
+        self.write(&event)
+    }
+
+    /// Sends a comment, which clients ignore, to keep the connection from being idle.
+    pub fn keep_alive(&mut self) -> IoResult<()> {
+        self.write(": keep-alive\n\n")
+    }
+
+    /// Ends the stream. The client will reconnect unless it closes the `EventSource`.
+    pub fn end(self) -> IoResult<()> {
+        self.stream.end()
+    }
+
+    fn write(&mut self, text: &str) -> IoResult<()> {
+        try!(self.stream.write_all(text.as_bytes()));
+        self.stream.flush()
+    }
+}
+
+/// Adds a `data` line for each line of `data`, and the blank line that ends an event.
+fn push_data(event: &mut String, data: &str) {
+    for line in data.split('\n') {
+        event.push_str("data: ");
+        event.push_str(line.trim_right_matches('\r'));
+        event.push('\n');
+    }
+    event.push('\n');
+}
+
+/// Assembles a response before sending it, created with `Response::build`. Unlike with
+/// `Response::send`, the status set on the builder isn't replaced by the status of the body.
+pub struct ResponseBuilder<'a> {
+    res: Response<'a>,
+    status: Option<StatusCode>,
+}
+
+impl<'a> ResponseBuilder<'a> {
+    /// Sets the status, 200 (OK) by default.
+    pub fn status(mut self, status: StatusCode) -> ResponseBuilder<'a> {
+        self.status = Some(status);
+        self
+    }
+
+    /// Sets a header, replacing an earlier value of it.
+    pub fn header<H: Header + HeaderFormat>(mut self, header: H) -> ResponseBuilder<'a> {
+        self.res.set_header(header);
+        self
+    }
+
+    /// Sets the Content-Type header, see `Response::content_type`.
+    pub fn content_type<M: IntoMime>(mut self, media_type: M) -> ResponseBuilder<'a> {
+        self.res.content_type(media_type);
+        self
+    }
+
+    /// Adds a header by name, e.g. one hyper has no type for. Adding a header twice sends
+    /// both values.
+    pub fn raw_header(mut self, name: &str, value: &str) -> ResponseBuilder<'a> {
+        self.res.append_raw_header(name, value);
+        self
+    }
+
+    /// Sets a cookie, see `Response::cookies`.
+    pub fn cookie(mut self, cookie: Cookie) -> ResponseBuilder<'a> {
+        self.res.cookie_jar.add(cookie);
+        self
+    }
+
+    /// Sends the response with `body`.
+    pub fn body<'s, S: 's + Sendable<'s>>(self, body: S) -> IoResult<()> {
+        let ResponseBuilder { mut res, status } = self;
+        let content = match body.prepare(&mut res) {
+            Ok(content) => content,
+            Err(status) => return res.error(status),
+        };
+        if let Some(status) = status {
+            res.status(status);
+        }
+        res.finish(content)
+    }
+
+    /// Sends the response without a body.
+    pub fn send(self) -> IoResult<()> {
+        let status = self.status.unwrap_or(StatusCode::Ok);
+        self.body(status)
     }
 }
 
+#[test]
+fn appended_headers_are_joined() {
+    let mut headers = header::Headers::new();
+    append_raw_header(&mut headers, "Vary", "Cookie");
+    append_raw_header(&mut headers, "Vary", "Accept-Encoding");
+    append_raw_header(&mut headers, "Set-Cookie", "a=1");
+    append_raw_header(&mut headers, "Set-Cookie", "b=2");
+    headers.set(header::Allow(vec![Method::Get]));
+    append_raw_header(&mut headers, "Allow", "POST");
+
+    let written = headers.to_string();
+    assert!(written.contains("Vary: Cookie, Accept-Encoding\r\n"));
+    assert!(written.contains("Set-Cookie: a=1\r\nSet-Cookie: b=2\r\n"));
+    assert!(written.contains("Allow: GET, POST\r\n"));
+}
+
+#[test]
+fn accepted_encodings() {
+    let accepted = |value: &str| {
+        let mut headers = header::Headers::new();
+        headers.set_raw("Accept-Encoding", vec![value.as_bytes().to_vec()]);
+        accepted_encoding(&headers).map(|encoding| encoding.to_string())
+    };
+
+    assert_eq!(accepted("deflate, gzip"), Some("gzip".to_owned()));
+    assert_eq!(accepted("gzip;q=0.5, deflate"), Some("deflate".to_owned()));
+    assert_eq!(accepted("gzip;q=0, identity"), None);
+    if cfg!(feature = "brotli") {
+        assert_eq!(accepted("gzip, deflate, br"), Some("br".to_owned()));
+        assert_eq!(accepted("br;q=0.5, gzip"), Some("gzip".to_owned()));
+        assert_eq!(accepted("*"), Some("gzip".to_owned()));
+    } else {
+        assert_eq!(accepted("br"), None);
+    }
+}
+
+#[test]
+fn vary_lists_headers_once() {
+    let mut headers = header::Headers::new();
+    add_vary(&mut headers, "Accept");
+    add_vary(&mut headers, "Cookie");
+    add_vary(&mut headers, "accept");
+    assert_eq!(headers.get_raw("Vary").unwrap(), &[b"Accept, Cookie".to_vec()][..]);
+
+    headers.set_raw("Vary", vec![b"*".to_vec()]);
+    add_vary(&mut headers, "Accept-Encoding");
+    assert_eq!(headers.get_raw("Vary").unwrap(), &[b"*".to_vec()][..]);
+}
+
+#[test]
+fn line_breaks_are_replaced() {
+    let location = header::Location("/next\r\nSet-Cookie: admin=1".to_owned());
+    assert_eq!(without_line_breaks(&location), Some("/next  Set-Cookie: admin=1".to_owned()));
+    assert_eq!(without_line_breaks(&header::Location("/next".to_owned())), None);
+
+    let mut cookie = Cookie::new("b".to_owned(), "2".to_owned());
+    cookie.path = Some("/\nX-Injected: 1".to_owned());
+    let cookies = header::SetCookie(vec![Cookie::new("a".to_owned(), "1".to_owned()), cookie]);
+    assert_eq!(without_line_breaks(&cookies), Some("a=1; Path=/\r\nSet-Cookie: b=2; Path=/ X-Injected: 1".to_owned()));
+
+    let mut headers = header::Headers::new();
+    append_raw_header(&mut headers, "Link", "</a>\r\nX-Injected: 1");
+    assert_eq!(headers.to_string(), "Link: </a>  X-Injected: 1\r\n");
+}
+
+#[test]
+fn attachment_filenames() {
+    assert_eq!(content_disposition("report.csv"), "attachment; filename=\"report.csv\"");
+    assert_eq!(content_disposition("a \"b\".txt"),
+               "attachment; filename=\"a _b_.txt\"; filename*=UTF-8''a%20%22b%22.txt");
+    assert_eq!(content_disposition("Rapport år\r\n.pdf"),
+               "attachment; filename=\"Rapport _r__.pdf\"; filename*=UTF-8''Rapport%20%C3%A5r%0D%0A.pdf");
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn jsonp_callback_names() {
+    assert!(is_callback_name("showItem"));
+    assert!(is_callback_name("jQuery_123.$cb"));
+    assert!(!is_callback_name(""));
+    assert!(!is_callback_name("1st"));
+    assert!(!is_callback_name("app."));
+    assert!(!is_callback_name("alert(1);x"));
+    assert!(!is_callback_name(&"a".repeat(129)));
+}
+
+#[test]
+fn negotiated_media_types() {
+    let offered = vec![html(), Mime(TopLevel::Application, SubLevel::Json, vec![])];
+    let preferred_by = |accept: &str| preferred(&media_ranges(accept), offered.iter());
+
+    assert_eq!(preferred_by("application/json"), Some(1));
+    assert_eq!(preferred_by("text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8"), Some(0));
+    assert_eq!(preferred_by("text/*;q=0.5, application/json;q=0.9"), Some(1));
+    assert_eq!(preferred_by("*/*"), Some(0));
+    assert_eq!(preferred_by("*/*;q=0.1, text/html;q=0"), Some(1));
+    assert_eq!(preferred_by("image/png"), None);
+}
@@ -1,13 +1,28 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
 use regex::Regex;
 
 use hyper::method::Method;
+use hyper::mime::{Mime, TopLevel, SubLevel};
+
+use url::form_urlencoded;
+use url::percent_encoding::{lossy_utf8_percent_decode, utf8_percent_encode, PASSWORD_ENCODE_SET};
 
 use Handler;
 
 pub struct Route {
-    pub re: Regex,
+    pub name: Option<String>,
+    pub pattern: String,
     pub handler: Box<Handler>,
     pub methods: Vec<Method>,
+    pub priority: i32,
+    pub host: Option<String>,
+    /// The media types the route accepts request bodies in. Empty if it accepts all.
+    pub accepts: Vec<Mime>,
+    segments: Vec<Segment>,
+    host_segments: Vec<Segment>,
 }
 
 impl Eq for Route {
@@ -15,17 +30,13 @@ impl Eq for Route {
 
 impl PartialEq for Route {
     fn eq(&self, other: &Route) -> bool {
-        self.re == other.re
+        self.pattern == other.pattern
     }
 }
 
 impl Route {
     pub fn new<H: 'static + Handler>(re: &str, handler: H) -> Route {
-        let route_re = create_routing_rule(re);
-        Route {
-            re: route_re,
-            handler: Box::new(handler),
-            methods: Vec::new()}
+        Route::from_boxed(re, Box::new(handler), Vec::new())
     }
 
     pub fn with_methods<H: 'static + Handler>(
@@ -33,46 +44,843 @@ impl Route {
         handler: H,
         methods: &[Method]) -> Route
     {
-        let route_re = create_routing_rule(re);
+        Route::from_boxed(re, Box::new(handler), methods.to_vec())
+    }
+
+    fn from_boxed(re: &str, handler: Box<Handler>, methods: Vec<Method>) -> Route {
         Route {
-            re: route_re,
-            handler: Box::new(handler),
-            methods: methods.to_vec()}
+            name: None,
+            pattern: re.to_owned(),
+            handler: handler,
+            methods: methods,
+            priority: 0,
+            host: None,
+            accepts: Vec::new(),
+            segments: create_routing_rule(re),
+            host_segments: Vec::new()}
+    }
+
+    /// Gives the route a name, see `Rask::url_for`.
+    pub fn named(mut self, name: &str) -> Route {
+        self.name = Some(name.to_owned());
+        self
+    }
+
+    /// Sets the route's priority. Routes with a higher priority are tried before routes
+    /// with a lower one, regardless of how their patterns overlap. Defaults to 0.
+    pub fn with_priority(mut self, priority: i32) -> Route {
+        self.priority = priority;
+        self
+    }
+
+    /// Restricts the route to requests for `host`, e.g. "api.example.com". Labels of the
+    /// form `{name}` are variables, e.g. "{sub}.example.com".
+    ///
+    /// # Panics
+    ///
+    /// Panics if a label of `host` isn't a literal or a variable.
+    pub fn on_host(mut self, host: &str) -> Route {
+        self.host = Some(host.to_owned());
+        self.host_segments = create_host_rule(host);
+        self
+    }
+
+    /// Restricts the media types of request bodies the route accepts, e.g.
+    /// "application/json". Types like "text/*" match all subtypes. Requests with a body of
+    /// another type are answered with 415 (Unsupported Media Type) before the handler runs.
+    pub fn accepting(mut self, media_types: &[Mime]) -> Route {
+        self.accepts = media_types.to_vec();
+        self
+    }
+
+    /// Whether the route accepts request bodies of `content_type`, see `accepting`.
+    pub fn accepts_content_type(&self, content_type: Option<&Mime>) -> bool {
+        if self.accepts.is_empty() {
+            return true;
+        }
+        let content_type = match content_type {
+            Some(content_type) => content_type,
+            None => return false,
+        };
+        self.accepts.iter().any(|accepted| {
+            (accepted.0 == TopLevel::Star || accepted.0 == content_type.0)
+                && (accepted.1 == SubLevel::Star || accepted.1 == content_type.1)
+        })
+    }
+
+    /// Moves the route under `prefix`, e.g. "/users" becomes "/api/users".
+    pub fn prefixed(self, prefix: &str) -> Route {
+        let mut route = Route::from_boxed(&join_path(prefix, &self.pattern), self.handler, self.methods);
+        route.name = self.name;
+        route.priority = self.priority;
+        route.host = self.host;
+        route.accepts = self.accepts;
+        route.host_segments = self.host_segments;
+        route
+    }
+
+    /// Builds a url from the route's pattern by filling in its variables from `params`.
+    /// Params that aren't variables in the pattern are appended as a query string.
+    pub fn build_url(&self, params: &[(&str, &str)]) -> Result<String, UrlError> {
+        let mut url = String::new();
+        let mut used = Vec::new();
+        for segment in self.segments.iter() {
+            url.push('/');
+            match *segment {
+                Segment::Param(ref name, ref matcher) => {
+                    let value = match params.iter().find(|&&(k, _)| k == name) {
+                        Some(&(_, value)) => value,
+                        None => return Err(UrlError::MissingVariable(name.to_owned()))
+                    };
+                    if !matcher.matches(value) {
+                        return Err(UrlError::InvalidValue(name.to_owned(), value.to_owned()));
+                    }
+                    url.push_str(&utf8_percent_encode(value, PASSWORD_ENCODE_SET));
+                    used.push(&name[..]);
+                },
+                Segment::Static(ref part) => url.push_str(part),
+                Segment::Wildcard(Some(ref name)) => {
+                    let value = match params.iter().find(|&&(k, _)| k == name) {
+                        Some(&(_, value)) => value,
+                        None => return Err(UrlError::MissingVariable(name.to_owned()))
+                    };
+                    let value: Vec<_> = value
+                        .split("/")
+                        .map(|part| utf8_percent_encode(part, PASSWORD_ENCODE_SET))
+                        .collect();
+                    url.push_str(&value.join("/"));
+                    used.push(&name[..]);
+                },
+                Segment::Wildcard(None) => return Err(UrlError::Wildcard),
+            }
+        }
+
+        let query: Vec<_> = params.iter().filter(|&&(k, _)| !used.contains(&k)).map(|&p| p).collect();
+        if !query.is_empty() {
+            url.push('?');
+            url.push_str(&form_urlencoded::serialize(query));
+        }
+
+        Ok(url)
+    }
+
+    /// The names of the values captured by the route's variables and wildcards, in order.
+    /// Anonymous wildcards have no name.
+    fn capture_names(&self) -> Vec<Option<&str>> {
+        self.segments
+            .iter()
+            .filter_map(|segment| match *segment {
+                Segment::Param(ref name, _) => Some(Some(&name[..])),
+                Segment::Wildcard(ref name) => Some(name.as_ref().map(|name| &name[..])),
+                Segment::Static(_) => None,
+            })
+            .collect()
+    }
+
+    /// Matches `host` against the route's host pattern, returning the values of its
+    /// variables. Routes without a host pattern match any host.
+    fn match_host(&self, host: Option<&str>) -> Option<Vec<(String, String)>> {
+        if self.host.is_none() {
+            return Some(Vec::new());
+        }
+        let host = match host {
+            Some(host) => host.to_lowercase(),
+            None => return None,
+        };
+        let labels: Vec<&str> = host.split(".").collect();
+        if labels.len() != self.host_segments.len() {
+            return None;
+        }
+
+        let mut vars = Vec::new();
+        for (segment, label) in self.host_segments.iter().zip(labels.into_iter()) {
+            match *segment {
+                Segment::Static(ref s) if *s == label => {},
+                Segment::Param(ref name, ref matcher) if matcher.matches(label) => {
+                    vars.push((name.to_owned(), label.to_owned()));
+                },
+                _ => return None,
+            }
+        }
+        Some(vars)
+    }
+}
+
+/// A read-only view of a registered route. See `Rask::routes`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RouteInfo<'r> {
+    pub pattern: &'r str,
+    pub name: Option<&'r str>,
+    /// Empty if the route accepts all methods.
+    pub methods: &'r [Method],
+    pub host: Option<&'r str>,
+    pub priority: i32,
+}
+
+impl<'r> From<&'r Route> for RouteInfo<'r> {
+    fn from(route: &'r Route) -> RouteInfo<'r> {
+        RouteInfo {
+            pattern: &route.pattern,
+            name: route.name.as_ref().map(|name| &name[..]),
+            methods: &route.methods,
+            host: route.host.as_ref().map(|host| &host[..]),
+            priority: route.priority,
+        }
+    }
+}
+
+/// Parses a media type like "application/json", or a range like "text/*" or "*/*".
+#[doc(hidden)]
+pub fn parse_media_range(media_range: &str) -> Option<Mime> {
+    if media_range.trim() == "*/*" {
+        return Some(Mime(TopLevel::Star, SubLevel::Star, Vec::new()));
+    }
+    if media_range.trim().ends_with("/*") {
+        let top = media_range.trim().trim_right_matches("/*");
+        return top.parse().ok().map(|top| Mime(top, SubLevel::Star, Vec::new()));
+    }
+    media_range.parse().ok()
+}
+
+/// A route that matches a path, along with the values of its variables.
+pub struct Match<'r> {
+    pub route: &'r Route,
+    pub vars: HashMap<String, String>,
+}
+
+/// The registered routes, kept in a prefix tree of path segments. Finding the routes for a
+/// path only visits the nodes for its segments instead of trying every route.
+///
+/// For each segment, literal segments are tried before variables, and variables before
+/// wildcards. Routes with the same pattern are tried in the order they are registered.
+pub struct Router {
+    routes: Vec<Route>,
+    root: Node,
+}
+
+impl Router {
+    pub fn new() -> Router {
+        Router {
+            routes: Vec::new(),
+            root: Node::new(),
+        }
+    }
+
+    pub fn add(&mut self, route: Route) {
+        if let Some(earlier) = self.shadowing(&route) {
+            warn!("Route {:?} is unreachable, it's shadowed by {:?}.", route.pattern, earlier.pattern);
+        }
+
+        let index = self.routes.len();
+        {
+            let mut node = &mut self.root;
+            for segment in route.segments.iter() {
+                let parent = node;
+                node = parent.child(segment);
+            }
+            node.routes.push(index);
+        }
+        self.routes.push(route);
+    }
+
+    /// The registered route that every request for `route` would be dispatched to before
+    /// reaching it, if any. That is a route with the same pattern (ignoring variable names),
+    /// host, at least the same priority and all of its methods.
+    ///
+    /// Overlapping patterns like "/{name}" and "/static" don't shadow each other, since
+    /// literal segments are tried before variables.
+    pub fn shadowing(&self, route: &Route) -> Option<&Route> {
+        self.routes.iter().find(|earlier| {
+            earlier.priority >= route.priority &&
+                earlier.host_segments == route.host_segments &&
+                (earlier.methods.is_empty() ||
+                 (!route.methods.is_empty() && route.methods.iter().all(|m| earlier.methods.contains(m)))) &&
+                earlier.segments.len() == route.segments.len() &&
+                earlier.segments.iter().zip(route.segments.iter()).all(|(a, b)| a.same_shape(b))
+        })
+    }
+
+    /// The registered routes, in the order they are registered.
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    /// Finds all routes that match `path`, in the order they should be tried. Routes
+    /// restricted to a host are skipped.
+    pub fn find<'r>(&'r self, path: &str) -> Vec<Match<'r>> {
+        self.find_on_host(None, path)
+    }
+
+    /// Same as `find`, but also matches routes restricted to `host`. These are tried before
+    /// routes with the same priority that aren't restricted to a host.
+    ///
+    /// The segments of `path` are percent-decoded before they're matched, so the values of
+    /// the variables are decoded too.
+    pub fn find_on_host<'r>(&'r self, host: Option<&str>, path: &str) -> Vec<Match<'r>> {
+        // decoded after splitting, so an encoded "/" doesn't separate segments
+        let segments: Vec<String> = path
+            .split("/")
+            .skip(1)
+            .map(|segment| lossy_utf8_percent_decode(segment.as_bytes()))
+            .collect();
+        let segments: Vec<&str> = segments.iter().map(|segment| &segment[..]).collect();
+        let mut found = Vec::new();
+        self.root.collect(&segments, &mut Vec::new(), &mut found);
+
+        // stable, so routes with the same priority keep their precedence
+        found.sort_by(|&(a, _), &(b, _)| {
+            let (a, b) = (&self.routes[a], &self.routes[b]);
+            (b.priority, b.host.is_some()).cmp(&(a.priority, a.host.is_some()))
+        });
+
+        found
+            .into_iter()
+            .filter_map(|(index, values)| {
+                let route = &self.routes[index];
+                let mut vars: HashMap<_, _> = match route.match_host(host) {
+                    Some(vars) => vars.into_iter().collect(),
+                    None => return None,
+                };
+                vars.extend(route.capture_names()
+                    .into_iter()
+                    .zip(values.into_iter())
+                    .filter_map(|(name, value)| name.map(|name| (name.to_owned(), value))));
+                Some(Match { route: route, vars: vars })
+            })
+            .collect()
+    }
+}
+
+struct Node {
+    statics: HashMap<String, Node>,
+    params: Vec<(Matcher, Node)>,
+    wildcard: Option<Box<Node>>,
+    routes: Vec<usize>,
+}
+
+impl Node {
+    fn new() -> Node {
+        Node {
+            statics: HashMap::new(),
+            params: Vec::new(),
+            wildcard: None,
+            routes: Vec::new(),
+        }
+    }
+
+    fn child(&mut self, segment: &Segment) -> &mut Node {
+        match *segment {
+            Segment::Static(ref part) => self.statics.entry(part.clone()).or_insert_with(Node::new),
+            Segment::Param(_, ref matcher) => {
+                let index = match self.params.iter().position(|&(ref m, _)| m == matcher) {
+                    Some(index) => index,
+                    None => {
+                        self.params.push((matcher.clone(), Node::new()));
+                        self.params.len() - 1
+                    }
+                };
+                &mut self.params[index].1
+            },
+            Segment::Wildcard(_) => {
+                if self.wildcard.is_none() {
+                    self.wildcard = Some(Box::new(Node::new()));
+                }
+                self.wildcard.as_mut().unwrap()
+            },
+        }
+    }
+
+    fn collect(&self, segments: &[&str], values: &mut Vec<String>, found: &mut Vec<(usize, Vec<String>)>) {
+        let (segment, rest) = match segments.split_first() {
+            Some(split) => split,
+            None => {
+                for &index in self.routes.iter() {
+                    found.push((index, values.clone()));
+                }
+                return;
+            }
+        };
+
+        if let Some(child) = self.statics.get(*segment) {
+            child.collect(rest, values, found);
+        }
+
+        for &(ref matcher, ref child) in self.params.iter() {
+            if matcher.matches(segment) {
+                values.push(segment.to_string());
+                child.collect(rest, values, found);
+                values.pop();
+            }
+        }
+
+        if let Some(ref child) = self.wildcard {
+            // a wildcard swallows one or more segments
+            for end in 1..segments.len() + 1 {
+                values.push(segments[..end].join("/"));
+                child.collect(&segments[end..], values, found);
+                values.pop();
+            }
+        }
+    }
+}
+
+/// A route variable's converter, with its pattern compiled when the route is registered.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Matcher {
+    converter: Converter,
+    re: Regex,
+}
+
+impl Matcher {
+    fn new(converter: Converter) -> Matcher {
+        let re = match Regex::new(&format!(r"^(?:{})$", converter.pattern())) {
+            Ok(re) => re,
+            Err(err) => panic!("{}", err)
+        };
+        Matcher {
+            converter: converter,
+            re: re,
+        }
+    }
+
+    fn matches(&self, value: &str) -> bool {
+        self.re.is_match(value) && self.converter.accepts(value)
+    }
+}
+
+/// How requests for a path that only differs from a registered route by a trailing slash are
+/// handled. See `Rask::trailing_slash`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrailingSlash {
+    /// "/foo" and "/foo/" are different routes. This is the default.
+    Strict,
+    /// "/foo/" is dispatched to the route for "/foo" if only that is registered, and vice
+    /// versa.
+    Ignore,
+    /// "/foo/" is redirected (301) to "/foo" if only that is registered, and vice versa.
+    Redirect,
+}
+
+/// Adds or removes the trailing slash of `path`. Returns `None` for "/".
+pub fn toggle_trailing_slash(path: &str) -> Option<String> {
+    if path == "/" {
+        None
+    }
+    else if path.ends_with('/') {
+        Some(path.trim_right_matches('/').to_owned())
+    }
+    else {
+        Some(format!("{}/", path))
+    }
+}
+
+/// The error returned when a route can't be registered. See `Rask::try_register`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RouteError {
+    /// The route would never be reached, because the route with the given pattern is
+    /// registered already and matches the same requests.
+    Shadowed(String),
+}
+
+impl fmt::Display for RouteError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RouteError::Shadowed(ref pattern) => write!(f, "Route is shadowed by '{}'.", pattern),
+        }
     }
 }
 
-fn create_routing_rule(input: &str) -> Regex {
-    let url_exp = input
-        .split("/")
+impl Error for RouteError {
+    fn description(&self) -> &str {
+        match *self {
+            RouteError::Shadowed(_) => "shadowed route",
+        }
+    }
+}
+
+/// The error returned when a url can't be built for a route. See `Rask::url_for`.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UrlError {
+    /// No route is registered with the given name.
+    UnknownRoute(String),
+    /// A variable in the route's pattern wasn't given a value.
+    MissingVariable(String),
+    /// The value given for the variable can't be converted to its type.
+    InvalidValue(String, String),
+    /// The route's pattern contains an anonymous `**` wildcard.
+    Wildcard,
+}
+
+impl fmt::Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            UrlError::UnknownRoute(ref name) => write!(f, "No route named '{}'.", name),
+            UrlError::MissingVariable(ref name) => write!(f, "Missing value for variable '{}'.", name),
+            UrlError::InvalidValue(ref name, ref value) =>
+                write!(f, "Invalid value '{}' for variable '{}'.", value, name),
+            UrlError::Wildcard => write!(f, "Can't build a url for a route with an anonymous wildcard."),
+        }
+    }
+}
+
+impl Error for UrlError {
+    fn description(&self) -> &str {
+        match *self {
+            UrlError::UnknownRoute(_) => "unknown route",
+            UrlError::MissingVariable(_) => "missing variable",
+            UrlError::InvalidValue(_, _) => "invalid value",
+            UrlError::Wildcard => "wildcard in route",
+        }
+    }
+}
+
+/// The type of a route variable, e.g. `int` in "/user/{id:int}".
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Converter {
+    /// `{name}` or `{name:str}`, any text, e.g. "so-many-crabs", "smörgåsbord" or
+    /// "notes.txt".
+    Str,
+    /// `{name:slug}`, letters, digits, underscores and hyphens, e.g. "so-many-crabs".
+    Slug,
+    /// `{name:int}`, a signed 64 bit integer.
+    Int,
+    /// `{name:float}`, a 64 bit floating point number.
+    Float,
+    /// `{name:uuid}`, a hyphenated uuid.
+    Uuid,
+    /// `{name:[0-9]+}`, anything matching the regex.
+    Regex(String),
+}
+
+impl Converter {
+    /// The converter for the type `name`, or a regex converter if `name` isn't a word.
+    fn from_name(name: &str) -> Option<Converter> {
+        match name {
+            "" | "str" => Some(Converter::Str),
+            "slug" => Some(Converter::Slug),
+            "int" => Some(Converter::Int),
+            "float" => Some(Converter::Float),
+            "uuid" => Some(Converter::Uuid),
+            _ if name.chars().all(|c| c.is_alphanumeric() || c == '_') => None,
+            _ => Some(Converter::Regex(name.to_owned())),
+        }
+    }
+
+    fn pattern(&self) -> &str {
+        match *self {
+            Converter::Str => r"[^/]*",
+            Converter::Slug => r"[\w-]+",
+            Converter::Int => r"-?[0-9]+",
+            Converter::Float => r"-?[0-9]+(?:\.[0-9]+)?",
+            Converter::Uuid => r"[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}",
+            Converter::Regex(ref re) => re,
+        }
+    }
+
+    /// Whether `value` can be converted to this type. The regex only checks the shape of the
+    /// value, e.g. an `int` might still overflow.
+    pub fn accepts(&self, value: &str) -> bool {
+        match *self {
+            Converter::Int => value.parse::<i64>().is_ok(),
+            Converter::Float => value.parse::<f64>().is_ok(),
+            Converter::Str | Converter::Slug | Converter::Uuid | Converter::Regex(_) => true,
+        }
+    }
+}
+
+/// Prepends `prefix` to the route pattern `route`, e.g. "/api" and "/users" becomes
+/// "/api/users".
+pub fn join_path(prefix: &str, route: &str) -> String {
+    format!("{}{}", prefix.trim_right_matches('/'), route)
+}
+
+/// Whether `path` is `prefix` or below it, e.g. "/api/users" is below "/api" but
+/// "/apis" isn't.
+pub fn is_below(path: &str, prefix: &str) -> bool {
+    let prefix = prefix.trim_right_matches('/');
+    path == prefix || (path.starts_with(prefix) && path[prefix.len()..].starts_with('/'))
+}
+
+fn create_routing_rule(input: &str) -> Vec<Segment> {
+    split_pattern(input)
+        .into_iter()
         .skip(1)
-        .map(|i| create_regex_for_named(&i))
-        .fold(String::new(), |a, b| a + &b);
+        .map(parse_segment)
+        .collect()
+}
 
-    match Regex::new(&format!(r"^{}$", url_exp)) {
-        Ok(re) => re,
-        Err(err) => panic!("{}", err)
+/// Splits `pattern` on the slashes that aren't inside a variable, e.g. the one in
+/// "{path:[^/]+}" is kept.
+fn split_pattern(pattern: &str) -> Vec<&str> {
+    let mut segments = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in pattern.char_indices() {
+        match c {
+            '{' => depth += 1,
+            '}' if depth > 0 => depth -= 1,
+            '/' if depth == 0 => {
+                segments.push(&pattern[start..i]);
+                start = i + 1;
+            },
+            _ => {},
+        }
     }
+    segments.push(&pattern[start..]);
+    segments
 }
 
-fn create_regex_for_named(s: &str) -> String {
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Segment {
+    Static(String),
+    Param(String, Matcher),
+    Wildcard(Option<String>),
+}
+
+impl Segment {
+    /// Whether both segments match the same values, regardless of variable names.
+    fn same_shape(&self, other: &Segment) -> bool {
+        match (self, other) {
+            (&Segment::Static(ref a), &Segment::Static(ref b)) => a == b,
+            (&Segment::Param(_, ref a), &Segment::Param(_, ref b)) => a == b,
+            (&Segment::Wildcard(_), &Segment::Wildcard(_)) => true,
+            _ => false,
+        }
+    }
+}
+
+fn create_host_rule(input: &str) -> Vec<Segment> {
+    input
+        .split(".")
+        .map(|label| {
+            if label.starts_with("{") {
+                match parse_segment(label) {
+                    Segment::Wildcard(_) => panic!("Invalid host label '{}'.", label),
+                    segment => segment,
+                }
+            } else if label != "" && label.chars().all(|c| c.is_alphanumeric() || c == '-') {
+                Segment::Static(label.to_lowercase())
+            } else {
+                panic!("Invalid host label '{}'.", label)
+            }
+        })
+        .collect()
+}
+
+fn parse_segment(s: &str) -> Segment {
     if s == "" {
-        return r"/".to_string();
+        return Segment::Static(String::new());
     }
 
-    let re = Regex::new(r"^\{(?P<named>\w*)\}$|^(?P<part>\w*)$|^(?P<wildcard>\*\*)$").unwrap();
-    let caps = re.captures(s).unwrap();
+    let re = Regex::new(r"^\{(?P<named>\w*)(?::(?P<type>.*))?\}$|^(?P<part>[^{}*]*)$|^(?P<wildcard>\*\*)$").unwrap();
+    let caps = match re.captures(s) {
+        Some(caps) => caps,
+        None => panic!("Invalid route segment '{}'.", s)
+    };
 
     if let Some(n) = caps.name("named") {
-        return format!(r"/(?P<{}>\w*)", n).to_string();
+        let type_name = caps.name("type").unwrap_or("");
+        if type_name == "**" {
+            return Segment::Wildcard(Some(n.to_owned()));
+        }
+        return match Converter::from_name(type_name) {
+            Some(Converter::Regex(re)) => {
+                if let Err(err) = Regex::new(&re) {
+                    panic!("Invalid regex for route variable '{}': {}", n, err);
+                }
+                Segment::Param(n.to_owned(), Matcher::new(Converter::Regex(re)))
+            },
+            Some(converter) => Segment::Param(n.to_owned(), Matcher::new(converter)),
+            None => panic!("Unknown type '{}' for route variable '{}'.", type_name, n)
+        };
     }
 
     if let Some(p) = caps.name("part") {
-        return format!(r"/{}", p).to_string();
+        return Segment::Static(p.to_owned());
     }
 
-    if let Some(_) = caps.name("wildcard") {
-        return format!(r"/(.*)");
+    Segment::Wildcard(None)
+}
+
+#[test]
+fn join_path_with_and_without_trailing_slash() {
+    assert_eq!(join_path("/api", "/users"), "/api/users");
+    assert_eq!(join_path("/api/", "/users"), "/api/users");
+    assert_eq!(join_path("/", "/users"), "/users");
+}
+
+#[test]
+fn toggle_slash() {
+    assert_eq!(toggle_trailing_slash("/"), None);
+    assert_eq!(toggle_trailing_slash("/foo"), Some("/foo/".to_string()));
+    assert_eq!(toggle_trailing_slash("/foo/"), Some("/foo".to_string()));
+}
+
+#[test]
+fn path_below_prefix() {
+    assert!(is_below("/api", "/api"));
+    assert!(is_below("/api/users", "/api/"));
+    assert!(is_below("/users", "/"));
+    assert!(!is_below("/apis", "/api"));
+}
+
+#[cfg(test)]
+fn router(patterns: &[&str]) -> Router {
+    let mut router = Router::new();
+    for pattern in patterns {
+        router.add(Route::new(pattern, |_: &::request::Request, _: ::response::Response| {}));
     }
+    router
+}
+
+#[cfg(test)]
+fn find<'r>(router: &'r Router, path: &str) -> Vec<&'r str> {
+    router.find(path).into_iter().map(|m| &m.route.pattern[..]).collect()
+}
+
+#[test]
+fn typed_variable_only_matches_its_type() {
+    let router = router(&["/user/{id:int}"]);
+
+    assert_eq!(find(&router, "/user/42"), vec!["/user/{id:int}"]);
+    assert!(find(&router, "/user/bob").is_empty());
+    assert_eq!(router.find("/user/42")[0].vars["id"], "42");
+}
+
+#[test]
+fn int_variable_rejects_overflow() {
+    assert!(Converter::Int.accepts("-42"));
+    assert!(!Converter::Int.accepts("99999999999999999999"));
+    assert!(find(&router(&["/user/{id:int}"]), "/user/99999999999999999999").is_empty());
+}
+
+#[test]
+fn uuid_variable() {
+    let router = router(&["/file/{id:uuid}"]);
+
+    assert_eq!(find(&router, "/file/936da01f-9abd-4d9d-80c7-02af85c822a8").len(), 1);
+    assert!(find(&router, "/file/936da01f").is_empty());
+}
+
+#[test]
+fn regex_variable() {
+    let router = router(&["/user/{id:[0-9]{2,3}}", "/file/{path:[^/]+\\.txt}"]);
+
+    assert_eq!(find(&router, "/user/123").len(), 1);
+    assert!(find(&router, "/user/1").is_empty());
+    assert!(find(&router, "/user/abc").is_empty());
+    assert_eq!(find(&router, "/file/notes.txt").len(), 1);
+    assert!(find(&router, "/file/notes.md").is_empty());
+}
+
+#[test]
+fn literal_before_variable_before_wildcard() {
+    let router = router(&["/users/**", "/users/{name}", "/users/new", "/"]);
+
+    assert_eq!(find(&router, "/users/new"), vec!["/users/new", "/users/{name}", "/users/**"]);
+    assert_eq!(find(&router, "/users/a/b"), vec!["/users/**"]);
+    assert_eq!(find(&router, "/"), vec!["/"]);
+    assert!(find(&router, "/users").is_empty());
+}
+
+#[test]
+fn wildcard_in_the_middle() {
+    let router = router(&["/files/**/raw"]);
+
+    assert_eq!(find(&router, "/files/a/b/raw").len(), 1);
+    assert!(find(&router, "/files/raw").is_empty());
+}
+
+#[test]
+fn priority_before_precedence() {
+    let mut router = Router::new();
+    router.add(Route::new("/users/new", |_: &::request::Request, _: ::response::Response| {}));
+    router.add(Route::new("/users/{id}", |_: &::request::Request, _: ::response::Response| {}).with_priority(1));
+
+    assert_eq!(find(&router, "/users/new"), vec!["/users/{id}", "/users/new"]);
+}
+
+#[test]
+fn shadowed_routes() {
+    let router = router(&["/{name}", "/users/{id:int}"]);
+    let route = |pattern| Route::new(pattern, |_: &::request::Request, _: ::response::Response| {});
+
+    assert!(router.shadowing(&route("/static")).is_none());
+    assert!(router.shadowing(&route("/users/{id:uuid}")).is_none());
+    assert_eq!(router.shadowing(&route("/users/{user:int}")).unwrap().pattern, "/users/{id:int}");
+    assert_eq!(router.shadowing(&Route::with_methods("/{x}", |_: &::request::Request, _: ::response::Response| {},
+                                                     &[Method::Get])).unwrap().pattern, "/{name}");
+    assert!(router.shadowing(&route("/{x}").with_priority(1)).is_none());
+}
+
+#[test]
+fn host_routes() {
+    let mut router = Router::new();
+    router.add(Route::new("/", |_: &::request::Request, _: ::response::Response| {}));
+    router.add(Route::new("/", |_: &::request::Request, _: ::response::Response| {}).on_host("{sub}.example.com"));
+
+    let found = router.find_on_host(Some("API.example.com"), "/");
+    assert_eq!(found.len(), 2);
+    assert_eq!(found[0].vars["sub"], "api");
+    assert_eq!(router.find_on_host(Some("example.com"), "/").len(), 1);
+    assert_eq!(router.find("/").len(), 1);
+}
+
+#[test]
+fn percent_decoded_variables() {
+    let router = router(&["/profile/{name}", "/user/{id:int}"]);
+
+    assert_eq!(router.find("/profile/J%C3%B8rgen")[0].vars["name"], "Jørgen");
+    assert_eq!(router.find("/user/%34%32")[0].vars["id"], "42");
+}
+
+#[test]
+fn unicode_and_hyphenated_segments() {
+    let router = router(&["/blog/{post}", "/tag/{tag:slug}", "/robots.txt", "/so-many-crabs"]);
+
+    assert_eq!(router.find("/blog/so-many-crabs")[0].vars["post"], "so-many-crabs");
+    assert_eq!(router.find("/tag/sm%C3%B6rg%C3%A5sbord")[0].vars["tag"], "smörgåsbord");
+    assert!(find(&router, "/tag/a.b").is_empty());
+    assert_eq!(find(&router, "/robots.txt"), vec!["/robots.txt"]);
+    assert_eq!(find(&router, "/so-many-crabs"), vec!["/so-many-crabs"]);
+}
+
+#[test]
+fn named_wildcard() {
+    let router = router(&["/static/{path:**}"]);
+
+    assert_eq!(router.find("/static/css/site.css")[0].vars["path"], "css/site.css");
+}
+
+#[test]
+fn build_url_fills_in_wildcard() {
+    let route = Route::new("/static/{path:**}", |_: &::request::Request, _: ::response::Response| {});
+
+    assert_eq!(route.build_url(&[("path", "css/my site.css")]), Ok("/static/css/my%20site.css".to_string()));
+    assert_eq!(Route::new("/static/**", |_: &::request::Request, _: ::response::Response| {}).build_url(&[]),
+               Err(UrlError::Wildcard));
+}
+
+#[test]
+fn build_url_fills_in_variables() {
+    let route = Route::new("/user/{id:int}/{name}", |_: &::request::Request, _: ::response::Response| {});
+
+    assert_eq!(route.build_url(&[("id", "1"), ("name", "bob")]), Ok("/user/1/bob".to_string()));
+    assert_eq!(route.build_url(&[("id", "1"), ("name", "bob"), ("page", "2")]), Ok("/user/1/bob?page=2".to_string()));
+    assert_eq!(route.build_url(&[("id", "1")]), Err(UrlError::MissingVariable("name".to_string())));
+    assert_eq!(route.build_url(&[("id", "bob"), ("name", "bob")]),
+               Err(UrlError::InvalidValue("id".to_string(), "bob".to_string())));
+}
+
+#[test]
+fn accepted_content_types() {
+    let mime = |s: &str| parse_media_range(s).unwrap();
+    let route = Route::new("/items", |_: &::request::Request, _: ::response::Response| {})
+        .accepting(&[mime("application/json"), mime("text/*")]);
 
-    "".to_string()
+    assert!(route.accepts_content_type(Some(&mime("application/json; charset=utf-8"))));
+    assert!(route.accepts_content_type(Some(&mime("text/csv"))));
+    assert!(!route.accepts_content_type(Some(&mime("application/xml"))));
+    assert!(!route.accepts_content_type(None));
+    assert!(Route::new("/items", |_: &::request::Request, _: ::response::Response| {}).accepts_content_type(None));
 }
@@ -0,0 +1,724 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use cookie::{Cookie, CookieJar};
+
+use rand::{self, Rng};
+
+#[cfg(feature = "redis-sessions")]
+use r2d2::Pool;
+#[cfg(feature = "redis-sessions")]
+use redis::{self, Commands, RedisResult};
+
+use rustc_serialize::hex::ToHex;
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+#[cfg(feature = "json")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "json")]
+use serde_json;
+
+use time::{self, Timespec};
+
+use url::form_urlencoded;
+
+/// The name of the cookie the session is stored in.
+const SESSION_COOKIE: &'static str = "rask-session";
+
+/// How long permanent sessions last without a configured `Expiry`.
+const PERMANENT_LIFETIME: u64 = 31 * 24 * 60 * 60;
+
+/// Values that are kept between requests from the same client. The values are kept by the
+/// application's `SessionStore`, by default in a cookie encrypted with the application's
+/// secret, so they can't be read or changed by the client.
+///
+/// Read the session with `Request::session` and change it with `Response::session`. Changes
+/// are sent with the response, so they aren't visible from `Request::session` until the next
+/// request.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::request::Request;
+/// use rask::response::Response;
+///
+/// fn index(req: &Request, res: Response) {
+///     match req.session().get("username") {
+///         Some(username) => res.send(format!("You're logged in as '{}'.", username)),
+///         None => res.send("You're not logged in."),
+///     };
+/// }
+///
+/// fn logout(_: &Request, mut res: Response) {
+///     res.session().remove("username");
+///     res.redirect("/");
+/// }
+/// ```
+///
+/// Sessions last until the browser is closed, or as long as the application's `Expiry`, see
+/// `Rask::session_expiry`. Permanent sessions, see `set_permanent`, last as long even if the
+/// browser is closed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Session {
+    id: Option<String>,
+    values: BTreeMap<String, String>,
+    expires: Option<SystemTime>,
+    permanent: bool,
+    expiry: Option<Expiry>,
+    regenerate: bool,
+    modified: bool,
+}
+
+/// How long sessions last, see `Rask::session_expiry`. Expired sessions are rejected as
+/// empty, also if the client sends an old session cookie.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// Sessions expire when they haven't been used for the duration. They're saved with
+    /// every response to extend them.
+    Sliding(Duration),
+    /// Sessions expire the duration after they're first saved, however they're used, e.g.
+    /// to make users log in again every day.
+    Absolute(Duration),
+}
+
+/// A session as it's kept by a `SessionStore`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SessionData {
+    pub values: BTreeMap<String, String>,
+    /// When the session expires, if it does.
+    pub expires: Option<SystemTime>,
+    pub permanent: bool,
+}
+
+impl SessionData {
+    /// The session as text, for stores that keep it as text.
+    pub fn encode(&self) -> String {
+        let expires = self.expires.map_or(String::new(), |expires| unix_seconds(expires).to_string());
+        format!("{}|{}|{}", expires, self.permanent as u8, form_urlencoded::serialize(self.values.iter()))
+    }
+
+    /// The session of text made by `encode`.
+    pub fn decode(text: &str) -> SessionData {
+        let parts: Vec<&str> = text.splitn(3, '|').collect();
+        if parts.len() < 3 {
+            // the values alone, as sessions were kept before they could expire
+            return SessionData { values: form_urlencoded::parse(text.as_bytes()).into_iter().collect(), ..SessionData::default() };
+        }
+        SessionData {
+            values: form_urlencoded::parse(parts[2].as_bytes()).into_iter().collect(),
+            expires: parts[0].parse().ok().map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+            permanent: parts[1] == "1",
+        }
+    }
+}
+
+/// Where sessions are kept between requests, see `Rask::session_store`. The session cookie
+/// holds the id the store gives a session when it's saved, and the session is loaded by
+/// that id.
+///
+/// Stores are called while handling requests, from several threads at once.
+pub trait SessionStore: Send + Sync {
+    /// The session with `id`, or `None` if there's none, e.g. because it was destroyed.
+    fn load(&self, id: &str) -> Option<SessionData>;
+
+    /// Saves the session with `id`, or a new session if `id` is `None`, returning the id to
+    /// load it by.
+    fn save(&self, id: Option<&str>, data: &SessionData) -> io::Result<String>;
+
+    /// Deletes the session with `id`, e.g. when all its values are removed.
+    fn destroy(&self, id: &str);
+
+    /// Whether the session cookie is encrypted rather than signed, because the id holds the
+    /// values. `false` by default.
+    fn encrypts_cookie(&self) -> bool {
+        false
+    }
+}
+
+/// Keeps the values of a session in the session cookie itself, encrypted. The default
+/// `SessionStore`. Cookies are limited to about 4 kB, so it's only fit for a few small
+/// values.
+pub struct CookieStore;
+
+impl SessionStore for CookieStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        Some(SessionData::decode(id))
+    }
+
+    fn save(&self, _: Option<&str>, data: &SessionData) -> io::Result<String> {
+        Ok(data.encode())
+    }
+
+    fn destroy(&self, _: &str) {
+    }
+
+    fn encrypts_cookie(&self) -> bool {
+        true
+    }
+}
+
+/// Keeps sessions in memory, in this process, e.g. for development and tests. They're lost
+/// when the application is restarted. The session cookie only holds the signed id.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::session::MemoryStore;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.session_store(MemoryStore::new());
+/// ```
+pub struct MemoryStore {
+    sessions: Mutex<HashMap<String, SessionData>>,
+}
+
+impl MemoryStore {
+    pub fn new() -> MemoryStore {
+        MemoryStore { sessions: Mutex::new(HashMap::new()) }
+    }
+}
+
+impl SessionStore for MemoryStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    fn save(&self, id: Option<&str>, data: &SessionData) -> io::Result<String> {
+        let id = id.map(|id| id.to_owned()).unwrap_or_else(new_id);
+        self.sessions.lock().unwrap().insert(id.clone(), data.clone());
+        Ok(id)
+    }
+
+    fn destroy(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+/// Keeps each session in a file in a directory, which is created when the first session is
+/// saved. The session cookie only holds the signed id.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::session::FileStore;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.session_store(FileStore::new("./sessions"));
+/// ```
+pub struct FileStore {
+    dir: PathBuf,
+}
+
+impl FileStore {
+    /// Keeps the sessions in the directory `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> FileStore {
+        FileStore { dir: dir.as_ref().to_path_buf() }
+    }
+
+    /// The file of the session `id`, or `None` if the id isn't one this store made.
+    fn file(&self, id: &str) -> Option<PathBuf> {
+        if is_id(id) {
+            Some(self.dir.join(id))
+        } else {
+            None
+        }
+    }
+}
+
+impl SessionStore for FileStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        let mut content = String::new();
+        let read = self.file(id).map(|file| File::open(file).and_then(|mut file| file.read_to_string(&mut content)));
+        match read {
+            Some(Ok(_)) => Some(SessionData::decode(&content)),
+            Some(Err(ref err)) if err.kind() == ErrorKind::NotFound => None,
+            Some(Err(err)) => {
+                error!("Couldn't read the session file: {}", err);
+                None
+            },
+            None => None,
+        }
+    }
+
+    fn save(&self, id: Option<&str>, data: &SessionData) -> io::Result<String> {
+        let id = id.map(|id| id.to_owned()).unwrap_or_else(new_id);
+        let file = try!(self.file(&id).ok_or(io::Error::new(ErrorKind::InvalidInput, "invalid session id")));
+        try!(fs::create_dir_all(&self.dir));
+        // written next to it and renamed, so it's never read half written
+        let partial = self.dir.join(format!("{}.tmp", id));
+        try!(File::create(&partial).and_then(|mut partial| partial.write_all(data.encode().as_bytes())));
+        try!(fs::rename(&partial, &file));
+        Ok(id)
+    }
+
+    fn destroy(&self, id: &str) {
+        if let Some(file) = self.file(id) {
+            let _ = fs::remove_file(file);
+        }
+    }
+}
+
+/// Keeps sessions in Redis, so they're shared by all instances of an application and survive
+/// restarts. Each session is a key with the session's values, which expires after the time to
+/// live unless the session is saved again. The session cookie only holds the signed id.
+///
+/// Only available with the `redis-sessions` feature.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use rask::Rask;
+/// use rask::session::RedisStore;
+///
+/// let store = RedisStore::new("redis://127.0.0.1/").unwrap()
+///     .prefix("shop:session:")
+///     .ttl(Duration::from_secs(7 * 24 * 60 * 60));
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.session_store(store);
+/// ```
+#[cfg(feature = "redis-sessions")]
+pub struct RedisStore {
+    pool: Pool<redis::Client>,
+    prefix: String,
+    ttl: Duration,
+}
+
+#[cfg(feature = "redis-sessions")]
+impl RedisStore {
+    /// Connects to the Redis server at `url`, like "redis://127.0.0.1/", with a pool of up
+    /// to 10 connections, made as they're needed. Keys are prefixed with "rask:session:" and
+    /// expire after a day.
+    pub fn new(url: &str) -> RedisResult<RedisStore> {
+        let client = try!(redis::Client::open(url));
+        Ok(RedisStore::from_pool(Pool::builder().max_size(10).min_idle(Some(0)).build_unchecked(client)))
+    }
+
+    /// Uses the connections of `pool`, e.g. one with another size or timeouts.
+    pub fn from_pool(pool: Pool<redis::Client>) -> RedisStore {
+        RedisStore {
+            pool: pool,
+            prefix: "rask:session:".to_owned(),
+            ttl: Duration::from_secs(24 * 60 * 60),
+        }
+    }
+
+    /// Prefixes the keys with `prefix` instead, e.g. to keep the sessions of several
+    /// applications in one database.
+    pub fn prefix(mut self, prefix: &str) -> RedisStore {
+        self.prefix = prefix.to_owned();
+        self
+    }
+
+    /// Keeps sessions for `ttl` after they're last saved instead, in whole seconds, unless
+    /// they expire earlier, see `Rask::session_expiry`.
+    pub fn ttl(mut self, ttl: Duration) -> RedisStore {
+        self.ttl = ttl;
+        self
+    }
+
+    fn key(&self, id: &str) -> String {
+        format!("{}{}", self.prefix, id)
+    }
+}
+
+#[cfg(feature = "redis-sessions")]
+impl SessionStore for RedisStore {
+    fn load(&self, id: &str) -> Option<SessionData> {
+        let loaded = self.pool
+            .get()
+            .map_err(|err| err.to_string())
+            .and_then(|mut connection| connection.get::<_, Option<String>>(self.key(id)).map_err(|err| err.to_string()));
+        match loaded {
+            Ok(value) => value.map(|value| SessionData::decode(&value)),
+            Err(err) => {
+                error!("Couldn't load the session from Redis: {}", err);
+                None
+            },
+        }
+    }
+
+    fn save(&self, id: Option<&str>, data: &SessionData) -> io::Result<String> {
+        let id = id.map(|id| id.to_owned()).unwrap_or_else(new_id);
+        let mut connection = try!(self.pool.get().map_err(|err| io::Error::new(ErrorKind::Other, err.to_string())));
+        let value = data.encode();
+        let ttl = data.expires
+            .map_or(self.ttl, |expires| expires.duration_since(SystemTime::now()).unwrap_or(Duration::from_secs(0)))
+            .as_secs()
+            .max(1);
+        try!(connection.set_ex::<_, _, ()>(self.key(&id), value, ttl as usize).map_err(|err| io::Error::new(ErrorKind::Other, err.to_string())));
+        Ok(id)
+    }
+
+    fn destroy(&self, id: &str) {
+        let destroyed = self.pool
+            .get()
+            .map_err(|err| err.to_string())
+            .and_then(|mut connection| connection.del::<_, ()>(self.key(id)).map_err(|err| err.to_string()));
+        if let Err(err) = destroyed {
+            error!("Couldn't destroy the session in Redis: {}", err);
+        }
+    }
+}
+
+/// A new random session id, 64 hex digits.
+fn new_id() -> String {
+    rand::thread_rng().gen_iter::<u8>().take(32).collect::<Vec<u8>>().to_hex()
+}
+
+fn is_id(id: &str) -> bool {
+    id.len() == 64 && id.chars().all(|c| c.is_digit(16))
+}
+
+impl Session {
+    /// An empty session.
+    #[doc(hidden)]
+    pub fn new() -> Session {
+        Session {
+            id: None,
+            values: BTreeMap::new(),
+            expires: None,
+            permanent: false,
+            expiry: None,
+            regenerate: false,
+            modified: false,
+        }
+    }
+
+    /// Loads the session of the session cookie in `jar`, or else in one of the `previous`
+    /// jars keyed with previous secrets, from `store`, to be saved with `expiry`.
+    #[doc(hidden)]
+    pub fn load(store: &SessionStore, jar: &CookieJar, previous: &[CookieJar], expiry: Option<Expiry>) -> Session {
+        let mut session = Session::new();
+        session.expiry = expiry;
+        let id = match find_cookie(store, jar) {
+            Some(cookie) => cookie.value,
+            None => match previous.iter().filter_map(|jar| find_cookie(store, jar)).next() {
+                Some(cookie) => {
+                    // saved again with the current secret
+                    session.modified = true;
+                    cookie.value
+                },
+                None => return session,
+            },
+        };
+        match store.load(&id) {
+            Some(ref data) if data.expires.map_or(false, |expires| expires <= SystemTime::now()) => {
+                debug!("Expired session, starting a new one.");
+                store.destroy(&id);
+                // makes the client drop the cookie
+                session.modified = true;
+            },
+            Some(data) => {
+                session.id = Some(id);
+                session.values = data.values;
+                session.expires = data.expires;
+                session.permanent = data.permanent;
+            },
+            None => debug!("Unknown session, starting a new one."),
+        }
+        session
+    }
+
+    /// Saves the session to `store`, and its id to `jar`, if it's changed or its expiry is
+    /// extended with every response.
+    #[doc(hidden)]
+    pub fn save(&self, store: &SessionStore, jar: &CookieJar) -> io::Result<()> {
+        let sliding = match self.expiry {
+            Some(Expiry::Sliding(_)) => true,
+            Some(Expiry::Absolute(_)) => false,
+            None => self.permanent,
+        };
+        if !self.modified && !(sliding && self.id.is_some()) {
+            return Ok(());
+        }
+        let now = SystemTime::now();
+        let (id, data) = match try!(self.persist(store, now)) {
+            Some(saved) => saved,
+            None => {
+                remove_cookie(store, jar);
+                return Ok(());
+            },
+        };
+        let mut cookie = Cookie::new(SESSION_COOKIE.to_owned(), id);
+        cookie.path = Some("/".to_owned());
+        cookie.httponly = true;
+        if let (true, Some(expires)) = (self.permanent, data.expires) {
+            cookie.max_age = Some(expires.duration_since(now).map(|left| left.as_secs()).unwrap_or(0));
+            cookie.expires = Some(time::at_utc(Timespec::new(unix_seconds(expires) as i64, 0)));
+        }
+        if store.encrypts_cookie() {
+            jar.encrypted().add(cookie);
+        } else {
+            jar.signed().add(cookie);
+        }
+        Ok(())
+    }
+
+    /// Saves the session to `store`, or destroys it if it's empty, returning the id and data
+    /// of the saved session.
+    fn persist(&self, store: &SessionStore, now: SystemTime) -> io::Result<Option<(String, SessionData)>> {
+        let mut id = self.id.as_ref().map(|id| &id[..]);
+        if self.values.is_empty() || self.regenerate {
+            if let Some(id) = id {
+                store.destroy(id);
+            }
+            id = None;
+        }
+        if self.values.is_empty() {
+            return Ok(None);
+        }
+
+        let data = SessionData {
+            values: self.values.clone(),
+            expires: self.next_expiry(now),
+            permanent: self.permanent,
+        };
+        let id = try!(store.save(id, &data));
+        Ok(Some((id, data)))
+    }
+
+    /// When the session expires if it's saved now.
+    fn next_expiry(&self, now: SystemTime) -> Option<SystemTime> {
+        match self.expiry {
+            Some(Expiry::Absolute(lifetime)) => Some(self.expires.unwrap_or(now + lifetime)),
+            Some(Expiry::Sliding(lifetime)) => Some(now + lifetime),
+            None if self.permanent => Some(now + Duration::from_secs(PERMANENT_LIFETIME)),
+            None => None,
+        }
+    }
+
+    /// Makes the session outlast the browser being closed, until it expires, or makes it a
+    /// session that ends when the browser is closed again. Sessions aren't permanent by
+    /// default. Without an `Expiry` permanent sessions expire 31 days after they're last
+    /// used.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn login(req: &Request, mut res: Response) {
+    ///     let remember = req.form().ok().map_or(false, |form| form.get("remember").is_some());
+    ///     res.session().set("username", "bob");
+    ///     res.session().set_permanent(remember);
+    ///     res.redirect("/");
+    /// }
+    /// ```
+    pub fn set_permanent(&mut self, permanent: bool) {
+        self.modified |= self.permanent != permanent;
+        self.permanent = permanent;
+    }
+
+    pub fn is_permanent(&self) -> bool {
+        self.permanent
+    }
+
+    /// When the session expires, as of when it was last saved. `None` if it doesn't, or it's
+    /// new.
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Gives the session a new id, keeping its values, and makes the old id unknown. Call it
+    /// when a user logs in or gets other privileges, so an attacker who made the user's
+    /// browser use a session id known to them, e.g. with a link, can't use the logged in
+    /// session.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// fn login(_: &Request, mut res: Response) {
+    ///     res.session().regenerate();
+    ///     res.session().set("username", "bob");
+    ///     res.redirect("/");
+    /// }
+    /// ```
+    pub fn regenerate(&mut self) {
+        self.regenerate = true;
+        self.modified = true;
+    }
+
+    /// The value of `key`.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.values.get(key).map(|value| &value[..])
+    }
+
+    /// Sets `key` to `value`.
+    pub fn set(&mut self, key: &str, value: &str) {
+        self.values.insert(key.to_owned(), value.to_owned());
+        self.modified = true;
+    }
+
+    /// The value of `key` deserialized from json, as set with `set_as`. `None` if there's no
+    /// value or it can't be deserialized to `T`. Requires the `json` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use std::collections::BTreeMap;
+    /// use rask::request::Request;
+    /// use rask::response::Response;
+    ///
+    /// // product ids and quantities
+    /// type Cart = BTreeMap<u64, u32>;
+    ///
+    /// fn add_to_cart(req: &Request, mut res: Response) {
+    ///     let mut cart = req.session().get_as::<Cart>("cart").unwrap_or_default();
+    ///     *cart.entry(42).or_insert(0) += 1;
+    ///     let _ = res.session().set_as("cart", &cart);
+    ///     res.redirect("/cart");
+    /// }
+    /// ```
+    #[cfg(feature = "json")]
+    pub fn get_as<T: DeserializeOwned>(&self, key: &str) -> Option<T> {
+        self.values.get(key).and_then(|value| serde_json::from_str(value).ok())
+    }
+
+    /// Sets `key` to `value` serialized to json. Requires the `json` feature.
+    #[cfg(feature = "json")]
+    pub fn set_as<T: Serialize>(&mut self, key: &str, value: &T) -> serde_json::Result<()> {
+        let value = try!(serde_json::to_string(value));
+        self.values.insert(key.to_owned(), value);
+        self.modified = true;
+        Ok(())
+    }
+
+    /// Removes `key`, returning its value.
+    pub fn remove(&mut self, key: &str) -> Option<String> {
+        let value = self.values.remove(key);
+        self.modified |= value.is_some();
+        value
+    }
+
+    /// Removes all values, e.g. when logging out.
+    pub fn clear(&mut self) {
+        self.modified |= !self.values.is_empty();
+        self.values.clear();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+}
+
+fn unix_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or(0)
+}
+
+fn find_cookie(store: &SessionStore, jar: &CookieJar) -> Option<Cookie> {
+    if store.encrypts_cookie() {
+        jar.encrypted().find(SESSION_COOKIE)
+    } else {
+        jar.signed().find(SESSION_COOKIE)
+    }
+}
+
+fn remove_cookie(store: &SessionStore, jar: &CookieJar) {
+    if store.encrypts_cookie() {
+        jar.encrypted().remove(SESSION_COOKIE);
+    } else {
+        jar.signed().remove(SESSION_COOKIE);
+    }
+}
+
+#[test]
+fn cookie_store_keeps_values_in_the_id() {
+    let mut data = SessionData::default();
+    data.values.insert("username".to_owned(), "bob & alice".to_owned());
+    data.values.insert("theme".to_owned(), "dark".to_owned());
+    let id = CookieStore.save(None, &data).unwrap();
+    assert_eq!(CookieStore.load(&id), Some(data));
+    assert!(CookieStore.encrypts_cookie());
+}
+
+#[test]
+fn expiry_is_kept_and_extended() {
+    let mut data = SessionData::default();
+    data.values.insert("a|b".to_owned(), "c|d".to_owned());
+    data.expires = Some(UNIX_EPOCH + Duration::from_secs(1500000000));
+    data.permanent = true;
+    assert_eq!(SessionData::decode(&data.encode()), data);
+    assert_eq!(SessionData::decode("username=bob").values.get("username").map(|v| &v[..]), Some("bob"));
+    assert_eq!(SessionData::decode("username=bob").expires, None);
+
+    let now = UNIX_EPOCH + Duration::from_secs(1600000000);
+    let day = Duration::from_secs(24 * 60 * 60);
+    let mut session = Session::new();
+    assert_eq!(session.next_expiry(now), None);
+    session.set_permanent(true);
+    assert_eq!(session.next_expiry(now), Some(now + Duration::from_secs(PERMANENT_LIFETIME)));
+    session.expiry = Some(Expiry::Sliding(day));
+    assert_eq!(session.next_expiry(now), Some(now + day));
+    session.expiry = Some(Expiry::Absolute(day));
+    session.expires = Some(now + Duration::from_secs(60));
+    assert_eq!(session.next_expiry(now), Some(now + Duration::from_secs(60)));
+}
+
+#[test]
+fn server_side_stores() {
+    let mut data = SessionData::default();
+    data.values.insert("username".to_owned(), "bob".to_owned());
+
+    let memory = MemoryStore::new();
+    let id = memory.save(None, &data).unwrap();
+    assert!(is_id(&id));
+    assert_eq!(memory.load(&id), Some(data.clone()));
+    memory.destroy(&id);
+    assert_eq!(memory.load(&id), None);
+
+    let dir = ::std::env::temp_dir().join(format!("rask-sessions-{}", new_id()));
+    let files = FileStore::new(&dir);
+    let id = files.save(None, &data).unwrap();
+    assert_eq!(files.load(&id), Some(data.clone()));
+    assert_eq!(files.save(Some(&id), &SessionData::default()).unwrap(), id);
+    assert_eq!(files.load(&id), Some(SessionData::default()));
+    files.destroy(&id);
+    assert_eq!(files.load(&id), None);
+    assert_eq!(files.load("../../etc/passwd"), None);
+
+    let mut session = Session::new();
+    session.set("username", "bob");
+    let (first, _) = session.persist(&memory, UNIX_EPOCH).unwrap().unwrap();
+    session.id = Some(first.clone());
+    session.regenerate();
+    let (second, saved) = session.persist(&memory, UNIX_EPOCH).unwrap().unwrap();
+    assert!(first != second);
+    assert_eq!(memory.load(&first), None);
+    assert_eq!(memory.load(&second), Some(saved));
+    assert!(files.save(Some("../escape"), &data).is_err());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(feature = "redis-sessions")]
+#[test]
+fn redis_keys_are_prefixed() {
+    let store = RedisStore::new("redis://127.0.0.1/").unwrap();
+    assert_eq!(store.key("3fa9"), "rask:session:3fa9");
+    assert_eq!(store.prefix("shop:").key("3fa9"), "shop:3fa9");
+    assert!(RedisStore::new("not a url").is_err());
+}
+
+#[cfg(feature = "json")]
+#[test]
+fn typed_values() {
+    let mut session = Session::new();
+    session.set_as("cart", &vec![1u64, 2, 3]).unwrap();
+    assert_eq!(session.get("cart"), Some("[1,2,3]"));
+    assert_eq!(session.get_as::<Vec<u64>>("cart"), Some(vec![1, 2, 3]));
+    assert_eq!(session.get_as::<String>("cart"), None);
+    session.set("username", "bob");
+    assert_eq!(session.get_as::<String>("username"), None);
+}
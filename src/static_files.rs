@@ -0,0 +1,87 @@
+use std::path::{Path, PathBuf};
+
+use hyper::mime::Mime;
+use hyper::status::StatusCode;
+
+use request::Request;
+use response::Response;
+use Handler;
+
+/// A handler that serves the files in a directory. The file is given by the route variable
+/// `path`, so it must be registered with a "{path:**}" wildcard. Usually registered with
+/// `Rask::serve_static`.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::static_files::ServeStatic;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.get("/assets/{path:**}", ServeStatic::new("./public"));
+/// ```
+pub struct ServeStatic {
+    root: PathBuf,
+}
+
+impl ServeStatic {
+    /// Serves the files in the directory `root`.
+    pub fn new<P: AsRef<Path>>(root: P) -> ServeStatic {
+        ServeStatic {
+            root: root.as_ref().to_path_buf(),
+        }
+    }
+
+    /// The file for the route variable `path`, or `None` if it would escape `root`.
+    fn file_path(&self, path: &str) -> Option<PathBuf> {
+        let mut file_path = self.root.clone();
+        for part in path.split('/') {
+            if part == "" || part == "." || part == ".." || part.contains('\\') {
+                return None;
+            }
+            file_path.push(part);
+        }
+        Some(file_path)
+    }
+}
+
+impl Handler for ServeStatic {
+    fn handle(&self, req: &Request, res: Response) {
+        let _ = match req.vars.get("path").and_then(|path| self.file_path(path)) {
+            Some(file_path) => res.send_file(file_path),
+            None => res.error(StatusCode::NotFound),
+        };
+    }
+}
+
+/// Guesses the content type of the file from its extension.
+#[doc(hidden)]
+pub fn content_type(path: &Path) -> Mime {
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or("").to_lowercase();
+    let mime = match &extension[..] {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" => "application/javascript; charset=utf-8",
+        "json" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "xml" => "application/xml",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "ico" => "image/x-icon",
+        "woff" => "application/font-woff",
+        "pdf" => "application/pdf",
+        _ => "application/octet-stream",
+    };
+    mime.parse().unwrap()
+}
+
+#[test]
+fn file_path_stays_below_root() {
+    let serve = ServeStatic::new("/srv/public");
+
+    assert_eq!(serve.file_path("css/site.css"), Some(PathBuf::from("/srv/public/css/site.css")));
+    assert_eq!(serve.file_path("../secret"), None);
+    assert_eq!(serve.file_path("css//site.css"), None);
+}
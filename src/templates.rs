@@ -0,0 +1,366 @@
+//! Rendering templates into response bodies, see `Response::render`. Requires the
+//! `templates` feature.
+//!
+//! Templates are rendered by a `TemplateEngine` registered with `Rask::template_engine`, or
+//! by the built-in `Mustache` engine with `Rask::templates`. The context of a template is any
+//! value serde can serialize, converted to a `serde_json::Value`.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, ErrorKind, Read};
+use std::mem;
+use std::path::{Component, Path, PathBuf};
+
+use serde_json::Value;
+
+/// Renders templates by name.
+///
+/// # Examples
+///
+/// ```rust
+/// extern crate rask;
+/// extern crate serde_json;
+///
+/// use rask::Rask;
+/// use rask::templates::{TemplateEngine, TemplateError};
+/// use serde_json::Value;
+///
+/// struct Debug;
+///
+/// impl TemplateEngine for Debug {
+///     fn render(&self, name: &str, context: &Value) -> Result<String, TemplateError> {
+///         Ok(format!("{}: {}", name, context))
+///     }
+/// }
+///
+/// fn main() {
+///     let mut app = Rask::new("SUPER SECRET KEY");
+///     app.template_engine(Debug);
+/// }
+/// ```
+pub trait TemplateEngine: Send + Sync {
+    /// Renders the template `name` with `context`.
+    fn render(&self, name: &str, context: &Value) -> Result<String, TemplateError>;
+}
+
+/// The error returned when a template can't be rendered.
+#[derive(Debug)]
+pub enum TemplateError {
+    /// There's no template with the name.
+    NotFound(String),
+    /// The template couldn't be read.
+    Io(io::Error),
+    /// The template isn't valid.
+    Syntax(String),
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            TemplateError::NotFound(ref name) => write!(f, "No template named {:?}.", name),
+            TemplateError::Io(ref err) => write!(f, "Couldn't read the template: {}", err),
+            TemplateError::Syntax(ref message) => write!(f, "Invalid template: {}", message),
+        }
+    }
+}
+
+impl Error for TemplateError {
+    fn description(&self) -> &str {
+        match *self {
+            TemplateError::NotFound(_) => "template not found",
+            TemplateError::Io(_) => "io error",
+            TemplateError::Syntax(_) => "invalid template",
+        }
+    }
+}
+
+/// How deeply partials may include each other, which stops a partial that includes itself.
+const MAX_PARTIAL_DEPTH: usize = 32;
+
+/// A template engine for a subset of [Mustache](https://mustache.github.io/mustache.5.html),
+/// reading the templates from a directory. Templates are read on every render, so changes
+/// show up without restarting the application.
+///
+/// * `{{name}}` -> the value of `name`, html escaped. Dotted names like `{{user.name}}`
+/// look up nested values and `{{.}}` is the current value.
+/// * `{{{name}}}` or `{{& name}}` -> the value of `name`, not escaped.
+/// * `{{#name}}...{{/name}}` -> renders the section once for each item if `name` is an array,
+/// once with `name` as the current value if it's any other value but null or false, and not
+/// at all otherwise.
+/// * `{{^name}}...{{/name}}` -> renders the section if `name` is missing, null, false or an
+/// empty array.
+/// * `{{> header.html}}` -> the template "header.html", rendered with the current values.
+/// * `{{! comment}}` -> nothing.
+///
+/// # Examples
+///
+/// ```rust
+/// use rask::Rask;
+/// use rask::templates::Mustache;
+///
+/// let mut app = Rask::new("SUPER SECRET KEY");
+/// app.template_engine(Mustache::new("./templates"));
+/// ```
+pub struct Mustache {
+    dir: PathBuf,
+}
+
+impl Mustache {
+    /// Reads the templates from the directory `dir`.
+    pub fn new<P: AsRef<Path>>(dir: P) -> Mustache {
+        Mustache {
+            dir: dir.as_ref().to_path_buf(),
+        }
+    }
+
+    fn load(&self, name: &str) -> Result<Vec<Node>, TemplateError> {
+        let relative = Path::new(name);
+        if relative.components().any(|component| match component {
+            Component::Normal(_) => false,
+            _ => true,
+        }) {
+            return Err(TemplateError::NotFound(name.to_owned()));
+        }
+
+        let mut source = String::new();
+        match File::open(self.dir.join(relative)).and_then(|mut file| file.read_to_string(&mut source)) {
+            Ok(_) => parse(&source),
+            Err(ref err) if err.kind() == ErrorKind::NotFound => Err(TemplateError::NotFound(name.to_owned())),
+            Err(err) => Err(TemplateError::Io(err)),
+        }
+    }
+
+    fn render_nodes<'v>(
+        &self,
+        nodes: &[Node],
+        stack: &mut Vec<&'v Value>,
+        depth: usize,
+        out: &mut String) -> Result<(), TemplateError>
+    {
+        for node in nodes {
+            match *node {
+                Node::Text(ref text) => out.push_str(text),
+                Node::Value(ref name, escape) => {
+                    let text = lookup(stack, name).map(to_text).unwrap_or(String::new());
+                    if escape {
+                        escape_html(&text, out);
+                    } else {
+                        out.push_str(&text);
+                    }
+                },
+                Node::Section(ref name, false, ref children) => {
+                    match lookup(stack, name) {
+                        Some(&Value::Array(ref items)) => {
+                            for item in items {
+                                stack.push(item);
+                                let rendered = self.render_nodes(children, stack, depth, out);
+                                stack.pop();
+                                try!(rendered);
+                            }
+                        },
+                        Some(value) if is_truthy(value) => {
+                            stack.push(value);
+                            let rendered = self.render_nodes(children, stack, depth, out);
+                            stack.pop();
+                            try!(rendered);
+                        },
+                        _ => {},
+                    }
+                },
+                Node::Section(ref name, true, ref children) => {
+                    if !lookup(stack, name).map_or(false, is_truthy) {
+                        try!(self.render_nodes(children, stack, depth, out));
+                    }
+                },
+                Node::Partial(ref name) => {
+                    if depth >= MAX_PARTIAL_DEPTH {
+                        return Err(TemplateError::Syntax(format!("Partials nested too deeply at {:?}.", name)));
+                    }
+                    let partial = try!(self.load(name));
+                    try!(self.render_nodes(&partial, stack, depth + 1, out));
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+impl TemplateEngine for Mustache {
+    fn render(&self, name: &str, context: &Value) -> Result<String, TemplateError> {
+        let nodes = try!(self.load(name));
+        let mut out = String::new();
+        try!(self.render_nodes(&nodes, &mut vec![context], 0, &mut out));
+        Ok(out)
+    }
+}
+
+/// A parsed piece of a template. Names are split at the dots, `.` is the empty name.
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    /// A value, and whether it's escaped.
+    Value(Vec<String>, bool),
+    /// A section, whether it's inverted, and its contents.
+    Section(Vec<String>, bool, Vec<Node>),
+    Partial(String),
+}
+
+fn parse(source: &str) -> Result<Vec<Node>, TemplateError> {
+    // the sections being parsed, with the nodes before them
+    let mut open: Vec<(String, bool, Vec<Node>)> = Vec::new();
+    let mut nodes = Vec::new();
+    let mut rest = source;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            nodes.push(Node::Text(rest[..start].to_owned()));
+        }
+        rest = &rest[start..];
+
+        let (tag, end) = if rest.starts_with("{{{") {
+            match rest.find("}}}") {
+                Some(end) => (format!("&{}", &rest[3..end]), end + 3),
+                None => return Err(unclosed(rest)),
+            }
+        } else {
+            match rest.find("}}") {
+                Some(end) => (rest[2..end].to_owned(), end + 2),
+                None => return Err(unclosed(rest)),
+            }
+        };
+        rest = &rest[end..];
+
+        let tag = tag.trim();
+        let (sigil, name) = match tag.chars().next() {
+            Some(sigil) if "&#^/>!".contains(sigil) => (Some(sigil), tag[1..].trim()),
+            _ => (None, tag),
+        };
+        match sigil {
+            None => nodes.push(Node::Value(split_name(name), true)),
+            Some('&') => nodes.push(Node::Value(split_name(name), false)),
+            Some('!') => {},
+            Some('>') => nodes.push(Node::Partial(name.to_owned())),
+            Some('/') => {
+                match open.pop() {
+                    Some((ref section, inverted, ref mut outer)) if section == name => {
+                        let children = mem::replace(&mut nodes, mem::replace(outer, Vec::new()));
+                        nodes.push(Node::Section(split_name(name), inverted, children));
+                    },
+                    Some((section, _, _)) => {
+                        return Err(TemplateError::Syntax(format!("{{{{/{}}}}} closes {{{{#{}}}}}.", name, section)));
+                    },
+                    None => return Err(TemplateError::Syntax(format!("{{{{/{}}}}} closes no section.", name))),
+                }
+            },
+            Some(sigil) => {
+                let outer = mem::replace(&mut nodes, Vec::new());
+                open.push((name.to_owned(), sigil == '^', outer));
+            },
+        }
+    }
+    if !rest.is_empty() {
+        nodes.push(Node::Text(rest.to_owned()));
+    }
+
+    match open.pop() {
+        Some((section, _, _)) => Err(TemplateError::Syntax(format!("Unclosed section {:?}.", section))),
+        None => Ok(nodes),
+    }
+}
+
+fn unclosed(rest: &str) -> TemplateError {
+    let tag: String = rest.chars().take(20).collect();
+    TemplateError::Syntax(format!("Unclosed tag {:?}.", tag))
+}
+
+fn split_name(name: &str) -> Vec<String> {
+    if name == "." {
+        Vec::new()
+    } else {
+        name.split('.').map(|part| part.to_owned()).collect()
+    }
+}
+
+/// Looks up the first part of `name` from the innermost value that has it, then the rest of
+/// it below that.
+fn lookup<'v>(stack: &[&'v Value], name: &[String]) -> Option<&'v Value> {
+    let (first, rest) = match name.split_first() {
+        Some(split) => split,
+        None => return stack.last().cloned(),
+    };
+    let mut value = match stack.iter().rev().filter_map(|value| value.get(first)).next() {
+        Some(value) => value,
+        None => return None,
+    };
+    for part in rest {
+        value = match value.get(part) {
+            Some(value) => value,
+            None => return None,
+        };
+    }
+    Some(value)
+}
+
+fn is_truthy(value: &Value) -> bool {
+    match *value {
+        Value::Null | Value::Bool(false) => false,
+        Value::Array(ref items) => !items.is_empty(),
+        _ => true,
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match *value {
+        Value::Null => String::new(),
+        Value::String(ref text) => text.clone(),
+        ref value => value.to_string(),
+    }
+}
+
+fn escape_html(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+#[test]
+fn values_and_sections() {
+    let engine = Mustache::new("/nonexistent");
+    let render = |source: &str, context: &Value| {
+        let mut out = String::new();
+        engine.render_nodes(&parse(source).unwrap(), &mut vec![context], 0, &mut out).map(|_| out)
+    };
+    let context: Value = ::serde_json::from_str(r#"{
+        "title": "Tom & Jerry",
+        "user": {"name": "<bob>", "admin": false},
+        "items": [{"name": "lamp"}, {"name": "desk"}],
+        "tags": []
+    }"#).unwrap();
+
+    assert_eq!(render("{{title}} {{{title}}} {{& title}}", &context).unwrap(), "Tom &amp; Jerry Tom & Jerry Tom & Jerry");
+    assert_eq!(render("{{user.name}}{{missing}}{{! note }}", &context).unwrap(), "&lt;bob&gt;");
+    assert_eq!(render("{{#items}}{{name}} of {{title}}, {{/items}}", &context).unwrap(), "lamp of Tom &amp; Jerry, desk of Tom &amp; Jerry, ");
+    assert_eq!(render("{{#user}}{{name}}{{/user}}{{#user.admin}}admin{{/user.admin}}", &context).unwrap(), "&lt;bob&gt;");
+    assert_eq!(render("{{^tags}}no tags{{/tags}}{{^items}}no items{{/items}}", &context).unwrap(), "no tags");
+    assert!(match render("{{> header.html}}", &context) {
+        Err(TemplateError::NotFound(ref name)) => name == "header.html",
+        _ => false,
+    });
+}
+
+#[test]
+fn invalid_templates() {
+    assert!(parse("{{#items}}{{name}}").is_err());
+    assert!(parse("{{#items}}{{/tags}}").is_err());
+    assert!(parse("{{/items}}").is_err());
+    assert!(parse("{{name").is_err());
+    assert_eq!(parse("a {{b}}").unwrap(), vec![Node::Text("a ".to_owned()), Node::Value(vec!["b".to_owned()], true)]);
+}